@@ -1,5 +1,10 @@
 use crate::bindings::asterai::host::api;
+use crate::bindings::asterbot::types::types::{
+    AgentError, ConverseRequest, ConverseResponse, ErrorKind, InputPart, Location, Media,
+    MediaData, StopReason, ToolInvocation, Usage,
+};
 use crate::bindings::exports::asterbot::types::agent::Guest;
+use serde_json::Value;
 
 #[allow(warnings)]
 mod bindings {
@@ -13,18 +18,193 @@ mod bindings {
 struct Component;
 
 impl Guest for Component {
-    fn converse(input: String) -> String {
-        let core = std::env::var("ASTERBOT_CORE_COMPONENT").unwrap_or_default();
-        let core = match core.is_empty() {
-            true => "asterbot:core",
-            false => &core,
-        };
-        let input_json = serde_json::to_string(&input).unwrap_or_default();
-        let args = format!("[{input_json}]");
-        match api::call_component_function(core, "core/converse", &args) {
-            Ok(output) => serde_json::from_str::<String>(&output).unwrap_or_else(|_| output),
-            Err(e) => format!("error: core component '{}' failed: {}", core, e.message,),
+    fn converse(request: ConverseRequest) -> Result<ConverseResponse, AgentError> {
+        let output = call_core("core/converse", vec![encode_request(&request)])?;
+        decode_result(&output).map(|ok| decode_converse_response(&ok))
+    }
+}
+
+fn call_core(function: &str, args: Vec<Value>) -> Result<String, AgentError> {
+    let core = std::env::var("ASTERBOT_CORE_COMPONENT").unwrap_or_default();
+    let core = match core.is_empty() {
+        true => "asterbot:core",
+        false => &core,
+    };
+    let args = Value::Array(args).to_string();
+    api::call_component_function(core, function, &args).map_err(|e| AgentError {
+        kind: ErrorKind::Config,
+        message: format!("core component '{}' failed: {}", core, e.message),
+    })
+}
+
+/// Encodes a `converse-request` as WIT JSON. Options are encoded
+/// as the value or null.
+fn encode_request(request: &ConverseRequest) -> Value {
+    let progress = request.progress.as_ref().map_or(Value::Null, |target| {
+        serde_json::json!({
+            "component": target.component,
+            "context": target.context,
+        })
+    });
+    serde_json::json!({
+        "session": request.session,
+        "parts": request.parts.iter().map(encode_input_part).collect::<Vec<_>>(),
+        "progress": progress,
+    })
+}
+
+/// Encodes an `input-part` as WIT JSON: variants as
+/// `{"case": payload}` and records with kebab-case fields.
+fn encode_input_part(part: &InputPart) -> Value {
+    let (case, media) = match part {
+        InputPart::Text(text) => return serde_json::json!({ "text": text }),
+        InputPart::Image(media) => ("image", media),
+        InputPart::Audio(media) => ("audio", media),
+        InputPart::File(media) => ("file", media),
+    };
+    let data = match &media.data {
+        MediaData::Bytes(bytes) => serde_json::json!({ "bytes": bytes }),
+        MediaData::Url(url) => serde_json::json!({ "url": url }),
+    };
+    serde_json::json!({
+        case: {
+            "mime-type": media.mime_type,
+            "name": media.name,
+            "data": data,
         }
+    })
+}
+
+/// Decodes the JSON encoding of `result<T, agent-error>`,
+/// i.e. `{"ok": ...}` or `{"err": {"kind": "...", "message": "..."}}`.
+/// Anything else is treated as a bare success value.
+fn decode_result(output: &str) -> Result<Value, AgentError> {
+    let value: Value = match serde_json::from_str(output) {
+        Ok(v) => v,
+        Err(_) => return Ok(Value::String(output.to_string())),
+    };
+    if let Some(ok) = value.get("ok") {
+        return Ok(ok.clone());
+    }
+    if let Some(err) = value.get("err") {
+        let kind = err.get("kind").and_then(Value::as_str).unwrap_or_default();
+        let message = err
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        return Err(AgentError {
+            kind: parse_error_kind(kind),
+            message: message.to_string(),
+        });
+    }
+    Ok(value)
+}
+
+/// Decodes the kebab-case JSON encoding of `converse-response`.
+fn decode_converse_response(value: &Value) -> ConverseResponse {
+    let str_field = |v: &Value, key: &str| {
+        v.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let u64_field = |v: &Value, key: &str| v.get(key).and_then(Value::as_u64).unwrap_or(0);
+    let tool_invocations = value
+        .get("tool-invocations")
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .map(|t| ToolInvocation {
+                    name: str_field(t, "name"),
+                    args_json: str_field(t, "args-json"),
+                    result: str_field(t, "result"),
+                    duration_ms: u64_field(t, "duration-ms"),
+                    is_error: t.get("is-error").and_then(Value::as_bool).unwrap_or(false),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let attachments = value
+        .get("attachments")
+        .and_then(Value::as_array)
+        .map(|items| items.iter().filter_map(decode_media).collect())
+        .unwrap_or_default();
+    let buttons = value
+        .get("buttons")
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let location = value
+        .get("location")
+        .filter(|l| !l.is_null())
+        .map(|l| Location {
+            latitude: l.get("latitude").and_then(Value::as_f64).unwrap_or(0.0),
+            longitude: l.get("longitude").and_then(Value::as_f64).unwrap_or(0.0),
+            label: str_field(l, "label"),
+        });
+    let usage = value.get("usage").cloned().unwrap_or(Value::Null);
+    let stop_reason = match value.get("stop-reason").and_then(Value::as_str) {
+        Some("max-tool-rounds") => StopReason::MaxToolRounds,
+        _ => StopReason::Completed,
+    };
+    ConverseResponse {
+        reply: str_field(value, "reply"),
+        attachments,
+        buttons,
+        location,
+        tool_invocations,
+        usage: Usage {
+            rounds: u64_field(&usage, "rounds") as u32,
+            llm_calls: u64_field(&usage, "llm-calls") as u32,
+            input_tokens: u64_field(&usage, "input-tokens"),
+            output_tokens: u64_field(&usage, "output-tokens"),
+            cost_usd: usage.get("cost-usd").and_then(Value::as_f64).unwrap_or(0.0),
+        },
+        model: str_field(value, "model"),
+        stop_reason,
+        turn_id: str_field(value, "turn-id"),
+    }
+}
+
+/// Decodes the WIT JSON encoding of `media`, the inverse of the
+/// encoding in `encode_input_part`.
+fn decode_media(value: &Value) -> Option<Media> {
+    let data = value.get("data")?;
+    let data = match (data.get("bytes"), data.get("url")) {
+        (Some(bytes), _) => MediaData::Bytes(serde_json::from_value(bytes.clone()).ok()?),
+        (None, Some(url)) => MediaData::Url(url.as_str()?.to_string()),
+        (None, None) => return None,
+    };
+    Some(Media {
+        mime_type: value
+            .get("mime-type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        name: value
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        data,
+    })
+}
+
+fn parse_error_kind(kind: &str) -> ErrorKind {
+    match kind {
+        "llm" => ErrorKind::Llm,
+        "tool" => ErrorKind::Tool,
+        "storage" => ErrorKind::Storage,
+        "permission" => ErrorKind::Permission,
+        "limit" => ErrorKind::Limit,
+        _ => ErrorKind::Config,
     }
 }
 
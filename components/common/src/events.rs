@@ -0,0 +1,231 @@
+//! Structured event log.
+//!
+//! When `ASTERBOT_EVENT_LOG` names a file, events are appended to
+//! it (relative to the host dir) as JSON lines, e.g.
+//!
+//! ```json
+//! {"ts":1773446400000,"component":"asterbot:core","turnId":"…","event":"tool.finished","durationMs":12,…}
+//! ```
+//!
+//! Prompts, replies and tool arguments/results are logged
+//! according to `ASTERBOT_EVENT_LOG_CONTENT`: `omit` (the default)
+//! logs only their length, `truncate` the first 200 characters and
+//! `full` everything.
+//!
+//! Core allocates an id per turn and passes it to the components
+//! it calls for the turn (e.g. toolkit's `try-call-tool-in-turn`), and
+//! returns it to the caller in the `converse-response`, so their
+//! events can be correlated with the turn that caused them. Events
+//! outside a turn have a null `turnId`.
+
+use serde_json::{Map, Value};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const TRUNCATE_CHARS: usize = 200;
+
+static TURN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// How much message content to include in events.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ContentMode {
+    #[default]
+    Omit,
+    Truncate,
+    Full,
+}
+
+impl ContentMode {
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "full" => ContentMode::Full,
+            "truncate" => ContentMode::Truncate,
+            _ => ContentMode::Omit,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EventLog {
+    /// The emitting component, e.g. "asterbot:telegram-gateway".
+    component: &'static str,
+    /// Log file path. Events are dropped if `None`.
+    path: Option<String>,
+    content: ContentMode,
+}
+
+impl EventLog {
+    pub fn new(component: &'static str, path: Option<String>, content: ContentMode) -> Self {
+        EventLog {
+            component,
+            path,
+            content,
+        }
+    }
+
+    /// The log named by `ASTERBOT_EVENT_LOG` in the host dir,
+    /// which drops events if either is unset.
+    pub fn from_env(component: &'static str) -> Self {
+        let dir = crate::host_dir();
+        let filename = std::env::var("ASTERBOT_EVENT_LOG").unwrap_or_default();
+        let content =
+            ContentMode::parse(&std::env::var("ASTERBOT_EVENT_LOG_CONTENT").unwrap_or_default());
+        let path = (!dir.is_empty() && !filename.is_empty()).then(|| format!("{dir}/{filename}"));
+        EventLog::new(component, path, content)
+    }
+
+    /// Allocates an id for a new turn.
+    pub fn start_turn(&self) -> Turn<'_> {
+        self.turn(&new_turn_id())
+    }
+
+    /// A turn started elsewhere, e.g. by core, whose id was
+    /// passed in.
+    pub fn turn(&self, id: &str) -> Turn<'_> {
+        Turn {
+            log: self,
+            id: id.to_string(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Appends an event outside any turn. `fields` must be a
+    /// JSON object and may override the common fields, e.g. with
+    /// the `turnId` of a turn that has finished.
+    pub fn emit(&self, event: &str, fields: Value) {
+        self.append(Value::Null, event, fields);
+    }
+
+    /// Message content, redacted according to the content mode.
+    pub fn content(&self, text: &str) -> Value {
+        let chars = text.chars().count();
+        match self.content {
+            ContentMode::Omit => serde_json::json!({ "chars": chars }),
+            ContentMode::Truncate if chars > TRUNCATE_CHARS => {
+                let text: String = text.chars().take(TRUNCATE_CHARS).collect();
+                serde_json::json!({ "chars": chars, "text": format!("{text}...") })
+            }
+            _ => serde_json::json!({ "chars": chars, "text": text }),
+        }
+    }
+
+    fn append(&self, turn_id: Value, event: &str, fields: Value) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut line = Map::new();
+        line.insert("ts".into(), now_ms().into());
+        line.insert("component".into(), self.component.into());
+        line.insert("turnId".into(), turn_id);
+        line.insert("event".into(), event.into());
+        if let Value::Object(fields) = fields {
+            line.extend(fields);
+        }
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| writeln!(f, "{}", Value::Object(line)));
+        if let Err(e) = result {
+            eprintln!("warning: failed to write event to {path}: {e}");
+        }
+    }
+}
+
+/// A turn in progress, stamping its id on every event.
+pub struct Turn<'a> {
+    log: &'a EventLog,
+    pub id: String,
+    started: Instant,
+}
+
+impl Turn<'_> {
+    /// Appends an event. `fields` must be a JSON object.
+    pub fn emit(&self, event: &str, fields: Value) {
+        self.log.append(self.id.clone().into(), event, fields);
+    }
+
+    /// Message content, redacted according to the content mode.
+    pub fn content(&self, text: &str) -> Value {
+        self.log.content(text)
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Unique enough to tell turns apart within a log.
+fn new_turn_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    let count = TURN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}", nanos ^ count.rotate_right(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_mode_parses_with_omit_default() {
+        assert_eq!(ContentMode::parse("full"), ContentMode::Full);
+        assert_eq!(ContentMode::parse(" Truncate "), ContentMode::Truncate);
+        assert_eq!(ContentMode::parse(""), ContentMode::Omit);
+        assert_eq!(ContentMode::parse("bogus"), ContentMode::Omit);
+    }
+
+    #[test]
+    fn content_is_redacted_by_mode() {
+        let text = "é".repeat(250);
+        let content = |mode| EventLog::new("asterbot:test", None, mode).content(&text);
+        assert_eq!(
+            content(ContentMode::Omit),
+            serde_json::json!({ "chars": 250 })
+        );
+        let truncated = content(ContentMode::Truncate);
+        assert_eq!(truncated["text"].as_str().unwrap().chars().count(), 203);
+        assert_eq!(content(ContentMode::Full)["text"], text.as_str());
+    }
+
+    #[test]
+    fn events_carry_the_turn_id_they_are_given() {
+        let dir = std::env::temp_dir().join(format!("common-events-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl").to_string_lossy().to_string();
+        let log = EventLog::new("asterbot:test", Some(path.clone()), ContentMode::Omit);
+        log.turn("abc")
+            .emit("tool.dispatched", serde_json::json!({ "n": 1 }));
+        log.emit("access.denied", serde_json::json!({ "sender": "1" }));
+        log.emit("message.handled", serde_json::json!({ "turnId": "def" }));
+        let lines: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["component"], "asterbot:test");
+        assert_eq!(lines[0]["turnId"], "abc");
+        assert_eq!(lines[0]["n"], 1);
+        assert_eq!(lines[1]["turnId"], Value::Null);
+        assert_eq!(lines[2]["turnId"], "def");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn turn_ids_are_distinct() {
+        assert_ne!(new_turn_id(), new_turn_id());
+        let log = EventLog::default();
+        assert_ne!(log.start_turn().id, log.start_turn().id);
+    }
+}
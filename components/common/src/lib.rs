@@ -0,0 +1,24 @@
+//! Code shared by asterbot:core, asterbot:history, asterbot:toolkit,
+//! asterbot:scheduler and the gateways, which each keep the same
+//! files in the host dir.
+//!
+//! This is a plain Rust library linked into each component, with
+//! no WIT bindings of its own.
+
+pub mod events;
+pub mod origin;
+pub mod usage;
+
+/// The host dir: `ASTERBOT_HOST_DIR`, else the first of
+/// `ASTERAI_ALLOWED_DIRS`. Empty if neither is set.
+pub fn host_dir() -> String {
+    std::env::var("ASTERBOT_HOST_DIR")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .or_else(|| {
+            std::env::var("ASTERAI_ALLOWED_DIRS")
+                .ok()
+                .and_then(|dirs| dirs.split(':').next().map(str::to_string))
+        })
+        .unwrap_or_default()
+}
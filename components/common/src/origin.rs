@@ -0,0 +1,105 @@
+//! Where a session's turns come from.
+//!
+//! Before each agent turn a gateway records itself and the chat it
+//! is answering as the origin of the turn's session, in
+//! `origins/` in the host dir. Tools reach the user later through
+//! it: asterbot:scheduler looks up the origin of the session that
+//! schedules a task (core passes the session to tools, see
+//! asterbot:toolkit) and, when the task is due, delivers the reply
+//! through the gateway's `delivery` export.
+//!
+//! A session's chat never changes, so each session keeps its own
+//! file and turns in other sessions can't overwrite it.
+
+use serde::{Deserialize, Serialize};
+
+pub const ORIGINS_DIR: &str = "origins";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The gateway, e.g. "asterbot:telegram-gateway".
+    pub component: String,
+    /// What the gateway's `deliver` takes to reach the chat: a
+    /// chat id, channel id or phone number.
+    pub address: String,
+}
+
+impl Origin {
+    pub fn new(component: &str, address: &str) -> Self {
+        Origin {
+            component: component.to_string(),
+            address: address.to_string(),
+        }
+    }
+
+    /// Records this as the origin of `session`. Nothing is
+    /// recorded without a host dir or for the default session,
+    /// which has no chat of its own.
+    pub fn record(&self, host_dir: &str, session: &str) {
+        if host_dir.is_empty() || session.is_empty() {
+            return;
+        }
+        let dir = format!("{host_dir}/{ORIGINS_DIR}");
+        let _ = std::fs::create_dir_all(&dir);
+        let path = format!("{dir}/{}", file_name(session));
+        let json = serde_json::to_string(self).unwrap_or_default();
+        if let Err(e) = std::fs::write(&path, json) {
+            eprintln!("failed to write {path}: {e}");
+        }
+    }
+
+    /// The origin recorded for `session`, if any.
+    pub fn load(host_dir: &str, session: &str) -> Option<Self> {
+        if host_dir.is_empty() || session.is_empty() {
+            return None;
+        }
+        let path = format!("{host_dir}/{ORIGINS_DIR}/{}", file_name(session));
+        let json = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+}
+
+/// The session with ':' as '_' and characters other than letters,
+/// digits, '-' and '.' as %XX, so no two sessions share a file.
+fn file_name(session: &str) -> String {
+    let mut name = String::new();
+    for b in session.bytes() {
+        match b {
+            b':' => name.push('_'),
+            b'-' | b'.' => name.push(b as char),
+            b if b.is_ascii_alphanumeric() => name.push(b as char),
+            b => name.push_str(&format!("%{b:02X}")),
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_session_keeps_its_own_origin() {
+        let dir = std::env::temp_dir().join(format!("asterbot-origin-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let dir = dir.to_string_lossy().to_string();
+        let group = Origin::new("asterbot:telegram-gateway", "-100123");
+        let direct = Origin::new("asterbot:whatsapp-gateway", "+447700900123");
+        group.record(&dir, "telegram:group:-100123");
+        direct.record(&dir, "whatsapp:dm:+447700900123");
+        direct.record(&dir, "");
+        assert_eq!(Origin::load(&dir, "telegram:group:-100123"), Some(group));
+        assert_eq!(
+            Origin::load(&dir, "whatsapp:dm:+447700900123"),
+            Some(direct)
+        );
+        assert_eq!(Origin::load(&dir, "telegram:dm:42"), None);
+        assert_eq!(Origin::load(&dir, ""), None);
+        assert_eq!(
+            file_name("whatsapp:dm:+447700900123"),
+            "whatsapp_dm_%2B447700900123.json"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
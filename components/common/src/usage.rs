@@ -0,0 +1,183 @@
+//! The usage ledger.
+//!
+//! LLM token totals are persisted to `usage.json` in the host dir,
+//! keyed by UTC day and model, and by session and model. Core
+//! records its turns' calls, `asterbot:history` its compaction
+//! calls, and the gateways' `/status` reads it.
+//!
+//! Writers read the file afresh just before recording, so totals
+//! another component wrote meanwhile (e.g. history compacting
+//! during one of core's turns) are kept rather than overwritten.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const USAGE_FILENAME: &str = "usage.json";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelUsage {
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl ModelUsage {
+    fn add(&mut self, usage: ModelUsage) {
+        self.calls += usage.calls;
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+    }
+}
+
+/// Persisted usage totals.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct Ledger {
+    /// Totals keyed by UTC day ("YYYY-MM-DD"), then model.
+    #[serde(default)]
+    pub days: BTreeMap<String, BTreeMap<String, ModelUsage>>,
+    /// Totals keyed by session, then model. The default
+    /// session's key is empty.
+    #[serde(default)]
+    pub sessions: BTreeMap<String, BTreeMap<String, ModelUsage>>,
+}
+
+impl Ledger {
+    /// Loads the ledger, starting fresh if missing or malformed.
+    pub fn load(path: &str) -> Self {
+        std::fs::read(path)
+            .map(|bytes| Ledger::parse(&bytes))
+            .unwrap_or_default()
+    }
+
+    /// Parses a ledger, starting fresh if malformed.
+    pub fn parse(bytes: &[u8]) -> Self {
+        serde_json::from_slice(bytes).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    eprintln!("error: failed to write {path}: {e}");
+                }
+            }
+            Err(e) => eprintln!("error: failed to serialise usage: {e}"),
+        }
+    }
+
+    pub fn record(&mut self, day: &str, session: &str, model: &str, usage: ModelUsage) {
+        for totals in [
+            self.days.entry(day.to_string()).or_default(),
+            self.sessions.entry(session.to_string()).or_default(),
+        ] {
+            totals.entry(model.to_string()).or_default().add(usage);
+        }
+    }
+
+    /// Records `usage` in the ledger at `path`, reading it first
+    /// so totals written since anyone last loaded it are kept.
+    pub fn record_at(path: &str, day: &str, session: &str, model: &str, usage: ModelUsage) {
+        let mut ledger = Ledger::load(path);
+        ledger.record(day, session, model, usage);
+        ledger.save(path);
+    }
+}
+
+/// Formats a unix timestamp as a UTC "YYYY-MM-DD" day.
+pub fn utc_day(unix_secs: u64) -> String {
+    // Civil-from-days, after Howard Hinnant's date algorithms.
+    let z = (unix_secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// The current UTC day.
+pub fn today() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    utc_day(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> ModelUsage {
+        ModelUsage {
+            calls: 1,
+            input_tokens,
+            output_tokens,
+        }
+    }
+
+    #[test]
+    fn utc_day_formats_dates() {
+        assert_eq!(utc_day(0), "1970-01-01");
+        assert_eq!(utc_day(951_782_400), "2000-02-29");
+        assert_eq!(utc_day(1_773_446_400), "2026-03-14");
+        assert_eq!(utc_day(1_773_532_799), "2026-03-14");
+    }
+
+    #[test]
+    fn record_accumulates_per_day_session_and_model() {
+        let mut ledger = Ledger::default();
+        ledger.record("2026-03-14", "telegram:dm:1", "a/model", usage(100, 10));
+        ledger.record("2026-03-14", "telegram:dm:2", "a/model", usage(50, 5));
+        ledger.record("2026-03-15", "telegram:dm:1", "a/model", usage(1, 1));
+        let day = &ledger.days["2026-03-14"]["a/model"];
+        assert_eq!(day.calls, 2);
+        assert_eq!(day.input_tokens, 150);
+        assert_eq!(day.output_tokens, 15);
+        assert_eq!(ledger.days.len(), 2);
+        let session = &ledger.sessions["telegram:dm:1"]["a/model"];
+        assert_eq!(session.calls, 2);
+        assert_eq!(session.input_tokens, 101);
+        assert_eq!(ledger.sessions["telegram:dm:2"]["a/model"].calls, 1);
+    }
+
+    #[test]
+    fn ledger_round_trips_camel_case() {
+        let mut ledger = Ledger::default();
+        ledger.record("2026-03-14", "", "a/model", usage(1, 2));
+        let json = serde_json::to_string(&ledger).unwrap();
+        assert!(json.contains("\"inputTokens\":1"));
+        assert_eq!(Ledger::parse(json.as_bytes()), ledger);
+        // Ledgers from before per-session totals still load.
+        let old = r#"{"days":{"2026-03-14":{"a/model":
+            {"calls":3,"inputTokens":10,"outputTokens":5}}}}"#;
+        assert_eq!(
+            Ledger::parse(old.as_bytes()).days["2026-03-14"]["a/model"].calls,
+            3
+        );
+        assert_eq!(Ledger::parse(b"{"), Ledger::default());
+    }
+
+    #[test]
+    fn recording_keeps_totals_written_meanwhile() {
+        let dir = std::env::temp_dir().join(format!("asterbot-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(USAGE_FILENAME).to_string_lossy().to_string();
+        let _ = std::fs::remove_file(&path);
+        // Core loads the ledger for its budget check...
+        let stale = Ledger::load(&path);
+        // ...history records a compaction during the turn...
+        Ledger::record_at(&path, "2026-03-14", "s", "a/model", usage(100, 10));
+        // ...and core records the turn afterwards.
+        Ledger::record_at(&path, "2026-03-14", "s", "a/model", usage(50, 5));
+        assert!(stale.days.is_empty());
+        let ledger = Ledger::load(&path);
+        assert_eq!(ledger.days["2026-03-14"]["a/model"].calls, 2);
+        assert_eq!(ledger.sessions["s"]["a/model"].input_tokens, 150);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
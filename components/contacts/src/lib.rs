@@ -0,0 +1,127 @@
+#[cfg(not(test))]
+use crate::bindings::asterai::host::api;
+#[cfg(not(test))]
+use crate::bindings::asterbot::types::types::{AgentError, ErrorKind};
+#[cfg(not(test))]
+use crate::bindings::exports::asterbot::types::contacts::Guest;
+#[cfg(not(test))]
+use crate::registry::Policy;
+#[cfg(not(test))]
+use serde_json::Value;
+
+mod registry;
+
+#[cfg(not(test))]
+#[allow(warnings)]
+mod bindings {
+    wit_bindgen::generate!({
+        path: "wit/package.wasm",
+        world: "component",
+        generate_all,
+    });
+}
+
+#[cfg(not(test))]
+struct Component;
+
+#[cfg(not(test))]
+impl Guest for Component {
+    fn list() -> Result<String, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let contacts = registry::load(&host_dir).map_err(storage_error)?;
+        Ok(registry::list_text(&contacts, &resolve_policy()))
+    }
+
+    fn notify(contact: String, text: String) -> Result<String, AgentError> {
+        if text.trim().is_empty() {
+            return Err(tool_error("text is empty".to_string()));
+        }
+        let policy = resolve_policy();
+        if policy == Policy::Off {
+            return Err(permission_error(
+                "messaging contacts is turned off (CONTACTS_NOTIFY)".to_string(),
+            ));
+        }
+        let host_dir = resolve_host_dir()?;
+        let contacts = registry::load(&host_dir).map_err(storage_error)?;
+        let contact = registry::find(&contacts, &contact).map_err(tool_error)?;
+        if !policy.allows(contact) {
+            return Err(permission_error(format!(
+                "{} is not in CONTACTS_NOTIFY",
+                contact.label()
+            )));
+        }
+        let args = serde_json::to_string(&[&contact.address, &text]).unwrap_or_default();
+        let output = api::call_component_function(&contact.component, "delivery/deliver", &args)
+            .map_err(|e| {
+                tool_error(format!(
+                    "{}/delivery/deliver failed: {}",
+                    contact.component, e.message
+                ))
+            })?;
+        let value: Value = serde_json::from_str(&output).unwrap_or(Value::Null);
+        if let Some(err) = value.get("err") {
+            return Err(tool_error(format!(
+                "failed to message {}: {}",
+                contact.label(),
+                err.get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+            )));
+        }
+        Ok(format!("Sent to {} on {}.", contact.label(), contact.app()))
+    }
+}
+
+#[cfg(not(test))]
+fn resolve_policy() -> Policy {
+    Policy::parse(&std::env::var("CONTACTS_NOTIFY").unwrap_or_default())
+}
+
+#[cfg(not(test))]
+fn resolve_host_dir() -> Result<String, AgentError> {
+    if let Ok(v) = std::env::var("ASTERBOT_HOST_DIR") {
+        if !v.is_empty() {
+            return Ok(v);
+        }
+    }
+    if let Ok(dirs) = std::env::var("ASTERAI_ALLOWED_DIRS") {
+        if let Some(first) = dirs.split(':').next() {
+            if !first.is_empty() {
+                return Ok(first.to_string());
+            }
+        }
+    }
+    Err(AgentError {
+        kind: ErrorKind::Config,
+        message: "no state directory available — pass --allow-dir to grant filesystem access"
+            .to_string(),
+    })
+}
+
+#[cfg(not(test))]
+fn tool_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Tool,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+fn permission_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Permission,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+fn storage_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Storage,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+bindings::export!(Component with_types_in bindings);
@@ -0,0 +1,213 @@
+//! The contacts the gateways learned, in `contacts.json` in the
+//! host dir, and who `notify` may message.
+
+use serde::Deserialize;
+
+pub const CONTACTS_FILENAME: &str = "contacts.json";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+    /// The gateway and the sender's id on it, e.g. "telegram:42".
+    pub id: String,
+    pub name: String,
+    /// The gateway whose `delivery` export reaches the contact.
+    pub component: String,
+    pub address: String,
+    #[serde(default)]
+    pub last_seen: u64,
+}
+
+impl Contact {
+    /// "telegram" for "telegram:42".
+    pub fn app(&self) -> &str {
+        self.id.split(':').next().unwrap_or_default()
+    }
+
+    /// "Alice (telegram:42)", or the id alone when the name is it.
+    pub fn label(&self) -> String {
+        match self.name == self.id.split_once(':').map_or("", |(_, sender)| sender) {
+            true => self.id.clone(),
+            false => format!("{} ({})", self.name, self.id),
+        }
+    }
+}
+
+/// Who `notify` may message, from `CONTACTS_NOTIFY`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy {
+    Off,
+    All,
+    /// Contact ids, lowercased.
+    Only(Vec<String>),
+}
+
+impl Policy {
+    /// "off" (or empty), "all", or comma-separated contact ids.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "" | "off" | "none" | "false" => Policy::Off,
+            "all" | "*" => Policy::All,
+            _ => Policy::Only(
+                value
+                    .split(',')
+                    .map(|id| id.trim().to_lowercase())
+                    .filter(|id| !id.is_empty())
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn allows(&self, contact: &Contact) -> bool {
+        match self {
+            Policy::Off => false,
+            Policy::All => true,
+            Policy::Only(ids) => ids.contains(&contact.id.to_lowercase()),
+        }
+    }
+}
+
+/// The contacts in the host dir, none if there is no file yet.
+pub fn load(host_dir: &str) -> Result<Vec<Contact>, String> {
+    let path = format!("{host_dir}/{CONTACTS_FILENAME}");
+    match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("invalid {path}: {e}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("failed to read {path}: {e}")),
+    }
+}
+
+/// The contact `query` names: its id, or a name only one contact
+/// has, ignoring case. Names are what users call themselves, so
+/// the policy is checked against the id found.
+pub fn find<'a>(contacts: &'a [Contact], query: &str) -> Result<&'a Contact, String> {
+    let query = query.trim().trim_start_matches('@');
+    if let Some(contact) = contacts.iter().find(|c| c.id.eq_ignore_ascii_case(query)) {
+        return Ok(contact);
+    }
+    let named: Vec<&Contact> = contacts
+        .iter()
+        .filter(|c| c.name.to_lowercase() == query.to_lowercase())
+        .collect();
+    match named[..] {
+        [contact] => Ok(contact),
+        [] => Err(format!(
+            "no contact {query:?}; list the contacts to see who can be messaged"
+        )),
+        _ => Err(format!(
+            "{} contacts are named {query:?}, give one of their ids: {}",
+            named.len(),
+            named
+                .iter()
+                .map(|c| c.id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// The contacts the policy lets `notify` message, one per line.
+pub fn list_text(contacts: &[Contact], policy: &Policy) -> String {
+    if *policy == Policy::Off {
+        return "Messaging contacts is turned off (CONTACTS_NOTIFY).".to_string();
+    }
+    let allowed: Vec<String> = contacts
+        .iter()
+        .filter(|c| policy.allows(c))
+        .map(|c| format!("- {} on {}", c.label(), c.app()))
+        .collect();
+    match allowed.is_empty() {
+        true => "No contacts can be messaged yet.".to_string(),
+        false => format!("{} contacts:\n{}", allowed.len(), allowed.join("\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contacts() -> Vec<Contact> {
+        let contact = |id: &str, name: &str, component: &str, address: &str| Contact {
+            id: id.to_string(),
+            name: name.to_string(),
+            component: component.to_string(),
+            address: address.to_string(),
+            last_seen: 0,
+        };
+        vec![
+            contact("discord:555", "alicew", "asterbot:discord-gateway", "D555"),
+            contact("telegram:42", "Alice", "asterbot:telegram-gateway", "42"),
+            contact(
+                "twilio:+14155550100",
+                "Bob",
+                "asterbot:twilio-gateway",
+                "+14155550100",
+            ),
+            contact(
+                "whatsapp:+351912345678",
+                "bob",
+                "asterbot:whatsapp-gateway",
+                "+351912345678",
+            ),
+        ]
+    }
+
+    #[test]
+    fn contacts_are_found_by_id_or_unique_name() {
+        let contacts = contacts();
+        assert_eq!(find(&contacts, "telegram:42").unwrap().address, "42");
+        assert_eq!(find(&contacts, "alice").unwrap().id, "telegram:42");
+        assert_eq!(find(&contacts, "alicew").unwrap().id, "discord:555");
+        assert_eq!(
+            find(&contacts, "Bob").unwrap_err(),
+            "2 contacts are named \"Bob\", give one of their ids: \
+             twilio:+14155550100, whatsapp:+351912345678"
+        );
+        assert!(find(&contacts, "carol")
+            .unwrap_err()
+            .starts_with("no contact \"carol\""));
+    }
+
+    #[test]
+    fn the_policy_limits_who_is_listed() {
+        let contacts = contacts();
+        assert_eq!(Policy::parse(""), Policy::Off);
+        assert_eq!(
+            list_text(&contacts, &Policy::Off),
+            "Messaging contacts is turned off (CONTACTS_NOTIFY)."
+        );
+        let only = Policy::parse("Telegram:42, discord:555");
+        assert!(only.allows(&contacts[1]));
+        assert!(!only.allows(&contacts[2]));
+        assert_eq!(
+            list_text(&contacts, &only),
+            "2 contacts:\n- alicew (discord:555) on discord\n- Alice (telegram:42) on telegram"
+        );
+        assert_eq!(
+            list_text(&contacts, &Policy::parse("all")).lines().count(),
+            5
+        );
+        assert_eq!(
+            list_text(&[], &Policy::All),
+            "No contacts can be messaged yet."
+        );
+    }
+
+    #[test]
+    fn contacts_load_from_the_gateways_file() {
+        let dir = std::env::temp_dir().join(format!("contacts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+        assert_eq!(load(&dir).unwrap(), []);
+        let json = r#"[{"id":"telegram:42","name":"Alice","component":"asterbot:telegram-gateway",
+            "address":"42","lastSeen":1792400100}]"#;
+        std::fs::write(format!("{dir}/{CONTACTS_FILENAME}"), json).unwrap();
+        let contacts = load(&dir).unwrap();
+        assert_eq!(contacts[0].label(), "Alice (telegram:42)");
+        assert_eq!(contacts[0].last_seen, 1_792_400_100);
+        std::fs::write(format!("{dir}/{CONTACTS_FILENAME}"), "{").unwrap();
+        assert!(load(&dir).unwrap_err().starts_with("invalid"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Spend budgets.
+//!
+//! Token totals are kept in the usage ledger (see
+//! `asterbot_common::usage`). Costs are derived from them at read
+//! time using the price table, so changing a price re-prices past
+//! usage.
+
+use crate::error::{AgentError, ErrorKind};
+use asterbot_common::usage::Ledger;
+use std::collections::BTreeMap;
+
+/// USD per million input/output tokens.
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("anthropic/claude-opus-4-1", 15.0, 75.0),
+    ("anthropic/claude-sonnet-4-5", 3.0, 15.0),
+    ("anthropic/claude-haiku-4-5", 1.0, 5.0),
+    ("openai/gpt-4o", 2.5, 10.0),
+    ("openai/gpt-4o-mini", 0.15, 0.6),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prices(BTreeMap<String, (f64, f64)>);
+
+impl Prices {
+    /// The built-in table, extended or overridden by a
+    /// `model=input:output,...` spec (USD per million tokens).
+    pub fn parse(spec: &str) -> Self {
+        let mut table: BTreeMap<String, (f64, f64)> = DEFAULT_PRICES
+            .iter()
+            .map(|(m, i, o)| (m.to_string(), (*i, *o)))
+            .collect();
+        for entry in spec.split(',') {
+            let Some((model, price)) = entry.trim().split_once('=') else {
+                continue;
+            };
+            let Some((input, output)) = price.split_once(':') else {
+                continue;
+            };
+            match (input.trim().parse(), output.trim().parse()) {
+                (Ok(i), Ok(o)) => {
+                    table.insert(model.trim().to_string(), (i, o));
+                }
+                _ => eprintln!("warning: ignoring invalid price for {model}"),
+            }
+        }
+        Prices(table)
+    }
+
+    /// Total spend in `ledger` for all days starting with
+    /// `prefix`, so a day ("2026-03-14") or a month ("2026-03")
+    /// can be queried.
+    pub fn spent(&self, ledger: &Ledger, prefix: &str) -> f64 {
+        ledger
+            .days
+            .iter()
+            .filter(|(day, _)| day.starts_with(prefix))
+            .flat_map(|(_, models)| models.iter())
+            .map(|(model, u)| self.cost(model, u.input_tokens, u.output_tokens))
+            .sum()
+    }
+
+    /// Estimated cost in USD. Unknown models cost nothing.
+    pub fn cost(&self, model: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+        match self.0.get(model) {
+            Some((i, o)) => (input_tokens as f64 * i + output_tokens as f64 * o) / 1_000_000.0,
+            None => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Budget {
+    pub daily_usd: Option<f64>,
+    pub monthly_usd: Option<f64>,
+    /// Model to downgrade to once a budget is exceeded.
+    /// Turns are refused if unset.
+    pub fallback_model: Option<String>,
+}
+
+impl Budget {
+    /// Returns the model to use for the turn, or a `limit` error
+    /// if a budget is exhausted and there is no fallback.
+    pub fn select_model(
+        &self,
+        model: &str,
+        ledger: &Ledger,
+        prices: &Prices,
+        day: &str,
+    ) -> Result<String, AgentError> {
+        let month = &day[..day.len().min(7)];
+        let exceeded = [
+            ("daily", self.daily_usd, day),
+            ("monthly", self.monthly_usd, month),
+        ]
+        .into_iter()
+        .find_map(|(period, limit, prefix)| {
+            let limit = limit?;
+            let spent = prices.spent(ledger, prefix);
+            (spent >= limit)
+                .then(|| format!("{period} budget of ${limit:.2} exceeded (${spent:.2} spent)"))
+        });
+        match (exceeded, &self.fallback_model) {
+            (None, _) => Ok(model.to_string()),
+            (Some(reason), Some(fallback)) => {
+                eprintln!("warning: {reason}; using {fallback}");
+                Ok(fallback.clone())
+            }
+            (Some(reason), None) => Err(AgentError::new(ErrorKind::Limit, reason)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asterbot_common::usage::ModelUsage;
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> ModelUsage {
+        ModelUsage {
+            calls: 1,
+            input_tokens,
+            output_tokens,
+        }
+    }
+
+    #[test]
+    fn prices_use_defaults_and_overrides() {
+        let prices = Prices::parse("custom/model=2:8, openai/gpt-4o=1:1, bad=x:1");
+        assert_eq!(prices.cost("custom/model", 1_000_000, 500_000), 6.0);
+        assert_eq!(prices.cost("openai/gpt-4o", 1_000_000, 1_000_000), 2.0);
+        assert_eq!(
+            prices.cost("anthropic/claude-sonnet-4-5", 1_000_000, 0),
+            3.0
+        );
+        assert_eq!(prices.cost("unknown/model", 1_000_000, 1_000_000), 0.0);
+        assert_eq!(prices.cost("bad", 1_000_000, 1_000_000), 0.0);
+    }
+
+    #[test]
+    fn spent_filters_by_day_or_month() {
+        let prices = Prices::parse("m=1:0");
+        let mut ledger = Ledger::default();
+        ledger.record("2026-03-14", "", "m", usage(1_000_000, 0));
+        ledger.record("2026-03-15", "", "m", usage(2_000_000, 0));
+        ledger.record("2026-04-01", "", "m", usage(4_000_000, 0));
+        assert_eq!(prices.spent(&ledger, "2026-03-14"), 1.0);
+        assert_eq!(prices.spent(&ledger, "2026-03"), 3.0);
+    }
+
+    #[test]
+    fn budget_allows_refuses_or_downgrades() {
+        let prices = Prices::parse("m=1:0");
+        let mut ledger = Ledger::default();
+        ledger.record("2026-03-14", "", "m", usage(2_000_000, 0));
+        let within = Budget {
+            daily_usd: Some(5.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            within.select_model("m", &ledger, &prices, "2026-03-14"),
+            Ok("m".into())
+        );
+        let daily = Budget {
+            daily_usd: Some(2.0),
+            ..Default::default()
+        };
+        let err = daily
+            .select_model("m", &ledger, &prices, "2026-03-14")
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Limit);
+        assert_eq!(err.message, "daily budget of $2.00 exceeded ($2.00 spent)");
+        // A new day resets the daily budget but not the monthly one.
+        assert!(daily
+            .select_model("m", &ledger, &prices, "2026-03-15")
+            .is_ok());
+        let monthly = Budget {
+            monthly_usd: Some(1.0),
+            fallback_model: Some("cheap".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            monthly.select_model("m", &ledger, &prices, "2026-03-20"),
+            Ok("cheap".into()),
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Mirrors `asterbot:types/types.error-kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    Config,
+    Llm,
+    Tool,
+    Storage,
+    Permission,
+    Limit,
+}
+
+/// Mirrors `asterbot:types/types.agent-error`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AgentError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl AgentError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        AgentError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Renders the error in the "error: " string form the model
+/// sees for failed tool calls.
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.message)
+    }
+}
+
+/// JSON encoding of a WIT `result<T, agent-error>` at the
+/// dynamic call boundary: `{"ok": ...}` or `{"err": {...}}`.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WitResult<T> {
+    Ok(T),
+    Err(AgentError),
+}
+
+impl<T> From<WitResult<T>> for Result<T, AgentError> {
+    fn from(r: WitResult<T>) -> Self {
+        match r {
+            WitResult::Ok(v) => Ok(v),
+            WitResult::Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Scripted stand-ins for the LLM and the components core talks
+//! to, so the full agent loop can run offline in tests.
+
+use crate::error::{AgentError, ErrorKind};
+use crate::host::{ChatMessage, ChatResponse, Host, HostError, ToolCall, ToolDefinition};
+use crate::WitChatMessage;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+type Handler = Box<dyn Fn(&str) -> Result<String, HostError>>;
+type ToolHandler = Box<dyn Fn(&str) -> Result<String, AgentError>>;
+
+struct FakeTool {
+    component: String,
+    function: String,
+    description: String,
+    params: Vec<(String, String)>,
+    handler: ToolHandler,
+}
+
+/// In-memory `asterbot:history`.
+#[derive(Default)]
+pub struct FakeHistory {
+    pub messages: Vec<ChatMessage>,
+    pub context: String,
+    pub compact_threshold: Option<usize>,
+    pub compactions: usize,
+    /// Working sets of the conversations other than the default.
+    pub sessions: HashMap<String, Vec<ChatMessage>>,
+}
+
+#[derive(Default)]
+pub struct FakeHost {
+    responses: RefCell<VecDeque<Result<ChatResponse, AgentError>>>,
+    functions: HashMap<(String, String), Handler>,
+    tools: Option<Vec<FakeTool>>,
+    pub history: Option<RefCell<FakeHistory>>,
+    /// Every chat request, in order.
+    pub requests: RefCell<Vec<Vec<ChatMessage>>>,
+    /// Every component call as (component, function, args).
+    pub calls: RefCell<Vec<(String, String, String)>>,
+}
+
+impl FakeHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a plain text LLM reply.
+    pub fn reply(self, content: &str) -> Self {
+        self.respond(ChatResponse {
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+        })
+    }
+
+    /// Queue an LLM reply that calls a single tool.
+    pub fn call(self, id: &str, tool_name: &str, arguments_json: &str) -> Self {
+        self.respond(ChatResponse {
+            content: String::new(),
+            tool_calls: vec![ToolCall {
+                id: id.to_string(),
+                name: tool_name.to_string(),
+                arguments_json: arguments_json.to_string(),
+            }],
+        })
+    }
+
+    pub fn respond(self, response: ChatResponse) -> Self {
+        self.responses.borrow_mut().push_back(Ok(response));
+        self
+    }
+
+    /// Queue an LLM provider failure.
+    pub fn llm_error(self, message: &str) -> Self {
+        self.responses
+            .borrow_mut()
+            .push_back(Err(AgentError::new(ErrorKind::Llm, message)));
+        self
+    }
+
+    /// Register a raw component function. `handler` receives the
+    /// JSON args array and returns the JSON-encoded result.
+    pub fn function(
+        mut self,
+        component: &str,
+        function: &str,
+        handler: impl Fn(&str) -> Result<String, HostError> + 'static,
+    ) -> Self {
+        self.functions.insert(
+            (component.to_string(), function.to_string()),
+            Box::new(handler),
+        );
+        self
+    }
+
+    /// Enable `asterbot:toolkit` and register a tool behind it.
+    /// `handler` receives the LLM's arguments JSON and returns
+    /// the tool's result.
+    pub fn tool(
+        mut self,
+        component: &str,
+        function: &str,
+        params: &[(&str, &str)],
+        handler: impl Fn(&str) -> Result<String, AgentError> + 'static,
+    ) -> Self {
+        self.tools.get_or_insert_with(Vec::new).push(FakeTool {
+            component: component.to_string(),
+            function: function.to_string(),
+            description: format!("Fake {function}."),
+            params: params
+                .iter()
+                .map(|(n, t)| (n.to_string(), t.to_string()))
+                .collect(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Enable `asterbot:history` with the given working set.
+    pub fn history(mut self, messages: Vec<ChatMessage>) -> Self {
+        self.history = Some(RefCell::new(FakeHistory {
+            messages,
+            ..Default::default()
+        }));
+        self
+    }
+
+    /// Make `asterbot:history` request compaction once the
+    /// working set reaches `threshold` messages.
+    pub fn compact_at(self, threshold: usize) -> Self {
+        if let Some(h) = &self.history {
+            h.borrow_mut().compact_threshold = Some(threshold);
+        }
+        self
+    }
+
+    /// Messages saved to the fake history.
+    pub fn saved(&self) -> Vec<ChatMessage> {
+        self.history
+            .as_ref()
+            .map(|h| h.borrow().messages.clone())
+            .unwrap_or_default()
+    }
+
+    /// Messages saved to a session in the fake history.
+    pub fn saved_in(&self, session: &str) -> Vec<ChatMessage> {
+        self.history
+            .as_ref()
+            .and_then(|h| h.borrow().sessions.get(session).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn remaining_responses(&self) -> usize {
+        self.responses.borrow().len()
+    }
+
+    /// Number of calls made to the given component function.
+    pub fn call_count(&self, component: &str, function: &str) -> usize {
+        self.calls
+            .borrow()
+            .iter()
+            .filter(|(c, f, _)| c == component && f == function)
+            .count()
+    }
+
+    fn toolkit(&self, function: &str, args: &str) -> Result<String, HostError> {
+        let tools = self.tools.as_ref().expect("toolkit enabled");
+        match function {
+            "toolkit/list-tools" => {
+                let infos: Vec<Value> = tools
+                    .iter()
+                    .map(|t| {
+                        serde_json::json!({
+                            "component-name": t.component,
+                            "function-name": t.function,
+                            "description": t.description,
+                            "params": t.params.iter().map(|(n, ty)| serde_json::json!({
+                                "name": n,
+                                "type-name": ty,
+                            })).collect::<Vec<_>>(),
+                            "return-type": "string",
+                        })
+                    })
+                    .collect();
+                Ok(serde_json::to_string(&infos).unwrap())
+            }
+            "toolkit/try-call-tool-in-turn" => {
+                let (_turn_id, _session, component, function, tool_args): (
+                    String,
+                    String,
+                    String,
+                    String,
+                    String,
+                ) = serde_json::from_str(args).expect("try-call-tool-in-turn args");
+                let result = match tools
+                    .iter()
+                    .find(|t| t.component == component && t.function == function)
+                {
+                    Some(t) => (t.handler)(&tool_args),
+                    None => Err(AgentError::new(
+                        ErrorKind::Permission,
+                        format!("component '{component}' is not in ASTERBOT_TOOLS"),
+                    )),
+                };
+                let encoded = match result {
+                    Ok(v) => serde_json::json!({ "ok": v }),
+                    Err(e) => serde_json::json!({
+                        "err": { "kind": kind_name(e.kind), "message": e.message },
+                    }),
+                };
+                Ok(encoded.to_string())
+            }
+            _ => Err(not_found("asterbot:toolkit", function)),
+        }
+    }
+
+    fn history_call(
+        &self,
+        history: &RefCell<FakeHistory>,
+        function: &str,
+        args: &str,
+    ) -> Result<String, HostError> {
+        let mut h = history.borrow_mut();
+        match function {
+            "history/load" => Ok(encode_messages(&h.messages)),
+            "history/save" => {
+                let (msgs,): (Vec<WitChatMessage>,) =
+                    serde_json::from_str(args).expect("save args");
+                h.messages = msgs.iter().map(|m| m.to_chat_message()).collect();
+                Ok("null".to_string())
+            }
+            "history/get-context" => Ok(serde_json::to_string(&h.context).unwrap()),
+            "history/should-compact" => {
+                let (count,): (usize,) = serde_json::from_str(args).expect("count args");
+                let should = h.compact_threshold.is_some_and(|t| count >= t);
+                Ok(should.to_string())
+            }
+            "history/compact-in-turn" => {
+                h.compactions += 1;
+                h.context = "## Conversation so far\nCompacted.".to_string();
+                h.messages.clear();
+                Ok("[]".to_string())
+            }
+            "history/load-session" => {
+                let (session,): (String,) = serde_json::from_str(args).expect("load args");
+                Ok(encode_messages(
+                    h.sessions.get(&session).map_or(&[][..], Vec::as_slice),
+                ))
+            }
+            "history/save-session" => {
+                let (session, msgs): (String, Vec<WitChatMessage>) =
+                    serde_json::from_str(args).expect("save args");
+                let msgs = msgs.iter().map(|m| m.to_chat_message()).collect();
+                h.sessions.insert(session, msgs);
+                Ok("null".to_string())
+            }
+            "history/get-session-context" => Ok("\"\"".to_string()),
+            _ => Err(not_found("asterbot:history", function)),
+        }
+    }
+}
+
+impl Host for FakeHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, HostError> {
+        self.calls.borrow_mut().push((
+            component.to_string(),
+            function.to_string(),
+            args_json.to_string(),
+        ));
+        if let Some(handler) = self
+            .functions
+            .get(&(component.to_string(), function.to_string()))
+        {
+            return handler(args_json);
+        }
+        match component {
+            "asterbot:toolkit" if self.tools.is_some() => self.toolkit(function, args_json),
+            "asterbot:history" => match &self.history {
+                Some(h) => self.history_call(h, function, args_json),
+                None => Err(not_found(component, function)),
+            },
+            _ => Err(not_found(component, function)),
+        }
+    }
+
+    fn chat(
+        &self,
+        messages: &[ChatMessage],
+        _tools: &[ToolDefinition],
+        _model: &str,
+    ) -> Result<ChatResponse, AgentError> {
+        self.requests.borrow_mut().push(messages.to_vec());
+        self.responses
+            .borrow_mut()
+            .pop_front()
+            .expect("unexpected chat request: no scripted responses left")
+    }
+}
+
+fn encode_messages(messages: &[ChatMessage]) -> String {
+    let msgs: Vec<WitChatMessage> = messages
+        .iter()
+        .map(WitChatMessage::from_chat_message)
+        .collect();
+    serde_json::to_string(&msgs).unwrap()
+}
+
+fn kind_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Config => "config",
+        ErrorKind::Llm => "llm",
+        ErrorKind::Tool => "tool",
+        ErrorKind::Storage => "storage",
+        ErrorKind::Permission => "permission",
+        ErrorKind::Limit => "limit",
+    }
+}
+
+fn not_found(component: &str, function: &str) -> HostError {
+    HostError {
+        kind: "NotFound".to_string(),
+        message: format!("{component}/{function} not found"),
+    }
+}
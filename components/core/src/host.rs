@@ -0,0 +1,293 @@
+//! The seam between the agent loop and the asterai host.
+//!
+//! Everything the loop needs from the outside world — dynamic
+//! component calls and LLM chat — goes through [`Host`], so the
+//! loop can run against the real host API in the component or
+//! against a scripted fake in tests.
+
+use crate::error::{AgentError, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Mirrors `asterai:llm/llm.chat-role`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// Mirrors `asterai:llm/llm.tool-call`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub arguments_json: String,
+}
+
+/// Mirrors `asterai:llm/llm.chat-message`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub tool_call_id: Option<String>,
+}
+
+/// Mirrors `asterai:llm/llm.tool-definition`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters_json_schema: String,
+}
+
+/// Mirrors `asterai:llm/llm.chat-response`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChatResponse {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// Converts the response of `asterai:llm` `chat`, which has no
+/// error channel: provider failures come back in-band as
+/// "error: ..." content with no tool calls.
+pub fn from_llm_response(response: ChatResponse) -> Result<ChatResponse, AgentError> {
+    if response.tool_calls.is_empty() {
+        if let Some(message) = response.content.strip_prefix("error: ") {
+            return Err(AgentError::new(ErrorKind::Llm, message));
+        }
+    }
+    Ok(response)
+}
+
+/// A failed dynamic component call.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostError {
+    /// Debug name of the host error kind (e.g. "NotFound").
+    pub kind: String,
+    pub message: String,
+}
+
+pub trait Host {
+    /// Call a component function with JSON-encoded arguments,
+    /// returning the JSON-encoded result.
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, HostError>;
+
+    /// Send a chat request to the LLM.
+    fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        model: &str,
+    ) -> Result<ChatResponse, AgentError>;
+}
+
+#[cfg(not(test))]
+pub use wasm::{from_wit_part, to_wit_error, to_wit_response, WasmHost};
+
+#[cfg(not(test))]
+mod wasm {
+    use super::{
+        from_llm_response, ChatMessage, ChatResponse, ChatRole, Host, HostError, ToolCall,
+        ToolDefinition,
+    };
+    use crate::bindings::asterai::host::api;
+    use crate::bindings::asterai::llm::llm;
+    use crate::bindings::asterbot::types::types as wit;
+    use crate::error::{AgentError, ErrorKind};
+    use crate::input::{InputPart, Media, MediaData};
+    use crate::trace::{ConverseResponse, StopReason};
+
+    /// The real host, backed by the generated WIT bindings.
+    pub struct WasmHost;
+
+    impl Host for WasmHost {
+        fn call_component_function(
+            &self,
+            component: &str,
+            function: &str,
+            args_json: &str,
+        ) -> Result<String, HostError> {
+            api::call_component_function(component, function, args_json).map_err(|e| HostError {
+                kind: format!("{:?}", e.kind),
+                message: e.message,
+            })
+        }
+
+        fn chat(
+            &self,
+            messages: &[ChatMessage],
+            tools: &[ToolDefinition],
+            model: &str,
+        ) -> Result<ChatResponse, AgentError> {
+            let messages: Vec<llm::ChatMessage> = messages.iter().map(to_llm_message).collect();
+            let tools: Vec<llm::ToolDefinition> = tools
+                .iter()
+                .map(|t| llm::ToolDefinition {
+                    name: t.name.clone(),
+                    description: t.description.clone(),
+                    parameters_json_schema: t.parameters_json_schema.clone(),
+                })
+                .collect();
+            let response = llm::chat(&messages, &tools, model);
+            from_llm_response(ChatResponse {
+                content: response.content,
+                tool_calls: response
+                    .tool_calls
+                    .into_iter()
+                    .map(|tc| ToolCall {
+                        id: tc.id,
+                        name: tc.name,
+                        arguments_json: tc.arguments_json,
+                    })
+                    .collect(),
+            })
+        }
+    }
+
+    pub fn to_wit_error(e: AgentError) -> wit::AgentError {
+        let kind = match e.kind {
+            ErrorKind::Config => wit::ErrorKind::Config,
+            ErrorKind::Llm => wit::ErrorKind::Llm,
+            ErrorKind::Tool => wit::ErrorKind::Tool,
+            ErrorKind::Storage => wit::ErrorKind::Storage,
+            ErrorKind::Permission => wit::ErrorKind::Permission,
+            ErrorKind::Limit => wit::ErrorKind::Limit,
+        };
+        wit::AgentError {
+            kind,
+            message: e.message,
+        }
+    }
+
+    pub fn to_wit_response(r: ConverseResponse) -> wit::ConverseResponse {
+        wit::ConverseResponse {
+            reply: r.reply,
+            attachments: r.attachments.into_iter().map(to_wit_media).collect(),
+            buttons: r.buttons,
+            location: r.location.map(|l| wit::Location {
+                latitude: l.latitude,
+                longitude: l.longitude,
+                label: l.label,
+            }),
+            tool_invocations: r
+                .tool_invocations
+                .into_iter()
+                .map(|t| wit::ToolInvocation {
+                    name: t.name,
+                    args_json: t.args_json,
+                    result: t.result,
+                    duration_ms: t.duration_ms,
+                    is_error: t.is_error,
+                })
+                .collect(),
+            usage: wit::Usage {
+                rounds: r.usage.rounds,
+                llm_calls: r.usage.llm_calls,
+                input_tokens: r.usage.input_tokens,
+                output_tokens: r.usage.output_tokens,
+                cost_usd: r.usage.cost_usd,
+            },
+            model: r.model,
+            stop_reason: match r.stop_reason {
+                StopReason::Completed => wit::StopReason::Completed,
+                StopReason::MaxToolRounds => wit::StopReason::MaxToolRounds,
+            },
+            turn_id: r.turn_id,
+        }
+    }
+
+    fn to_wit_media(m: Media) -> wit::Media {
+        wit::Media {
+            mime_type: m.mime_type,
+            name: m.name,
+            data: match m.data {
+                MediaData::Bytes(bytes) => wit::MediaData::Bytes(bytes),
+                MediaData::Url(url) => wit::MediaData::Url(url),
+            },
+        }
+    }
+
+    pub fn from_wit_part(part: wit::InputPart) -> InputPart {
+        let media = |m: wit::Media| Media {
+            mime_type: m.mime_type,
+            name: m.name,
+            data: match m.data {
+                wit::MediaData::Bytes(bytes) => MediaData::Bytes(bytes),
+                wit::MediaData::Url(url) => MediaData::Url(url),
+            },
+        };
+        match part {
+            wit::InputPart::Text(text) => InputPart::Text(text),
+            wit::InputPart::Image(m) => InputPart::Image(media(m)),
+            wit::InputPart::Audio(m) => InputPart::Audio(media(m)),
+            wit::InputPart::File(m) => InputPart::File(media(m)),
+        }
+    }
+
+    fn to_llm_message(msg: &ChatMessage) -> llm::ChatMessage {
+        let role = match msg.role {
+            ChatRole::System => llm::ChatRole::System,
+            ChatRole::User => llm::ChatRole::User,
+            ChatRole::Assistant => llm::ChatRole::Assistant,
+            ChatRole::Tool => llm::ChatRole::Tool,
+        };
+        llm::ChatMessage {
+            role,
+            content: msg.content.clone(),
+            tool_calls: msg
+                .tool_calls
+                .iter()
+                .map(|tc| llm::ToolCall {
+                    id: tc.id.clone(),
+                    name: tc.name.clone(),
+                    arguments_json: tc.arguments_json.clone(),
+                })
+                .collect(),
+            tool_call_id: msg.tool_call_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content: &str, tool_calls: Vec<ToolCall>) -> ChatResponse {
+        ChatResponse {
+            content: content.to_string(),
+            tool_calls,
+        }
+    }
+
+    #[test]
+    fn in_band_errors_are_llm_errors() {
+        let err = from_llm_response(response("error: provider said no", vec![])).unwrap_err();
+        assert_eq!(err, AgentError::new(ErrorKind::Llm, "provider said no"));
+        let reply = response("no error: all good", vec![]);
+        assert_eq!(from_llm_response(reply.clone()), Ok(reply));
+    }
+
+    #[test]
+    fn tool_calls_are_never_errors() {
+        let call = ToolCall {
+            id: "call-1".to_string(),
+            name: "memory--get".to_string(),
+            arguments_json: "{}".to_string(),
+        };
+        let response = response("error: checking memory first", vec![call]);
+        assert_eq!(from_llm_response(response.clone()), Ok(response));
+    }
+}
@@ -0,0 +1,213 @@
+//! Converse input with attachments.
+//!
+//! Gateways pass photos, voice notes and documents along with the
+//! message text. `asterai:llm` chat messages are text-only, so each
+//! attachment is described in the user message instead: its bytes
+//! are saved under `attachments/` in the host dir and referenced by
+//! path, where the agent's file tools can reach them, and text
+//! documents are also inlined so the model can read them directly.
+
+use crate::error::{AgentError, ErrorKind};
+
+pub const ATTACHMENTS_DIR: &str = "attachments";
+/// Text attachments longer than this are saved but not inlined.
+const MAX_INLINE_CHARS: usize = 20_000;
+
+/// Mirrors `asterbot:types/types.media-data`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaData {
+    Bytes(Vec<u8>),
+    Url(String),
+}
+
+/// Mirrors `asterbot:types/types.media`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Media {
+    pub mime_type: String,
+    pub name: String,
+    pub data: MediaData,
+}
+
+/// Mirrors `asterbot:types/types.input-part`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputPart {
+    Text(String),
+    Image(Media),
+    Audio(Media),
+    File(Media),
+}
+
+/// A prefix for attachment file names: the time in milliseconds,
+/// so files from different messages don't collide.
+#[cfg(not(test))]
+pub fn stamp() -> String {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+        .to_string()
+}
+
+/// Flattens `parts` into the text of the user message, saving
+/// attachment bytes to `dir` with file names starting `stamp`.
+pub fn flatten(parts: &[InputPart], dir: &str, stamp: &str) -> Result<String, AgentError> {
+    let mut lines = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let (kind, media) = match part {
+            InputPart::Text(text) => {
+                lines.push(text.clone());
+                continue;
+            }
+            InputPart::Image(media) => ("image", media),
+            InputPart::Audio(media) => ("audio", media),
+            InputPart::File(media) => ("file", media),
+        };
+        let mime = match media.mime_type.is_empty() {
+            true => "unknown type",
+            false => media.mime_type.as_str(),
+        };
+        let bytes = match &media.data {
+            MediaData::Url(url) => {
+                lines.push(format!("[Attached {kind}: {url} ({mime})]"));
+                continue;
+            }
+            MediaData::Bytes(bytes) => bytes,
+        };
+        let path = format!("{dir}/{stamp}-{}-{}", i + 1, file_name(media, kind));
+        save(&path, bytes)?;
+        lines.push(format!(
+            "[Attached {kind}: {path} ({mime}, {})]",
+            format_size(bytes.len())
+        ));
+        if let Some(text) = inline_text(media, bytes) {
+            lines.push(format!("```\n{text}\n```"));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+fn save(path: &str, bytes: &[u8]) -> Result<(), AgentError> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            AgentError::new(
+                ErrorKind::Storage,
+                format!("failed to create {}: {e}", parent.display()),
+            )
+        })?;
+    }
+    std::fs::write(path, bytes)
+        .map_err(|e| AgentError::new(ErrorKind::Storage, format!("failed to write {path}: {e}")))
+}
+
+/// The attachment's name, made safe for a path, or one made
+/// up from its kind.
+fn file_name(media: &Media, kind: &str) -> String {
+    let name: String = media
+        .name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    match name.trim_matches('.').is_empty() {
+        true => kind.to_string(),
+        false => name,
+    }
+}
+
+/// The attachment's content, if it is a short text document.
+fn inline_text<'a>(media: &Media, bytes: &'a [u8]) -> Option<&'a str> {
+    let mime = media.mime_type.as_str();
+    let is_text = mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml"
+        );
+    let text = std::str::from_utf8(bytes).ok()?;
+    (is_text && text.chars().count() <= MAX_INLINE_CHARS).then_some(text.trim_end())
+}
+
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{} KB", bytes / 1024),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media(mime_type: &str, name: &str, data: MediaData) -> Media {
+        Media {
+            mime_type: mime_type.to_string(),
+            name: name.to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn attachments_are_saved_and_described() {
+        let dir = std::env::temp_dir().join(format!("asterbot-core-input-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let parts = vec![
+            InputPart::Text("What's in these?".to_string()),
+            InputPart::Image(media(
+                "image/jpeg",
+                "../photo 1.jpg",
+                MediaData::Bytes(vec![0; 2048]),
+            )),
+            InputPart::File(media(
+                "text/plain",
+                "notes.txt",
+                MediaData::Bytes(b"buy milk\n".to_vec()),
+            )),
+            InputPart::Audio(media(
+                "",
+                "",
+                MediaData::Url("https://cdn.example/v.ogg".to_string()),
+            )),
+        ];
+        let text = flatten(&parts, dir, "100").unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "What's in these?\n\
+                 [Attached image: {dir}/100-2-photo_1.jpg (image/jpeg, 2 KB)]\n\
+                 [Attached file: {dir}/100-3-notes.txt (text/plain, 9 B)]\n\
+                 ```\nbuy milk\n```\n\
+                 [Attached audio: https://cdn.example/v.ogg (unknown type)]"
+            )
+        );
+        assert_eq!(
+            std::fs::read(format!("{dir}/100-2-photo_1.jpg"))
+                .unwrap()
+                .len(),
+            2048
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_text_documents_are_inlined() {
+        let pdf = media(
+            "application/pdf",
+            "a.pdf",
+            MediaData::Bytes(b"%PDF".to_vec()),
+        );
+        assert_eq!(inline_text(&pdf, b"%PDF"), None);
+        let json = media("application/json", "a.json", MediaData::Bytes(Vec::new()));
+        assert_eq!(inline_text(&json, b"{}\n"), Some("{}"));
+        assert_eq!(inline_text(&json, &[0xff, 0xfe]), None);
+        assert_eq!(file_name(&pdf, "file"), "a.pdf");
+        assert_eq!(
+            file_name(&media("", "..", MediaData::Bytes(Vec::new())), "image"),
+            "image"
+        );
+    }
+}
@@ -1,12 +1,35 @@
-use crate::bindings::asterai::host::api;
-use crate::bindings::asterai::llm::llm::{
-    chat, ChatMessage, ChatRole, ToolCall, ToolDefinition,
-};
+#[cfg(not(test))]
 use crate::bindings::exports::asterbot::types::core::Guest;
+use crate::budget::{Budget, Prices};
+use crate::error::{AgentError, ErrorKind, WitResult};
+use crate::host::{ChatMessage, ChatRole, Host, HostError, ToolCall, ToolDefinition};
+use crate::input::InputPart;
+use crate::progress::{ProgressTarget, Reporter};
+use crate::replay::{Recorder, Recording, ReplayReport, Replayer, Settings};
+use crate::reply::RichReply;
+use crate::stub::StubLlm;
+use crate::trace::{ConverseResponse, StopReason, ToolInvocation, Usage};
+use asterbot_common::events::{EventLog, Turn};
+use asterbot_common::usage::{self, Ledger, ModelUsage};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::Instant;
 
+mod budget;
+mod error;
+#[cfg(test)]
+mod fake_host;
+mod host;
+mod input;
+mod progress;
+mod replay;
+mod reply;
+mod stub;
+mod trace;
+
+const COMPONENT: &str = "asterbot:core";
 const MAX_SUGGESTIONS: usize = 3;
+#[cfg(not(test))]
 const DEFAULT_SYSTEM_PROMPT: &str = "\
 You are a personal AI assistant running inside Asterbot.
 
@@ -20,6 +43,7 @@ const TOOL_RESULT_TRUNCATE_CHARS: usize = 10_000;
 // ~120k tokens at ~4 chars/token, leaving room for model response.
 const DEFAULT_MAX_PROMPT_CHARS: usize = 500_000;
 
+#[cfg(not(test))]
 #[allow(warnings)]
 mod bindings {
     wit_bindgen::generate!({
@@ -29,6 +53,7 @@ mod bindings {
     });
 }
 
+#[cfg(not(test))]
 struct Component;
 
 /// WIT JSON encoding of ChatMessage for the dynamic call boundary.
@@ -126,117 +151,557 @@ struct ToolParamJson {
     type_name: String,
 }
 
-impl Guest for Component {
-    fn converse(input: String) -> String {
-        let model = std::env::var("ASTERBOT_MODEL").unwrap_or_default();
+/// Settings resolved from the environment at the start of a turn.
+#[derive(Clone)]
+struct Config {
+    model: String,
+    max_tool_rounds: usize,
+    max_prompt_chars: usize,
+    max_prompt_user_messages: Option<usize>,
+    system_prompt: String,
+    prices: Prices,
+    budget: Budget,
+    /// Where usage totals are persisted. Not tracked if `None`.
+    usage_path: Option<String>,
+    /// Current UTC day ("YYYY-MM-DD"), used for usage and budgets.
+    day: String,
+    events: EventLog,
+    /// Where turn recordings are written. Not recorded if `None`.
+    record_dir: Option<String>,
+    /// Recording to replay instead of running a live turn.
+    replay_path: Option<String>,
+    /// Script for the stub LLM, if the model is `asterbot/stub`.
+    stub_path: Option<String>,
+    /// Where attachments sent with `converse` are saved, and
+    /// attachments in replies are read from.
+    host_dir: String,
+    /// Where to report progress, if the request names a target.
+    progress: Option<ProgressTarget>,
+    /// The conversation the turn is in, from the request. Empty
+    /// for the default conversation.
+    session: String,
+    progress_interval_ms: u64,
+}
+
+#[cfg(not(test))]
+impl Config {
+    fn from_env() -> Result<Self, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let model = resolve_model(&host_dir);
         if model.is_empty() {
-            return "error: ASTERBOT_MODEL env var is required".to_string();
+            return Err(AgentError::new(
+                ErrorKind::Config,
+                "ASTERBOT_MODEL env var is required",
+            ));
         }
         let max_tool_rounds = std::env::var("ASTERBOT_MAX_TOOL_ROUNDS")
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(DEFAULT_MAX_TOOL_ROUNDS);
-        let host_dir = match resolve_host_dir() {
-            Ok(d) => d,
-            Err(e) => return e,
+        let max_prompt_chars = std::env::var("ASTERBOT_MAX_PROMPT_CHARS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_PROMPT_CHARS);
+        let max_prompt_user_messages = std::env::var("ASTERBOT_MAX_PROMPT_USER_MESSAGES")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let budget = Budget {
+            daily_usd: std::env::var("ASTERBOT_DAILY_BUDGET_USD")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            monthly_usd: std::env::var("ASTERBOT_MONTHLY_BUDGET_USD")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            fallback_model: std::env::var("ASTERBOT_BUDGET_FALLBACK_MODEL")
+                .ok()
+                .filter(|v| !v.is_empty()),
         };
-        let mut history = load_history();
-        // TODO: Run compaction asynchronously after response
-        // delivery to avoid blocking the user. Consider
-        // asterai:host-cron for deferred execution.
-        if should_compact_history(history.len()) {
-            history = compact_history(history);
-        }
-        let system_message = build_system_message(&host_dir, &input);
-        let tools = get_tool_entries();
-        history.push(ChatMessage {
-            role: ChatRole::User,
-            content: input,
-            tool_calls: Vec::new(),
-            tool_call_id: None,
-        });
-        let tool_defs: Vec<ToolDefinition> =
-            tools.iter().map(|t| t.definition.clone()).collect();
-        let mut rounds_remaining = max_tool_rounds;
-        loop {
-            let mut messages = vec![system_message.clone()];
-            messages.extend(trim_history(&history).iter().cloned());
-            let response = chat(&messages, &tool_defs, &model);
-            if response.content.starts_with("error: ") && response.tool_calls.is_empty() {
-                save_history(&history);
-                return response.content;
+        let stub_path =
+            (model == stub::STUB_MODEL).then(|| format!("{host_dir}/{}", stub::STUB_FILENAME));
+        Ok(Config {
+            model,
+            max_tool_rounds,
+            max_prompt_chars,
+            max_prompt_user_messages,
+            system_prompt: resolve_system_prompt(&host_dir),
+            prices: Prices::parse(&std::env::var("ASTERBOT_MODEL_PRICES").unwrap_or_default()),
+            budget,
+            usage_path: Some(format!("{host_dir}/{}", usage::USAGE_FILENAME)),
+            day: usage::today(),
+            events: EventLog::from_env(COMPONENT),
+            record_dir: std::env::var("ASTERBOT_RECORD_TURNS")
+                .map(|v| v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false)
+                .then(|| format!("{host_dir}/{}", replay::RECORDINGS_DIR)),
+            replay_path: std::env::var("ASTERBOT_REPLAY")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|v| format!("{host_dir}/{v}")),
+            stub_path,
+            host_dir,
+            progress: None,
+            session: String::new(),
+            progress_interval_ms: std::env::var("ASTERBOT_PROGRESS_INTERVAL_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(progress::DEFAULT_INTERVAL_MS),
+        })
+    }
+}
+
+impl Config {
+    fn settings(&self) -> Settings {
+        Settings {
+            model: self.model.clone(),
+            system_prompt: self.system_prompt.clone(),
+            max_tool_rounds: self.max_tool_rounds,
+            max_prompt_chars: self.max_prompt_chars,
+            max_prompt_user_messages: self.max_prompt_user_messages,
+            session: self.session.clone(),
+        }
+    }
+}
+
+#[cfg(not(test))]
+impl Guest for Component {
+    fn converse(
+        request: bindings::asterbot::types::types::ConverseRequest,
+    ) -> Result<
+        bindings::asterbot::types::types::ConverseResponse,
+        bindings::asterbot::types::types::AgentError,
+    > {
+        let config = Config {
+            progress: request.progress.map(|target| ProgressTarget {
+                component: target.component,
+                context: target.context,
+            }),
+            session: request.session.unwrap_or_default(),
+            ..Config::from_env().map_err(host::to_wit_error)?
+        };
+        let parts: Vec<InputPart> = request.parts.into_iter().map(host::from_wit_part).collect();
+        converse_parts(&host::WasmHost, &config, &parts, &input::stamp())
+            .map(host::to_wit_response)
+            .map_err(host::to_wit_error)
+    }
+}
+
+/// Runs a live turn, or replays the recording named by
+/// `ASTERBOT_REPLAY` (ignoring `input` in favour of the recorded
+/// one) and writes a report of divergences next to it.
+fn run_configured_turn(
+    host: &dyn Host,
+    config: &Config,
+    input: String,
+) -> Result<ConverseResponse, AgentError> {
+    let Some(path) = &config.replay_path else {
+        return match &config.stub_path {
+            Some(stub_path) => run_turn(&StubLlm::new(host, stub_path), config, input),
+            None => run_turn(host, config, input),
+        };
+    };
+    let recording = Recording::load(path)?;
+    let (response, report) = replay_turn(config, &recording);
+    for divergence in &report.divergences {
+        eprintln!("warning: replay diverged: {divergence}");
+    }
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => {
+            let report_path = format!("{path}.report.json");
+            if let Err(e) = std::fs::write(&report_path, json) {
+                eprintln!("error: failed to write {report_path}: {e}");
             }
-            if response.tool_calls.is_empty() {
-                history.push(ChatMessage {
-                    role: ChatRole::Assistant,
-                    content: response.content.clone(),
-                    tool_calls: Vec::new(),
-                    tool_call_id: None,
-                });
-                save_history(&history);
-                return response.content;
+        }
+        Err(e) => eprintln!("error: failed to serialise replay report: {e}"),
+    }
+    response
+}
+
+/// Runs a turn for text and attachments, which are flattened
+/// into the text of the user message.
+fn converse_parts(
+    host: &dyn Host,
+    config: &Config,
+    parts: &[InputPart],
+    stamp: &str,
+) -> Result<ConverseResponse, AgentError> {
+    let dir = format!("{}/{}", config.host_dir, input::ATTACHMENTS_DIR);
+    let input = input::flatten(parts, &dir, stamp)?;
+    run_configured_turn(host, config, input)
+}
+
+/// Re-runs a recorded turn against the recorded LLM and component
+/// responses, with the settings it was recorded with rather than
+/// this environment's. Usage is not tracked and the replay is not
+/// recorded.
+fn replay_turn(
+    config: &Config,
+    recording: &Recording,
+) -> (Result<ConverseResponse, AgentError>, ReplayReport) {
+    let settings = &recording.settings;
+    let config = Config {
+        model: settings.model.clone(),
+        system_prompt: settings.system_prompt.clone(),
+        max_tool_rounds: settings.max_tool_rounds,
+        max_prompt_chars: settings.max_prompt_chars,
+        max_prompt_user_messages: settings.max_prompt_user_messages,
+        session: settings.session.clone(),
+        usage_path: None,
+        record_dir: None,
+        replay_path: None,
+        progress: None,
+        ..config.clone()
+    };
+    let replayer = Replayer::new(recording);
+    // The recorded turn id, which core passed to the components.
+    let turn = config.events.turn(&recording.turn_id);
+    let response = run_traced_turn(&replayer, &config, &turn, recording.input.clone());
+    let report = ReplayReport {
+        turn_id: recording.turn_id.clone(),
+        recorded: recording.outcome.clone(),
+        replayed: response
+            .as_ref()
+            .map(|r| r.reply.clone())
+            .map_err(AgentError::clone),
+        divergences: replayer.finish(),
+    };
+    (response, report)
+}
+
+fn run_turn(
+    host: &dyn Host,
+    config: &Config,
+    input: String,
+) -> Result<ConverseResponse, AgentError> {
+    let turn = config.events.start_turn();
+    let Some(dir) = &config.record_dir else {
+        return run_traced_turn(host, config, &turn, input);
+    };
+    let recorder = Recorder::new(host);
+    let result = run_traced_turn(&recorder, config, &turn, input.clone());
+    let outcome = result
+        .as_ref()
+        .map(|r| r.reply.clone())
+        .map_err(AgentError::clone);
+    recorder
+        .finish(&turn.id, input, config.settings(), outcome)
+        .save(&format!("{dir}/{}.json", turn.id));
+    result
+}
+
+fn run_traced_turn(
+    host: &dyn Host,
+    config: &Config,
+    turn: &Turn,
+    input: String,
+) -> Result<ConverseResponse, AgentError> {
+    turn.emit(
+        "turn.started",
+        serde_json::json!({ "model": config.model, "input": turn.content(&input) }),
+    );
+    let ledger = config
+        .usage_path
+        .as_deref()
+        .map(Ledger::load)
+        .unwrap_or_default();
+    let selected = config
+        .budget
+        .select_model(&config.model, &ledger, &config.prices, &config.day);
+    let model = match selected {
+        Ok(model) => model,
+        Err(e) => {
+            emit_error(turn, &e);
+            return Err(e);
+        }
+    };
+    let mut usage = Usage::default();
+    let mut tool_invocations = Vec::new();
+    let result = run_loop(
+        host,
+        config,
+        turn,
+        &model,
+        input,
+        &mut usage,
+        &mut tool_invocations,
+    );
+    usage.cost_usd = config
+        .prices
+        .cost(&model, usage.input_tokens, usage.output_tokens);
+    // Usage is recorded even when the turn fails part-way. The
+    // ledger is read again first: history may have recorded a
+    // compaction since it was loaded.
+    if let Some(path) = &config.usage_path {
+        Ledger::record_at(
+            path,
+            &config.day,
+            &config.session,
+            &model,
+            ModelUsage {
+                calls: usage.llm_calls as u64,
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            },
+        );
+    }
+    let (reply, stop_reason) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            emit_error(turn, &e);
+            return Err(e);
+        }
+    };
+    turn.emit(
+        "turn.finished",
+        serde_json::json!({
+            "durationMs": turn.elapsed_ms(),
+            "model": model,
+            "stopReason": match stop_reason {
+                StopReason::Completed => "completed",
+                StopReason::MaxToolRounds => "max-tool-rounds",
+            },
+            "rounds": usage.rounds,
+            "llmCalls": usage.llm_calls,
+            "inputTokens": usage.input_tokens,
+            "outputTokens": usage.output_tokens,
+            "costUsd": usage.cost_usd,
+            "reply": turn.content(&reply),
+        }),
+    );
+    let rich = match stop_reason {
+        StopReason::Completed => reply::parse(&reply, &config.host_dir),
+        StopReason::MaxToolRounds => RichReply {
+            text: reply,
+            ..RichReply::default()
+        },
+    };
+    Ok(ConverseResponse {
+        reply: rich.text,
+        attachments: rich.attachments,
+        buttons: rich.buttons,
+        location: rich.location,
+        tool_invocations,
+        usage,
+        model,
+        stop_reason,
+        turn_id: turn.id.clone(),
+    })
+}
+
+fn emit_error(turn: &Turn, error: &AgentError) {
+    turn.emit(
+        "error",
+        serde_json::json!({
+            "durationMs": turn.elapsed_ms(),
+            "kind": error.kind,
+            "message": error.message,
+        }),
+    );
+}
+
+fn run_loop(
+    host: &dyn Host,
+    config: &Config,
+    turn: &Turn,
+    model: &str,
+    input: String,
+    usage: &mut Usage,
+    tool_invocations: &mut Vec<ToolInvocation>,
+) -> Result<(String, StopReason), AgentError> {
+    let mut history = load_history(host, &config.session);
+    // TODO: Run compaction asynchronously after response
+    // delivery to avoid blocking the user. Consider
+    // asterai:host-cron for deferred execution.
+    if should_compact_history(host, history.len()) {
+        let started = Instant::now();
+        let before = history.len();
+        history = compact_history(host, &config.session, &turn.id, history);
+        turn.emit(
+            "compaction",
+            serde_json::json!({
+                "durationMs": started.elapsed().as_millis() as u64,
+                "messagesBefore": before,
+                "messagesAfter": history.len(),
+            }),
+        );
+    }
+    let system_message = build_system_message(host, config, model, &input);
+    let tools = get_tool_entries(host);
+    history.push(ChatMessage {
+        role: ChatRole::User,
+        content: input,
+        tool_calls: Vec::new(),
+        tool_call_id: None,
+    });
+    let tool_defs: Vec<ToolDefinition> =
+        tools.iter().map(|t| t.definition.clone()).collect();
+    let progress = Reporter::new(
+        host,
+        config.progress.as_ref(),
+        &turn.id,
+        config.progress_interval_ms,
+    );
+    let mut rounds_remaining = config.max_tool_rounds;
+    loop {
+        if usage.rounds > 0 {
+            progress.report("Thinking…", None);
+        }
+        let mut messages = vec![system_message.clone()];
+        messages.extend(trim_history(&history, config).iter().cloned());
+        let prompt_chars: usize = messages.iter().map(|m| m.content.len()).sum();
+        turn.emit(
+            "llm.request",
+            serde_json::json!({
+                "model": model,
+                "messages": messages.len(),
+                "tools": tool_defs.len(),
+                "promptChars": prompt_chars,
+            }),
+        );
+        let started = Instant::now();
+        let response = match host.chat(&messages, &tool_defs, model) {
+            Ok(r) => r,
+            Err(e) => {
+                save_history(host, &config.session, &history);
+                return Err(e);
             }
+        };
+        turn.emit(
+            "llm.response",
+            serde_json::json!({
+                "durationMs": started.elapsed().as_millis() as u64,
+                "content": turn.content(&response.content),
+                "toolCalls": response.tool_calls.iter().map(|tc| &tc.name).collect::<Vec<_>>(),
+            }),
+        );
+        usage.record_chat(&messages, &tool_defs, &response);
+        if response.tool_calls.is_empty() {
             history.push(ChatMessage {
                 role: ChatRole::Assistant,
                 content: response.content.clone(),
-                tool_calls: response.tool_calls.clone(),
+                tool_calls: Vec::new(),
                 tool_call_id: None,
             });
-            for tc in &response.tool_calls {
-                let (component, function) = match resolve_tool_name(&tc.name, &tools) {
-                    Some(cf) => cf,
-                    None => {
-                        history.push(ChatMessage {
-                            role: ChatRole::Tool,
-                            content: format!("error: unknown tool '{}'", tc.name),
-                            tool_calls: Vec::new(),
-                            tool_call_id: Some(tc.id.clone()),
-                        });
-                        continue;
-                    }
-                };
-                let result = call_tool(&component, &function, &tc.arguments_json);
-                let truncated = truncate_result(&result);
-                history.push(ChatMessage {
-                    role: ChatRole::Tool,
-                    content: truncated,
-                    tool_calls: Vec::new(),
-                    tool_call_id: Some(tc.id.clone()),
-                });
-            }
-            rounds_remaining -= 1;
-            if rounds_remaining >= 1 && rounds_remaining <= 2 {
-                let note = match rounds_remaining {
-                    1 => "\n\n[System: final tool round. \
-                    Provide your response to the user now.]".to_owned(),
-                    x => format!("\n\n[System: {x} tool rounds remaining. \
-                    Begin wrapping up.]"),
-                };
-                if let Some(last) = history.last_mut() {
-                    last.content.push_str(&note);
+            save_history(host, &config.session, &history);
+            return Ok((response.content, StopReason::Completed));
+        }
+        history.push(ChatMessage {
+            role: ChatRole::Assistant,
+            content: response.content.clone(),
+            tool_calls: response.tool_calls.clone(),
+            tool_call_id: None,
+        });
+        for tc in &response.tool_calls {
+            turn.emit(
+                "tool.started",
+                serde_json::json!({
+                    "tool": tc.name,
+                    "callId": tc.id,
+                    "args": turn.content(&tc.arguments_json),
+                }),
+            );
+            let (component, function) = match resolve_tool_name(&tc.name, &tools) {
+                Some(cf) => cf,
+                None => {
+                    let error =
+                        AgentError::new(ErrorKind::Tool, format!("unknown tool '{}'", tc.name));
+                    let content = error.to_string();
+                    emit_tool_finished(turn, tc, 0, true, &content);
+                    tool_invocations.push(ToolInvocation::new(
+                        &tc.name,
+                        &tc.arguments_json,
+                        &content,
+                        0,
+                        true,
+                    ));
+                    history.push(ChatMessage {
+                        role: ChatRole::Tool,
+                        content,
+                        tool_calls: Vec::new(),
+                        tool_call_id: Some(tc.id.clone()),
+                    });
+                    continue;
                 }
-            }
-            if rounds_remaining == 0 {
-                let msg = "max tool rounds reached".to_string();
-                history.push(ChatMessage {
-                    role: ChatRole::Assistant,
-                    content: msg.clone(),
-                    tool_calls: Vec::new(),
-                    tool_call_id: None,
-                });
-                save_history(&history);
-                return msg;
+            };
+            progress.report(
+                &progress::tool_status(&component, &function),
+                Some(&tc.name),
+            );
+            let started = Instant::now();
+            let result = call_tool(
+                host,
+                &turn.id,
+                &config.session,
+                &component,
+                &function,
+                &tc.arguments_json,
+            );
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let is_error = result.is_err();
+            let content = match result {
+                Ok(result) => truncate_result(&result),
+                // Failures are fed back so the LLM can try another approach.
+                Err(e) => e.to_string(),
+            };
+            emit_tool_finished(turn, tc, duration_ms, is_error, &content);
+            tool_invocations.push(ToolInvocation::new(
+                &tc.name,
+                &tc.arguments_json,
+                &content,
+                duration_ms,
+                is_error,
+            ));
+            history.push(ChatMessage {
+                role: ChatRole::Tool,
+                content,
+                tool_calls: Vec::new(),
+                tool_call_id: Some(tc.id.clone()),
+            });
+        }
+        rounds_remaining -= 1;
+        usage.rounds += 1;
+        if rounds_remaining >= 1 && rounds_remaining <= 2 {
+            let note = match rounds_remaining {
+                1 => "\n\n[System: final tool round. \
+                Provide your response to the user now.]".to_owned(),
+                x => format!("\n\n[System: {x} tool rounds remaining. \
+                Begin wrapping up.]"),
+            };
+            if let Some(last) = history.last_mut() {
+                last.content.push_str(&note);
             }
         }
+        if rounds_remaining == 0 {
+            let msg = "max tool rounds reached".to_string();
+            history.push(ChatMessage {
+                role: ChatRole::Assistant,
+                content: msg.clone(),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            });
+            save_history(host, &config.session, &history);
+            return Ok((msg, StopReason::MaxToolRounds));
+        }
     }
 }
 
-fn build_system_message(host_dir: &str, input: &str) -> ChatMessage {
-    let mut content = resolve_system_prompt(host_dir);
-    let model = std::env::var("ASTERBOT_MODEL").unwrap_or_default();
-    let soul = fetch_soul();
-    let memory_names = list_component_files("asterbot:memory", "memory/list-all");
-    let skill_names = list_component_files("asterbot:skills", "skills/list-all");
+fn emit_tool_finished(turn: &Turn, tc: &ToolCall, duration_ms: u64, is_error: bool, result: &str) {
+    turn.emit(
+        "tool.finished",
+        serde_json::json!({
+            "tool": tc.name,
+            "callId": tc.id,
+            "durationMs": duration_ms,
+            "isError": is_error,
+            "result": turn.content(result),
+        }),
+    );
+}
+
+fn build_system_message(host: &dyn Host, config: &Config, model: &str, input: &str) -> ChatMessage {
+    let mut content = config.system_prompt.clone();
+    let soul = fetch_soul(host);
+    let memory_names = list_component_files(host, "asterbot:memory", "memory/list-all");
+    let skill_names = list_component_files(host, "asterbot:skills", "skills/list-all");
 
     // Context awareness.
     content.push_str("\n\n## Context\n");
@@ -265,7 +730,7 @@ fn build_system_message(host_dir: &str, input: &str) -> ChatMessage {
         }
     }
     // History context (user profile, conversation summary, bond).
-    let history_context = get_history_context();
+    let history_context = get_history_context(host, &config.session);
     if !history_context.is_empty() {
         content.push_str("\n\n");
         content.push_str(&history_context);
@@ -280,6 +745,10 @@ fn build_system_message(host_dir: &str, input: &str) -> ChatMessage {
         retrying the same thing.",
     );
 
+    // Rich replies.
+    content.push_str("\n\n## Replies\n");
+    content.push_str(reply::GUIDANCE);
+
     // Memory (mandatory).
     if let Some(names) = &memory_names {
         content.push_str(
@@ -336,9 +805,9 @@ fn build_system_message(host_dir: &str, input: &str) -> ChatMessage {
     }
 }
 
-fn get_tool_entries() -> Vec<ToolEntry> {
+fn get_tool_entries(host: &dyn Host) -> Vec<ToolEntry> {
     let tools_json =
-        match api::call_component_function("asterbot:toolkit", "toolkit/list-tools", "[]") {
+        match host.call_component_function("asterbot:toolkit", "toolkit/list-tools", "[]") {
             Ok(r) => r,
             Err(_) => return Vec::new(),
         };
@@ -433,16 +902,10 @@ fn truncate_result(result: &str) -> String {
     }
 }
 
-fn trim_history(history: &[ChatMessage]) -> &[ChatMessage] {
-    let max_chars = std::env::var("ASTERBOT_MAX_PROMPT_CHARS")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(DEFAULT_MAX_PROMPT_CHARS);
-    let max_user_messages_opt: Option<usize> = std::env::var("ASTERBOT_MAX_PROMPT_USER_MESSAGES")
-        .ok()
-        .and_then(|v| v.parse().ok());
+fn trim_history<'a>(history: &'a [ChatMessage], config: &Config) -> &'a [ChatMessage] {
+    let max_chars = config.max_prompt_chars;
     let mut start = 0;
-    if let Some(max) = max_user_messages_opt {
+    if let Some(max) = config.max_prompt_user_messages {
         let mut user_count = 0;
         for (i, msg) in history.iter().enumerate().rev() {
             if matches!(msg.role, ChatRole::User) {
@@ -465,15 +928,16 @@ fn trim_history(history: &[ChatMessage]) -> &[ChatMessage] {
     &history[start..]
 }
 
-fn get_history_context() -> String {
-    match api::call_component_function("asterbot:history", "history/get-context", "[]") {
+fn get_history_context(host: &dyn Host, session: &str) -> String {
+    let function = ("history/get-context", "history/get-session-context");
+    match call_history(host, session, function, &[]) {
         Ok(result) => decode_json_string(&result),
         Err(_) => String::new(),
     }
 }
 
-fn fetch_soul() -> Option<String> {
-    match api::call_component_function("asterbot:soul", "soul/get", "[]") {
+fn fetch_soul(host: &dyn Host) -> Option<String> {
+    match host.call_component_function("asterbot:soul", "soul/get", "[]") {
         Ok(result) => {
             let content = decode_json_string(&result);
             Some(content.trim().to_string())
@@ -483,8 +947,8 @@ fn fetch_soul() -> Option<String> {
 }
 
 /// Returns Some(names) if the component is available, None if not.
-fn list_component_files(component: &str, list_fn: &str) -> Option<Vec<String>> {
-    match api::call_component_function(component, list_fn, "[]") {
+fn list_component_files(host: &dyn Host, component: &str, list_fn: &str) -> Option<Vec<String>> {
+    match host.call_component_function(component, list_fn, "[]") {
         Ok(result) => Some(serde_json::from_str(&result).unwrap_or_default()),
         Err(_) => None,
     }
@@ -519,7 +983,8 @@ fn suggest_from_names(names: &[String], input: &str) -> Vec<String> {
         .collect()
 }
 
-fn resolve_host_dir() -> Result<String, String> {
+#[cfg(not(test))]
+fn resolve_host_dir() -> Result<String, AgentError> {
     if let Ok(v) = std::env::var("ASTERBOT_HOST_DIR") {
         if !v.is_empty() {
             return Ok(v);
@@ -532,12 +997,26 @@ fn resolve_host_dir() -> Result<String, String> {
             }
         }
     }
-    Err(
-        "error: no state directory available — pass --allow-dir to grant filesystem access"
-            .to_string(),
-    )
+    Err(AgentError::new(
+        ErrorKind::Config,
+        "no state directory available — pass --allow-dir to grant filesystem access",
+    ))
 }
 
+/// `MODEL.txt` in the host dir (written by the gateways'
+/// `/model` command) overrides `ASTERBOT_MODEL`.
+#[cfg(not(test))]
+fn resolve_model(host_dir: &str) -> String {
+    let path = format!("{host_dir}/MODEL.txt");
+    if let Ok(contents) = std::fs::read_to_string(&path) {
+        if !contents.trim().is_empty() {
+            return contents.trim().to_string();
+        }
+    }
+    std::env::var("ASTERBOT_MODEL").unwrap_or_default()
+}
+
+#[cfg(not(test))]
 fn resolve_system_prompt(host_dir: &str) -> String {
     let path = format!("{host_dir}/SYSTEM_PROMPT.md");
     if let Ok(contents) = std::fs::read_to_string(&path) {
@@ -552,19 +1031,60 @@ fn decode_json_string(json: &str) -> String {
     serde_json::from_str::<String>(json).unwrap_or_else(|_| json.to_string())
 }
 
-fn call_tool(component: &str, function: &str, args: &str) -> String {
-    let component_json = serde_json::to_string(component).unwrap_or_default();
-    let function_json = serde_json::to_string(function).unwrap_or_default();
-    let args_json = serde_json::to_string(args).unwrap_or_default();
-    let call_args = format!("[{component_json}, {function_json}, {args_json}]");
-    match api::call_component_function("asterbot:toolkit", "toolkit/call-tool", &call_args) {
-        Ok(result) => decode_json_string(&result),
-        Err(e) => format!("error: tool call failed: {:?}: {}", e.kind, e.message),
+/// Calls a tool through the toolkit, which passes the turn's
+/// session to tools that take one, e.g. asterbot:scheduler.
+fn call_tool(
+    host: &dyn Host,
+    turn_id: &str,
+    session: &str,
+    component: &str,
+    function: &str,
+    args: &str,
+) -> Result<String, AgentError> {
+    let call_args = serde_json::json!([turn_id, session, component, function, args]).to_string();
+    let result = host
+        .call_component_function(
+            "asterbot:toolkit",
+            "toolkit/try-call-tool-in-turn",
+            &call_args,
+        )
+        .map_err(|e| {
+            AgentError::new(
+                ErrorKind::Tool,
+                format!("tool call failed: {}: {}", e.kind, e.message),
+            )
+        })?;
+    match serde_json::from_str::<WitResult<String>>(&result) {
+        Ok(r) => r.into(),
+        Err(e) => Err(AgentError::new(
+            ErrorKind::Tool,
+            format!("tool call returned an unexpected result: {e}"),
+        )),
     }
 }
 
-fn load_history() -> Vec<ChatMessage> {
-    match api::call_component_function("asterbot:history", "history/load", "[]") {
+/// Calls the first of `function`'s names for the default
+/// conversation, and the second, with the session before `args`,
+/// for a session's.
+fn call_history(
+    host: &dyn Host,
+    session: &str,
+    function: (&str, &str),
+    args: &[String],
+) -> Result<String, HostError> {
+    let (function, args) = match session.is_empty() {
+        true => (function.0, args.to_vec()),
+        false => {
+            let session = serde_json::to_string(session).unwrap_or_default();
+            (function.1, [&[session], args].concat())
+        }
+    };
+    let args = format!("[{}]", args.join(","));
+    host.call_component_function("asterbot:history", function, &args)
+}
+
+fn load_history(host: &dyn Host, session: &str) -> Vec<ChatMessage> {
+    match call_history(host, session, ("history/load", "history/load-session"), &[]) {
         Ok(json) => {
             let msgs: Vec<WitChatMessage> =
                 serde_json::from_str(&json).unwrap_or_else(|e| {
@@ -574,15 +1094,15 @@ fn load_history() -> Vec<ChatMessage> {
             msgs.iter().map(|m| m.to_chat_message()).collect()
         }
         Err(e) => {
-            eprintln!("error: failed to load history: {:?}: {}", e.kind, e.message);
+            eprintln!("error: failed to load history: {}: {}", e.kind, e.message);
             Vec::new()
         }
     }
 }
 
-fn should_compact_history(count: usize) -> bool {
+fn should_compact_history(host: &dyn Host, count: usize) -> bool {
     let args = format!("[{}]", count);
-    match api::call_component_function(
+    match host.call_component_function(
         "asterbot:history",
         "history/should-compact",
         &args,
@@ -592,18 +1112,22 @@ fn should_compact_history(count: usize) -> bool {
     }
 }
 
-fn compact_history(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+fn compact_history(
+    host: &dyn Host,
+    session: &str,
+    turn_id: &str,
+    messages: Vec<ChatMessage>,
+) -> Vec<ChatMessage> {
     let wit_msgs: Vec<WitChatMessage> = messages
         .iter()
         .map(WitChatMessage::from_chat_message)
         .collect();
-    let json = serde_json::to_string(&wit_msgs).unwrap_or_default();
-    let args = format!("[{json}]");
-    match api::call_component_function(
-        "asterbot:history",
-        "history/compact",
-        &args,
-    ) {
+    let args = [
+        serde_json::to_string(turn_id).unwrap_or_default(),
+        serde_json::to_string(&wit_msgs).unwrap_or_default(),
+    ];
+    let function = ("history/compact-in-turn", "history/compact-session");
+    match call_history(host, session, function, &args) {
         Ok(result) => {
             let msgs: Vec<WitChatMessage> =
                 serde_json::from_str(&result).unwrap_or_default();
@@ -611,7 +1135,7 @@ fn compact_history(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
         }
         Err(e) => {
             eprintln!(
-                "error: compaction failed: {:?}: {}",
+                "error: compaction failed: {}: {}",
                 e.kind, e.message,
             );
             messages
@@ -619,14 +1143,917 @@ fn compact_history(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
     }
 }
 
-fn save_history(history: &[ChatMessage]) {
+fn save_history(host: &dyn Host, session: &str, history: &[ChatMessage]) {
     let msgs: Vec<WitChatMessage> =
         history.iter().map(WitChatMessage::from_chat_message).collect();
     let json = serde_json::to_string(&msgs).unwrap_or_default();
-    let args = format!("[{json}]");
-    if let Err(e) = api::call_component_function("asterbot:history", "history/save", &args) {
-        eprintln!("error: failed to save history: {:?}: {}", e.kind, e.message);
+    let function = ("history/save", "history/save-session");
+    if let Err(e) = call_history(host, session, function, &[json]) {
+        eprintln!("error: failed to save history: {}: {}", e.kind, e.message);
     }
 }
 
+#[cfg(not(test))]
 bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_host::FakeHost;
+    use asterbot_common::events::ContentMode;
+
+    const MEMORY_GET: &str = "asterbot-memory--memory-get";
+
+    fn config() -> Config {
+        Config {
+            model: "test/model".to_string(),
+            max_tool_rounds: DEFAULT_MAX_TOOL_ROUNDS,
+            max_prompt_chars: DEFAULT_MAX_PROMPT_CHARS,
+            max_prompt_user_messages: None,
+            system_prompt: "You are a test assistant.".to_string(),
+            prices: Prices::parse(""),
+            budget: Budget::default(),
+            usage_path: None,
+            day: "2026-03-14".to_string(),
+            events: EventLog::default(),
+            record_dir: None,
+            replay_path: None,
+            stub_path: None,
+            host_dir: std::env::temp_dir()
+                .join(format!("asterbot-core-host-{}", std::process::id()))
+                .to_str()
+                .unwrap()
+                .to_string(),
+            progress: None,
+            session: String::new(),
+            progress_interval_ms: 0,
+        }
+    }
+
+    /// Runs a turn and returns just the reply.
+    fn converse(host: &dyn Host, config: &Config, input: String) -> Result<String, AgentError> {
+        run_turn(host, config, input).map(|response| response.reply)
+    }
+
+    fn message(role: ChatRole, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    fn memory_tool(host: FakeHost) -> FakeHost {
+        host.tool(
+            "asterbot:memory",
+            "memory/get",
+            &[("name", "string")],
+            |args| Ok(format!("memory for {args}")),
+        )
+    }
+
+    #[test]
+    fn plain_reply_is_returned_and_saved() {
+        let host = FakeHost::new().history(vec![]).reply("hello there");
+        let reply = converse(&host, &config(), "hi".to_string()).unwrap();
+        assert_eq!(reply, "hello there");
+        let saved = host.saved();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0], message(ChatRole::User, "hi"));
+        assert_eq!(saved[1], message(ChatRole::Assistant, "hello there"));
+    }
+
+    #[test]
+    fn prompt_starts_with_system_message_then_history() {
+        let host = FakeHost::new()
+            .history(vec![
+                message(ChatRole::User, "earlier"),
+                message(ChatRole::Assistant, "reply"),
+            ])
+            .reply("ok");
+        converse(&host, &config(), "now".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        let prompt = &requests[0];
+        assert_eq!(prompt.len(), 4);
+        assert_eq!(prompt[0].role, ChatRole::System);
+        assert!(prompt[0].content.starts_with("You are a test assistant."));
+        assert!(prompt[0].content.contains("Model: test/model"));
+        assert_eq!(prompt[1].content, "earlier");
+        assert_eq!(prompt[3].content, "now");
+    }
+
+    #[test]
+    fn sessions_keep_their_own_history() {
+        let host = memory_tool(FakeHost::new())
+            .history(vec![message(ChatRole::User, "in the default conversation")])
+            .call("call-1", MEMORY_GET, r#"{"name":"prefs"}"#)
+            .reply("first")
+            .reply("second");
+        let config = Config {
+            session: "telegram:group:-100123".to_string(),
+            ..config()
+        };
+        converse(&host, &config, "hi".to_string()).unwrap();
+        converse(&host, &config, "again".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        assert_eq!(requests[0].len(), 2);
+        assert_eq!(requests[2][1], message(ChatRole::User, "hi"));
+        assert_eq!(host.saved_in("telegram:group:-100123").len(), 6);
+        // Tools are told the session, e.g. to schedule into it.
+        let calls = host.calls.borrow();
+        let (_, _, args) = calls
+            .iter()
+            .find(|(_, f, _)| f == "toolkit/try-call-tool-in-turn")
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(args).unwrap()[1],
+            "telegram:group:-100123"
+        );
+        assert_eq!(
+            host.saved(),
+            [message(ChatRole::User, "in the default conversation")]
+        );
+        assert_eq!(host.call_count("asterbot:history", "history/load"), 0);
+    }
+
+    #[test]
+    fn attachments_are_described_in_the_user_message() {
+        let host = FakeHost::new().history(vec![]).reply("a cat");
+        let config = config();
+        let parts = vec![
+            InputPart::Text("what is this?".to_string()),
+            InputPart::Image(input::Media {
+                mime_type: "image/png".to_string(),
+                name: "cat.png".to_string(),
+                data: input::MediaData::Bytes(vec![1, 2, 3]),
+            }),
+        ];
+        let response = converse_parts(&host, &config, &parts, "7").unwrap();
+        assert_eq!(response.reply, "a cat");
+        let path = format!("{}/attachments/7-2-cat.png", config.host_dir);
+        let expected = format!("what is this?\n[Attached image: {path} (image/png, 3 B)]");
+        assert_eq!(host.requests.borrow()[0][1].content, expected);
+        assert_eq!(host.saved()[0].content, expected);
+        assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3]);
+        std::fs::remove_dir_all(&config.host_dir).unwrap();
+    }
+
+    #[test]
+    fn reply_directives_become_attachments_and_buttons() {
+        let host_dir = event_dir("rich-reply");
+        std::fs::create_dir_all(host_dir.join(reply::OUTBOX_DIR)).unwrap();
+        std::fs::write(host_dir.join("outbox/plan.md"), "# Plan").unwrap();
+        let config = Config {
+            host_dir: host_dir.to_str().unwrap().to_string(),
+            ..config()
+        };
+        let raw = "Here's the plan.\n[attach: outbox/plan.md]\n[buttons: Book it | Not now]";
+        let host = FakeHost::new().history(vec![]).reply(raw);
+        let response = run_turn(&host, &config, "plan my trip".to_string()).unwrap();
+        assert_eq!(response.reply, "Here's the plan.");
+        assert_eq!(response.attachments[0].name, "plan.md");
+        assert_eq!(response.attachments[0].mime_type, "text/markdown");
+        assert_eq!(response.buttons, ["Book it", "Not now"]);
+        assert_eq!(host.saved()[1].content, raw);
+    }
+
+    #[test]
+    fn tool_round_feeds_result_back_to_llm() {
+        let host = memory_tool(FakeHost::new().history(vec![]))
+            .call("call-1", MEMORY_GET, r#"{"name":"prefs"}"#)
+            .reply("you like rust");
+        let reply = converse(&host, &config(), "what do I like?".to_string()).unwrap();
+        assert_eq!(reply, "you like rust");
+        assert_eq!(
+            host.call_count("asterbot:toolkit", "toolkit/try-call-tool-in-turn"),
+            1
+        );
+        let requests = host.requests.borrow();
+        assert_eq!(requests.len(), 2);
+        let tool_msg = requests[1].last().unwrap();
+        assert_eq!(tool_msg.role, ChatRole::Tool);
+        assert_eq!(tool_msg.tool_call_id.as_deref(), Some("call-1"));
+        assert_eq!(tool_msg.content, r#"memory for {"name":"prefs"}"#);
+        let saved = host.saved();
+        let roles: Vec<ChatRole> = saved.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            [
+                ChatRole::User,
+                ChatRole::Assistant,
+                ChatRole::Tool,
+                ChatRole::Assistant
+            ],
+        );
+    }
+
+    #[test]
+    fn progress_is_reported_to_the_target() {
+        let host = memory_tool(FakeHost::new().history(vec![]))
+            .call("call-1", MEMORY_GET, r#"{"name":"prefs"}"#)
+            .reply("you like rust");
+        let config = Config {
+            progress: Some(ProgressTarget {
+                component: "asterbot:test-gateway".to_string(),
+                context: "chat-1".to_string(),
+            }),
+            ..config()
+        };
+        run_turn(&host, &config, "what do I like?".to_string()).unwrap();
+        let reports: Vec<Value> = host
+            .calls
+            .borrow()
+            .iter()
+            .filter(|(component, function, _)| {
+                component == "asterbot:test-gateway" && function == "progress-listener/on-progress"
+            })
+            .map(|(_, _, args)| serde_json::from_str(args).unwrap())
+            .collect();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0][0], "chat-1");
+        assert_eq!(reports[0][1]["status"], "Using memory (get)…");
+        assert_eq!(reports[0][1]["tool"], MEMORY_GET);
+        assert_eq!(reports[1][1]["status"], "Thinking…");
+        assert_eq!(reports[1][1]["tool"], Value::Null);
+    }
+
+    #[test]
+    fn unknown_tool_reports_error_to_llm() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .call("call-1", "nope--nope", "{}")
+            .reply("sorry");
+        let reply = converse(&host, &config(), "do it".to_string()).unwrap();
+        assert_eq!(reply, "sorry");
+        assert_eq!(
+            host.call_count("asterbot:toolkit", "toolkit/try-call-tool-in-turn"),
+            0
+        );
+        let requests = host.requests.borrow();
+        let tool_msg = requests[1].last().unwrap();
+        assert_eq!(tool_msg.content, "error: unknown tool 'nope--nope'");
+    }
+
+    #[test]
+    fn long_tool_results_are_truncated() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .tool("asterai:big", "big/get", &[], |_| Ok("x".repeat(20_000)))
+            .call("call-1", "asterai-big--big-get", "{}")
+            .reply("done");
+        converse(&host, &config(), "go".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        let tool_msg = requests[1].last().unwrap();
+        assert!(tool_msg.content.ends_with("... (truncated)"));
+        assert_eq!(tool_msg.content.len(), TOOL_RESULT_TRUNCATE_CHARS + 15);
+    }
+
+    #[test]
+    fn round_limit_notes_and_max_rounds() {
+        let mut host = memory_tool(FakeHost::new().history(vec![]));
+        for i in 0..3 {
+            host = host.call(&format!("call-{i}"), MEMORY_GET, r#"{"name":"a"}"#);
+        }
+        let config = Config {
+            max_tool_rounds: 3,
+            ..config()
+        };
+        let response = run_turn(&host, &config, "loop".to_string()).unwrap();
+        assert_eq!(response.stop_reason, StopReason::MaxToolRounds);
+        assert_eq!(response.reply, "max tool rounds reached");
+        assert_eq!(host.remaining_responses(), 0);
+        let requests = host.requests.borrow();
+        assert_eq!(requests.len(), 3);
+        let after_first = requests[1].last().unwrap();
+        assert!(after_first
+            .content
+            .ends_with("[System: 2 tool rounds remaining. Begin wrapping up.]"));
+        let after_second = requests[2].last().unwrap();
+        assert!(after_second
+            .content
+            .ends_with("Provide your response to the user now.]"));
+        let saved = host.saved();
+        assert_eq!(saved.last().unwrap().content, "max tool rounds reached");
+    }
+
+    #[test]
+    fn llm_error_is_returned_and_history_saved() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .llm_error("provider unavailable");
+        let err = converse(&host, &config(), "hi".to_string()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Llm);
+        assert_eq!(err.to_string(), "error: provider unavailable");
+        let saved = host.saved();
+        assert_eq!(saved, vec![message(ChatRole::User, "hi")]);
+    }
+
+    #[test]
+    fn reply_starting_with_error_prefix_is_not_a_failure() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .reply("error: handling in Rust uses Result.");
+        let reply = converse(&host, &config(), "how do errors work?".to_string()).unwrap();
+        assert_eq!(reply, "error: handling in Rust uses Result.");
+    }
+
+    #[test]
+    fn tool_errors_are_fed_back_to_llm() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .tool("asterai:flaky", "flaky/run", &[], |_| {
+                Err(AgentError::new(
+                    ErrorKind::Tool,
+                    "flaky/run failed (Trap): boom",
+                ))
+            })
+            .call("call-1", "asterai-flaky--flaky-run", "{}")
+            .reply("it failed");
+        let reply = converse(&host, &config(), "run".to_string()).unwrap();
+        assert_eq!(reply, "it failed");
+        let requests = host.requests.borrow();
+        let tool_msg = requests[1].last().unwrap();
+        assert_eq!(tool_msg.content, "error: flaky/run failed (Trap): boom");
+    }
+
+    #[test]
+    fn detailed_response_traces_tool_calls() {
+        let host = memory_tool(FakeHost::new().history(vec![]))
+            .call("call-1", MEMORY_GET, r#"{"name":"prefs"}"#)
+            .call("call-2", "nope--nope", "{}")
+            .reply("done");
+        let response = run_turn(&host, &config(), "go".to_string()).unwrap();
+        assert_eq!(response.reply, "done");
+        assert_eq!(response.model, "test/model");
+        assert_eq!(response.stop_reason, StopReason::Completed);
+        assert_eq!(response.usage.rounds, 2);
+        assert_eq!(response.usage.llm_calls, 3);
+        assert!(response.usage.input_tokens > 0);
+        assert!(response.usage.output_tokens > 0);
+        let invocations = &response.tool_invocations;
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(invocations[0].name, MEMORY_GET);
+        assert_eq!(invocations[0].args_json, r#"{"name":"prefs"}"#);
+        assert_eq!(invocations[0].result, r#"memory for {"name":"prefs"}"#);
+        assert!(!invocations[0].is_error);
+        assert_eq!(invocations[1].result, "error: unknown tool 'nope--nope'");
+        assert!(invocations[1].is_error);
+    }
+
+    #[test]
+    fn detailed_response_reports_round_limit() {
+        let host = memory_tool(FakeHost::new().history(vec![])).call("call-1", MEMORY_GET, "{}");
+        let config = Config {
+            max_tool_rounds: 1,
+            ..config()
+        };
+        let response = run_turn(&host, &config, "go".to_string()).unwrap();
+        assert_eq!(response.stop_reason, StopReason::MaxToolRounds);
+        assert_eq!(response.reply, "max tool rounds reached");
+        assert_eq!(response.tool_invocations.len(), 1);
+    }
+
+    #[test]
+    fn trace_results_are_truncated() {
+        let host = FakeHost::new()
+            .history(vec![])
+            .tool("asterai:big", "big/get", &[], |_| Ok("é".repeat(1_000)))
+            .call("call-1", "asterai-big--big-get", "{}")
+            .reply("done");
+        let response = run_turn(&host, &config(), "go".to_string()).unwrap();
+        let result = &response.tool_invocations[0].result;
+        assert!(result.ends_with("..."));
+        assert!(result.len() <= 503);
+    }
+
+    #[test]
+    fn usage_is_persisted_and_priced() {
+        let dir = std::env::temp_dir().join(format!("asterbot-core-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir
+            .join(usage::USAGE_FILENAME)
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let config = Config {
+            prices: Prices::parse("test/model=1000000:1000000"),
+            usage_path: Some(path.clone()),
+            ..config()
+        };
+        let host = FakeHost::new().reply("one").reply("two");
+        let first = run_turn(&host, &config, "hi".to_string()).unwrap();
+        run_turn(&host, &config, "again".to_string()).unwrap();
+        let expected_cost = (first.usage.input_tokens + first.usage.output_tokens) as f64;
+        assert_eq!(first.usage.cost_usd, expected_cost);
+        let ledger = Ledger::load(&path);
+        let totals = ledger.days["2026-03-14"]["test/model"];
+        assert_eq!(totals.calls, 2);
+        assert!(totals.input_tokens >= 2 * first.usage.input_tokens);
+        assert_eq!(ledger.sessions[""]["test/model"], totals);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn usage_recorded_during_a_turn_is_kept() {
+        let dir =
+            std::env::temp_dir().join(format!("asterbot-core-usage-kept-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir
+            .join(usage::USAGE_FILENAME)
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let config = Config {
+            usage_path: Some(path.clone()),
+            session: "telegram:dm:42".to_string(),
+            ..config()
+        };
+        // Stands in for history recording a compaction mid-turn.
+        let compaction_path = path.clone();
+        let host = FakeHost::new()
+            .history(vec![])
+            .tool("asterai:compact", "compact/run", &[], move |_| {
+                let usage = ModelUsage {
+                    calls: 1,
+                    input_tokens: 7,
+                    output_tokens: 1,
+                };
+                Ledger::record_at(
+                    &compaction_path,
+                    "2026-03-14",
+                    "telegram:dm:42",
+                    "compact/model",
+                    usage,
+                );
+                Ok("done".to_string())
+            })
+            .call("call-1", "asterai-compact--compact-run", "{}")
+            .reply("ok");
+        run_turn(&host, &config, "go".to_string()).unwrap();
+        let ledger = Ledger::load(&path);
+        let day = &ledger.days["2026-03-14"];
+        assert_eq!(day["compact/model"].calls, 1);
+        assert_eq!(day["test/model"].calls, 2);
+        let session = &ledger.sessions["telegram:dm:42"];
+        assert_eq!(
+            session.keys().collect::<Vec<_>>(),
+            ["compact/model", "test/model"]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn read_events(
+        config: &Config,
+        dir: &std::path::Path,
+        input: &str,
+        host: &FakeHost,
+    ) -> Vec<Value> {
+        let _ = run_turn(host, config, input.to_string());
+        let log = std::fs::read_to_string(dir.join("events.jsonl")).unwrap();
+        log.lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    fn event_path(dir: &std::path::Path) -> Option<String> {
+        Some(dir.join("events.jsonl").to_str().unwrap().to_string())
+    }
+
+    fn event_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("asterbot-core-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn events_trace_the_turn_with_redacted_content() {
+        let dir = event_dir("events");
+        let config = Config {
+            events: EventLog::new(COMPONENT, event_path(&dir), ContentMode::Omit),
+            ..config()
+        };
+        let host = memory_tool(FakeHost::new())
+            .call("c1", MEMORY_GET, r#"{"name":"secret"}"#)
+            .reply("the secret");
+        let response = run_turn(&host, &config, "tell me the secret".to_string()).unwrap();
+        let events: Vec<Value> = std::fs::read_to_string(dir.join("events.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let names: Vec<&str> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "turn.started",
+                "llm.request",
+                "llm.response",
+                "tool.started",
+                "tool.finished",
+                "llm.request",
+                "llm.response",
+                "turn.finished",
+            ]
+        );
+        let turn_id = events[0]["turnId"].as_str().unwrap();
+        assert!(!turn_id.is_empty());
+        assert!(events.iter().all(|e| e["turnId"] == turn_id));
+        assert!(events.iter().all(|e| e["component"] == "asterbot:core"));
+        // The id goes back to the caller and on to the tool call.
+        assert_eq!(response.turn_id, turn_id);
+        let calls = host.calls.borrow();
+        let (_, _, args) = calls
+            .iter()
+            .find(|(_, f, _)| f == "toolkit/try-call-tool-in-turn")
+            .unwrap();
+        assert_eq!(serde_json::from_str::<Value>(args).unwrap()[0], turn_id);
+        assert_eq!(events[0]["input"], serde_json::json!({ "chars": 18 }));
+        assert_eq!(events[4]["tool"], MEMORY_GET);
+        assert_eq!(events[4]["isError"], false);
+        assert!(events[7]["durationMs"].is_u64());
+        assert_eq!(events[7]["stopReason"], "completed");
+        let log = std::fs::read_to_string(dir.join("events.jsonl")).unwrap();
+        assert!(!log.contains("secret"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn events_include_content_and_errors_when_configured() {
+        let dir = event_dir("events-full");
+        let config = Config {
+            events: EventLog::new(COMPONENT, event_path(&dir), ContentMode::Truncate),
+            ..config()
+        };
+        let long = "x".repeat(300);
+        let host = FakeHost::new().reply(&long).llm_error("provider down");
+        let events = read_events(&config, &dir, "hello", &host);
+        assert_eq!(events[0]["input"]["text"], "hello");
+        let reply = &events[2]["content"];
+        assert_eq!(reply["chars"], 300);
+        assert_eq!(reply["text"].as_str().unwrap().len(), 203);
+        let events = read_events(&config, &dir, "again", &host);
+        let last = events.last().unwrap();
+        assert_eq!(last["event"], "error");
+        assert_eq!(last["kind"], "llm");
+        assert_eq!(last["message"], "provider down");
+        assert_ne!(last["turnId"], events[0]["turnId"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn disabled_event_log_writes_nothing() {
+        let host = FakeHost::new().reply("hi");
+        let config = config();
+        let turn = config.events.start_turn();
+        turn.emit("turn.started", serde_json::json!({}));
+        assert_eq!(run_turn(&host, &config, "hi".into()).unwrap().reply, "hi");
+    }
+
+    /// Runs a turn with recording enabled and returns the recording.
+    fn record_turn(name: &str, host: &FakeHost, config: &Config, input: &str) -> Recording {
+        let dir = event_dir(name);
+        let config = Config {
+            record_dir: Some(dir.to_str().unwrap().to_string()),
+            ..config.clone()
+        };
+        let _ = run_turn(host, &config, input.to_string());
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let recording = Recording::load(file.path().to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        recording
+    }
+
+    #[test]
+    fn recorded_turn_replays_identically() {
+        let host = memory_tool(FakeHost::new())
+            .history(vec![message(ChatRole::User, "earlier")])
+            .call("c1", MEMORY_GET, r#"{"name":"notes"}"#)
+            .reply("Found your notes.");
+        let recording = record_turn("record", &host, &config(), "find my notes");
+        assert_eq!(recording.input, "find my notes");
+        assert_eq!(recording.outcome, Ok("Found your notes.".to_string()));
+        assert_eq!(recording.settings, config().settings());
+        let calls_before = host.calls.borrow().len();
+        // The recorded settings apply, not the replaying environment's.
+        let elsewhere = Config {
+            model: "other/model".to_string(),
+            system_prompt: "You are a different assistant.".to_string(),
+            max_tool_rounds: 1,
+            ..config()
+        };
+        let (response, report) = replay_turn(&elsewhere, &recording);
+        assert_eq!(response.unwrap().tool_invocations.len(), 1);
+        assert!(report.is_identical(), "{:?}", report.divergences);
+        // Nothing reaches the real host during a replay.
+        assert_eq!(host.calls.borrow().len(), calls_before);
+    }
+
+    #[test]
+    fn failed_turn_is_recorded_and_replayed() {
+        let host = FakeHost::new().llm_error("overloaded");
+        let recording = record_turn("record-error", &host, &config(), "hi");
+        assert_eq!(recording.outcome.as_ref().unwrap_err().kind, ErrorKind::Llm);
+        let (_, report) = replay_turn(&config(), &recording);
+        assert!(report.is_identical(), "{:?}", report.divergences);
+    }
+
+    #[test]
+    fn replay_reports_divergences() {
+        let host = memory_tool(FakeHost::new())
+            .call("c1", MEMORY_GET, r#"{"name":"notes"}"#)
+            .reply("Found your notes.");
+        let mut recording = record_turn("record-diverge", &host, &config(), "find my notes");
+        // As if the prompt the settings produce had changed since.
+        recording.settings.system_prompt = "You are a different assistant.".to_string();
+        recording.settings.max_tool_rounds = 1;
+        let (_, report) = replay_turn(&config(), &recording);
+        assert!(!report.is_identical());
+        assert_eq!(
+            report.divergences[0],
+            "llm request 1: messages differ from message 0 (2 sent, 2 recorded)"
+        );
+        // The round limit stops the turn before the second request.
+        assert!(report
+            .divergences
+            .contains(&"1 recorded LLM response(s) not used".to_string()));
+        assert_ne!(report.replayed, report.recorded);
+    }
+
+    #[test]
+    fn replay_fixtures_are_reproduced() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/replays");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let recording = Recording::load(path.to_str().unwrap()).unwrap();
+            let (_, report) = replay_turn(&config(), &recording);
+            assert!(
+                report.is_identical(),
+                "{}: {:?}",
+                path.display(),
+                report.divergences
+            );
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn replay_path_replays_instead_of_calling_the_host() {
+        let dir = event_dir("replay-file");
+        let fixture = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/replays/memory-lookup.json");
+        let path = dir.join("turn.json");
+        std::fs::copy(fixture, &path).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let config = Config {
+            replay_path: Some(path.clone()),
+            ..config()
+        };
+        let host = FakeHost::new();
+        let response = run_configured_turn(&host, &config, "ignored".to_string()).unwrap();
+        assert_eq!(
+            response.reply,
+            "Since you're in Lisbon, Porto is an easy weekend trip by train."
+        );
+        assert!(host.calls.borrow().is_empty());
+        // The recording's model and system prompt are used, though
+        // they differ from this config's.
+        assert_eq!(response.model, "anthropic/claude-sonnet-4-5");
+        let report: Value =
+            serde_json::from_str(&std::fs::read_to_string(format!("{path}.report.json")).unwrap())
+                .unwrap();
+        assert_eq!(report["turnId"], "0000018e3b1c2a40");
+        assert_eq!(report["recorded"], report["replayed"]);
+        assert_eq!(report["divergences"], serde_json::json!([]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stub_llm_serves_scripted_responses_in_order() {
+        let dir = event_dir("stub");
+        let path = dir.join("stub-llm.json").to_str().unwrap().to_string();
+        std::fs::write(
+            &path,
+            r#"[
+                {"toolCalls": [{"name": "asterbot-memory--memory-get", "argumentsJson": "{}"}]},
+                {"content": "From the script."}
+            ]"#,
+        )
+        .unwrap();
+        let config = Config {
+            model: stub::STUB_MODEL.to_string(),
+            stub_path: Some(path.clone()),
+            ..config()
+        };
+        // The fake has no scripted responses: every reply is the stub's.
+        let host = memory_tool(FakeHost::new());
+        let response = run_configured_turn(&host, &config, "hi".to_string()).unwrap();
+        assert_eq!(response.reply, "From the script.");
+        assert_eq!(response.tool_invocations[0].name, MEMORY_GET);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        let err = run_configured_turn(&host, &config, "again".to_string()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Llm);
+        assert!(err
+            .message
+            .starts_with("stub LLM has no scripted responses left"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exhausted_budget_refuses_or_downgrades() {
+        let dir = std::env::temp_dir().join(format!("asterbot-core-budget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir
+            .join(usage::USAGE_FILENAME)
+            .to_string_lossy()
+            .to_string();
+        let mut ledger = Ledger::default();
+        ledger.record(
+            "2026-03-14",
+            "",
+            "test/model",
+            ModelUsage {
+                calls: 1,
+                input_tokens: 1_000_000,
+                output_tokens: 0,
+            },
+        );
+        ledger.save(&path);
+        let config = Config {
+            prices: Prices::parse("test/model=10:10"),
+            budget: Budget {
+                daily_usd: Some(5.0),
+                ..Default::default()
+            },
+            usage_path: Some(path.clone()),
+            ..config()
+        };
+        let host = FakeHost::new();
+        let err = run_turn(&host, &config, "hi".to_string()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Limit);
+        assert!(host.requests.borrow().is_empty());
+        let config = Config {
+            budget: Budget {
+                daily_usd: Some(5.0),
+                fallback_model: Some("cheap/model".to_string()),
+                ..Default::default()
+            },
+            ..config
+        };
+        let host = FakeHost::new().reply("cheap reply");
+        let response = run_turn(&host, &config, "hi".to_string()).unwrap();
+        assert_eq!(response.model, "cheap/model");
+        let requests = host.requests.borrow();
+        assert!(requests[0][0].content.contains("Model: cheap/model"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wit_result_decodes_ok_and_err() {
+        let ok: Result<String, AgentError> =
+            serde_json::from_str::<WitResult<String>>(r#"{"ok":"fine"}"#)
+                .unwrap()
+                .into();
+        assert_eq!(ok, Ok("fine".to_string()));
+        let err: Result<String, AgentError> = serde_json::from_str::<WitResult<String>>(
+            r#"{"err":{"kind":"permission","message":"not allowed"}}"#,
+        )
+        .unwrap()
+        .into();
+        assert_eq!(
+            err,
+            Err(AgentError::new(ErrorKind::Permission, "not allowed"))
+        );
+    }
+
+    #[test]
+    fn compaction_runs_before_the_turn_when_requested() {
+        let history: Vec<ChatMessage> = (0..4)
+            .map(|i| message(ChatRole::User, &format!("old {i}")))
+            .collect();
+        let host = FakeHost::new()
+            .history(history)
+            .compact_at(4)
+            .reply("fresh");
+        converse(&host, &config(), "new".to_string()).unwrap();
+        assert_eq!(host.history.as_ref().unwrap().borrow().compactions, 1);
+        let requests = host.requests.borrow();
+        let prompt = &requests[0];
+        assert_eq!(prompt.len(), 2);
+        assert!(prompt[0]
+            .content
+            .contains("## Conversation so far\nCompacted."));
+        assert_eq!(host.saved().len(), 2);
+    }
+
+    #[test]
+    fn no_compaction_below_threshold() {
+        let host = FakeHost::new()
+            .history(vec![message(ChatRole::User, "old")])
+            .compact_at(4)
+            .reply("ok");
+        converse(&host, &config(), "new".to_string()).unwrap();
+        assert_eq!(host.call_count("asterbot:history", "history/compact"), 0);
+    }
+
+    #[test]
+    fn prompt_is_trimmed_to_max_user_messages() {
+        let history = vec![
+            message(ChatRole::User, "one"),
+            message(ChatRole::Assistant, "r1"),
+            message(ChatRole::User, "two"),
+            message(ChatRole::Assistant, "r2"),
+        ];
+        let host = FakeHost::new().history(history).reply("ok");
+        let config = Config {
+            max_prompt_user_messages: Some(2),
+            ..config()
+        };
+        converse(&host, &config, "three".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        let contents: Vec<&str> = requests[0][1..]
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        // The cut lands just after the oldest dropped user message.
+        assert_eq!(contents, ["r1", "two", "r2", "three"]);
+        // Trimming only affects the prompt, not what is persisted.
+        assert_eq!(host.saved().len(), 6);
+    }
+
+    #[test]
+    fn prompt_is_trimmed_to_max_chars() {
+        let history = vec![
+            message(ChatRole::User, "old"),
+            message(ChatRole::User, &"a".repeat(50)),
+            message(ChatRole::Assistant, &"b".repeat(50)),
+        ];
+        let host = FakeHost::new().history(history).reply("ok");
+        let config = Config {
+            max_prompt_chars: 60,
+            ..config()
+        };
+        converse(&host, &config, "c".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        let contents: Vec<String> = requests[0][1..].iter().map(|m| m.content.clone()).collect();
+        assert_eq!(contents, ["b".repeat(50), "c".to_string()]);
+    }
+
+    #[test]
+    fn missing_components_degrade_gracefully() {
+        // No history, toolkit, soul, memory or skills installed.
+        let host = FakeHost::new().reply("still works");
+        let reply = converse(&host, &config(), "hi".to_string()).unwrap();
+        assert_eq!(reply, "still works");
+        let requests = host.requests.borrow();
+        let system = &requests[0][0].content;
+        assert!(!system.contains("## Soul"));
+        assert!(!system.contains("## Memory"));
+        assert!(!system.contains("## Skills"));
+    }
+
+    #[test]
+    fn memory_suggestions_are_ranked_by_input() {
+        let host = FakeHost::new()
+            .function("asterbot:memory", "memory/list-all", |_| {
+                Ok(r#"["rust_prefs","shopping","travel_plans"]"#.to_string())
+            })
+            .reply("ok");
+        converse(&host, &config(), "my rust preferences".to_string()).unwrap();
+        let requests = host.requests.borrow();
+        let system = &requests[0][0].content;
+        assert!(system.contains("## Memory"));
+        assert!(system.contains("Memories that may be relevant:\n- rust_prefs\n"));
+    }
+
+    #[test]
+    fn tool_names_round_trip() {
+        assert_eq!(
+            encode_tool_name("asterbot:memory", "memory/get"),
+            "asterbot-memory--memory-get",
+        );
+    }
+
+    #[test]
+    fn params_schema_marks_options_optional() {
+        let params = vec![
+            ToolParamJson {
+                name: "name".to_string(),
+                type_name: "string".to_string(),
+            },
+            ToolParamJson {
+                name: "limit".to_string(),
+                type_name: "option<u32>".to_string(),
+            },
+        ];
+        let schema: Value = serde_json::from_str(&build_params_schema(&params)).unwrap();
+        assert_eq!(schema["properties"]["limit"]["type"], "integer");
+        assert_eq!(schema["required"], serde_json::json!(["name"]));
+    }
+}
@@ -0,0 +1,101 @@
+//! Progress reports for a turn in progress.
+//!
+//! Callers of `converse` can name a component exporting
+//! `progress-listener`; core calls it as the turn advances, so the
+//! REPL can show what the agent is doing. Reports are throttled to one per interval, and a
+//! failing listener never fails the turn.
+
+use crate::host::Host;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+#[cfg(not(test))]
+pub const DEFAULT_INTERVAL_MS: u64 = 4_000;
+
+/// Mirrors `asterbot:types/types.progress-target`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressTarget {
+    pub component: String,
+    pub context: String,
+}
+
+/// Reports progress to a turn's target, if it has one.
+pub struct Reporter<'a> {
+    host: &'a dyn Host,
+    target: Option<&'a ProgressTarget>,
+    turn_id: &'a str,
+    interval: Duration,
+    /// The turn's start counts as a report: the caller shows
+    /// progress itself when it starts the turn.
+    last: Cell<Instant>,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(
+        host: &'a dyn Host,
+        target: Option<&'a ProgressTarget>,
+        turn_id: &'a str,
+        interval_ms: u64,
+    ) -> Self {
+        Reporter {
+            host,
+            target,
+            turn_id,
+            interval: Duration::from_millis(interval_ms),
+            last: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Reports `status`, unless the last report was less than an
+    /// interval ago.
+    pub fn report(&self, status: &str, tool: Option<&str>) {
+        let Some(target) = self.target else {
+            return;
+        };
+        if self.last.get().elapsed() < self.interval {
+            return;
+        }
+        self.last.set(Instant::now());
+        let progress = serde_json::json!({
+            "turn-id": self.turn_id,
+            "status": status,
+            "tool": tool,
+        });
+        let args = serde_json::json!([target.context, progress]).to_string();
+        if let Err(e) = self.host.call_component_function(
+            &target.component,
+            "progress-listener/on-progress",
+            &args,
+        ) {
+            eprintln!(
+                "warning: progress report to {} failed: {}",
+                target.component, e.message
+            );
+        }
+    }
+}
+
+/// A status for a tool call, e.g. "Using firecrawl (scrape)…" for
+/// `asterai:firecrawl` and `firecrawl/scrape`.
+pub fn tool_status(component: &str, function: &str) -> String {
+    let component = component.rsplit(':').next().unwrap_or(component);
+    let function = function.rsplit('/').next().unwrap_or(function);
+    format!("Using {component} ({})…", function.replace('-', " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_statuses_name_the_component_and_function() {
+        assert_eq!(
+            tool_status("asterai:firecrawl", "firecrawl/scrape"),
+            "Using firecrawl (scrape)…"
+        );
+        assert_eq!(
+            tool_status("asterbot:memory", "memory/list-all"),
+            "Using memory (list all)…"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Record-and-replay of agent turns.
+//!
+//! With `ASTERBOT_RECORD_TURNS=true`, the host is wrapped in a
+//! [`Recorder`] and every LLM request/response and component call
+//! of a turn is written to `recordings/<turn id>.json` in the host
+//! dir. A [`Replayer`] answers from such a recording instead of the
+//! real host, so the exact turn can be re-run offline. Requests that
+//! differ from the recorded ones are reported as divergences.
+
+use crate::error::{AgentError, ErrorKind};
+use crate::host::{ChatMessage, ChatResponse, Host, HostError, ToolDefinition};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+#[cfg(not(test))]
+pub const RECORDINGS_DIR: &str = "recordings";
+const RECORDING_VERSION: u32 = 1;
+
+/// Everything needed to re-run a turn.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub version: u32,
+    pub turn_id: String,
+    pub input: String,
+    pub settings: Settings,
+    pub exchanges: Vec<Exchange>,
+    /// The turn's reply, or the error it failed with.
+    pub outcome: Result<String, AgentError>,
+}
+
+/// The config that shapes the prompt, as of the recording.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    pub model: String,
+    pub system_prompt: String,
+    pub max_tool_rounds: usize,
+    pub max_prompt_chars: usize,
+    pub max_prompt_user_messages: Option<usize>,
+    /// Empty for the default conversation, and in recordings
+    /// made before sessions.
+    #[serde(default)]
+    pub session: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Exchange {
+    Chat {
+        messages: Vec<ChatMessage>,
+        tools: Vec<ToolDefinition>,
+        model: String,
+        response: Result<ChatResponse, AgentError>,
+    },
+    Call {
+        component: String,
+        function: String,
+        args: String,
+        result: Result<String, HostError>,
+    },
+}
+
+impl Recording {
+    pub fn load(path: &str) -> Result<Self, AgentError> {
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            AgentError::new(ErrorKind::Config, format!("failed to read {path}: {e}"))
+        })?;
+        let recording: Recording = serde_json::from_str(&json).map_err(|e| {
+            AgentError::new(ErrorKind::Config, format!("invalid recording {path}: {e}"))
+        })?;
+        if recording.version != RECORDING_VERSION {
+            return Err(AgentError::new(
+                ErrorKind::Config,
+                format!("unsupported recording version {}", recording.version),
+            ));
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &str) {
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    eprintln!("error: failed to write {path}: {e}");
+                }
+            }
+            Err(e) => eprintln!("error: failed to serialise recording: {e}"),
+        }
+    }
+}
+
+/// Passes calls through to a host, capturing them.
+pub struct Recorder<'a> {
+    inner: &'a dyn Host,
+    exchanges: RefCell<Vec<Exchange>>,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(inner: &'a dyn Host) -> Self {
+        Recorder {
+            inner,
+            exchanges: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn finish(
+        self,
+        turn_id: &str,
+        input: String,
+        settings: Settings,
+        outcome: Result<String, AgentError>,
+    ) -> Recording {
+        Recording {
+            version: RECORDING_VERSION,
+            turn_id: turn_id.to_string(),
+            input,
+            settings,
+            exchanges: self.exchanges.into_inner(),
+            outcome,
+        }
+    }
+}
+
+impl Host for Recorder<'_> {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, HostError> {
+        let result = self
+            .inner
+            .call_component_function(component, function, args_json);
+        self.exchanges.borrow_mut().push(Exchange::Call {
+            component: component.to_string(),
+            function: function.to_string(),
+            args: args_json.to_string(),
+            result: result.clone(),
+        });
+        result
+    }
+
+    fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        model: &str,
+    ) -> Result<ChatResponse, AgentError> {
+        let response = self.inner.chat(messages, tools, model);
+        self.exchanges.borrow_mut().push(Exchange::Chat {
+            messages: messages.to_vec(),
+            tools: tools.to_vec(),
+            model: model.to_string(),
+            response: response.clone(),
+        });
+        response
+    }
+}
+
+type RecordedChat = (
+    Vec<ChatMessage>,
+    Vec<ToolDefinition>,
+    String,
+    Result<ChatResponse, AgentError>,
+);
+type RecordedCall = (String, Result<String, HostError>);
+
+/// Answers from a recording. LLM responses are served in order;
+/// component results in order per component function, so a turn
+/// that skips or reorders independent calls still replays.
+pub struct Replayer {
+    chats: RefCell<VecDeque<RecordedChat>>,
+    calls: RefCell<HashMap<(String, String), VecDeque<RecordedCall>>>,
+    llm_requests: RefCell<usize>,
+    divergences: RefCell<Vec<String>>,
+}
+
+impl Replayer {
+    pub fn new(recording: &Recording) -> Self {
+        let mut chats = VecDeque::new();
+        let mut calls: HashMap<_, VecDeque<_>> = HashMap::new();
+        for exchange in &recording.exchanges {
+            match exchange.clone() {
+                Exchange::Chat {
+                    messages,
+                    tools,
+                    model,
+                    response,
+                } => chats.push_back((messages, tools, model, response)),
+                Exchange::Call {
+                    component,
+                    function,
+                    args,
+                    result,
+                } => calls
+                    .entry((component, function))
+                    .or_default()
+                    .push_back((args, result)),
+            }
+        }
+        Replayer {
+            chats: RefCell::new(chats),
+            calls: RefCell::new(calls),
+            llm_requests: RefCell::new(0),
+            divergences: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Divergences from the recording, including recorded
+    /// exchanges the replayed turn never asked for.
+    pub fn finish(self) -> Vec<String> {
+        let mut divergences = self.divergences.into_inner();
+        let unused_chats = self.chats.into_inner().len();
+        if unused_chats > 0 {
+            divergences.push(format!("{unused_chats} recorded LLM response(s) not used"));
+        }
+        let mut unused_calls: Vec<_> = self
+            .calls
+            .into_inner()
+            .into_iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|((c, f), queue)| format!("{} recorded {c}/{f} call(s) not made", queue.len()))
+            .collect();
+        unused_calls.sort();
+        divergences.extend(unused_calls);
+        divergences
+    }
+
+    fn diverge(&self, divergence: String) {
+        self.divergences.borrow_mut().push(divergence);
+    }
+}
+
+impl Host for Replayer {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, HostError> {
+        let key = (component.to_string(), function.to_string());
+        let recorded = self
+            .calls
+            .borrow_mut()
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front);
+        match recorded {
+            Some((args, result)) => {
+                if args != args_json {
+                    self.diverge(format!("{component}/{function}: arguments differ"));
+                }
+                result
+            }
+            None => {
+                self.diverge(format!("{component}/{function}: call not in recording"));
+                Err(HostError {
+                    kind: "NotFound".to_string(),
+                    message: format!("{component}/{function} not in recording"),
+                })
+            }
+        }
+    }
+
+    fn chat(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        model: &str,
+    ) -> Result<ChatResponse, AgentError> {
+        let index = {
+            let mut count = self.llm_requests.borrow_mut();
+            *count += 1;
+            *count
+        };
+        let Some((recorded_messages, recorded_tools, recorded_model, response)) =
+            self.chats.borrow_mut().pop_front()
+        else {
+            self.diverge(format!("llm request {index}: not in recording"));
+            return Err(AgentError::new(
+                ErrorKind::Llm,
+                "recording has no more LLM responses",
+            ));
+        };
+        if model != recorded_model {
+            self.diverge(format!(
+                "llm request {index}: model {model} (recorded {recorded_model})"
+            ));
+        }
+        if tools != recorded_tools.as_slice() {
+            self.diverge(format!("llm request {index}: tool definitions differ"));
+        }
+        if let Some(at) = first_difference(messages, &recorded_messages) {
+            self.diverge(format!(
+                "llm request {index}: messages differ from message {at} \
+                 ({} sent, {} recorded)",
+                messages.len(),
+                recorded_messages.len(),
+            ));
+        }
+        response
+    }
+}
+
+fn first_difference(a: &[ChatMessage], b: &[ChatMessage]) -> Option<usize> {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(i) => Some(i),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+/// The outcome of replaying a recording.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    pub turn_id: String,
+    pub recorded: Result<String, AgentError>,
+    pub replayed: Result<String, AgentError>,
+    pub divergences: Vec<String>,
+}
+
+impl ReplayReport {
+    /// Whether the replay made the same requests and
+    /// produced the same outcome as the recording.
+    pub fn is_identical(&self) -> bool {
+        self.divergences.is_empty() && self.recorded == self.replayed
+    }
+}
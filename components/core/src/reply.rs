@@ -0,0 +1,309 @@
+//! Rich replies: attachments, quick-reply buttons and locations.
+//!
+//! The model asks for them with directive lines in its final reply,
+//! which are taken out of the text returned to gateways:
+//!
+//! ```text
+//! [attach: outbox/june.pdf]
+//! [attach: https://example.com/chart.png]
+//! [buttons: Yes | No | Later]
+//! [location: 51.5007, -0.1246 | Big Ben]
+//! ```
+//!
+//! Attached paths are relative to the host dir, where the agent's
+//! file tools write, and are read into the reply as bytes. Only
+//! files in its `outbox/` can be attached, so a reply can't send
+//! the host dir's own state: conversations, contacts, usage and the
+//! like. History keeps the reply as the model wrote it.
+
+use crate::input::{Media, MediaData};
+
+/// The only directory under the host dir that files are attached
+/// from.
+pub const OUTBOX_DIR: &str = "outbox";
+
+/// Files larger than this are not attached.
+const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+const MAX_BUTTONS: usize = 10;
+
+/// Mirrors `asterbot:types/types.location`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub label: String,
+}
+
+/// A reply with its directives resolved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RichReply {
+    pub text: String,
+    pub attachments: Vec<Media>,
+    pub buttons: Vec<String>,
+    pub location: Option<Location>,
+}
+
+/// Explains the directives to the model, for the system prompt.
+pub const GUIDANCE: &str = "\
+You can send more than text by ending your reply with directive \
+lines, each on its own line:\n\
+- [attach: <path or URL>] sends a file you wrote to outbox/ in \
+your working directory (e.g. [attach: outbox/report.pdf]) or a file \
+at a URL.\n\
+- [buttons: <option> | <option>] offers quick replies; the chosen \
+option comes back as the user's next message.\n\
+- [location: <latitude>, <longitude> | <label>] shares a place.\n\
+Only use them when they help; the user never sees the directives.";
+
+/// Takes the directive lines out of `reply`, reading attached
+/// files from `host_dir`. Directives that can't be honoured are
+/// replaced by a note in the text.
+pub fn parse(reply: &str, host_dir: &str) -> RichReply {
+    let mut rich = RichReply::default();
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    for line in reply.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let directive = match in_fence {
+            true => None,
+            false => directive(line),
+        };
+        let Some((name, value)) = directive else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let result = match name.as_str() {
+            "attach" => attachment(value, host_dir).map(|media| rich.attachments.push(media)),
+            "buttons" => {
+                rich.buttons.extend(
+                    value
+                        .split('|')
+                        .map(str::trim)
+                        .filter(|b| !b.is_empty())
+                        .map(str::to_string),
+                );
+                rich.buttons.truncate(MAX_BUTTONS);
+                Ok(())
+            }
+            "location" => location(value).map(|l| rich.location = Some(l)),
+            _ => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        if let Err(note) = result {
+            lines.push(format!("[{note}]"));
+        }
+    }
+    rich.text = lines.join("\n").trim().to_string();
+    rich
+}
+
+/// Splits "[name: value]" into its lowercased name and value.
+fn directive(line: &str) -> Option<(String, &str)> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(':')?;
+    let name = name.trim().to_ascii_lowercase();
+    matches!(name.as_str(), "attach" | "buttons" | "location").then(|| (name, value.trim()))
+}
+
+fn attachment(target: &str, host_dir: &str) -> Result<Media, String> {
+    let name = file_name(target);
+    if target.starts_with("https://") || target.starts_with("http://") {
+        return Ok(Media {
+            mime_type: mime_type(&name).to_string(),
+            name,
+            data: MediaData::Url(target.to_string()),
+        });
+    }
+    // "/host/dir2/x" is not in "/host/dir".
+    let relative = match target.strip_prefix(host_dir) {
+        Some(rest) if !host_dir.is_empty() && rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => target,
+    };
+    let in_outbox = relative
+        .strip_prefix(OUTBOX_DIR)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .is_some_and(|rest| !rest.is_empty());
+    if host_dir.is_empty() || !in_outbox || relative.split(['/', '\\']).any(|c| c == "..") {
+        return Err(format!(
+            "Could not attach {target}: only files in {OUTBOX_DIR}/ can be attached"
+        ));
+    }
+    let path = format!("{host_dir}/{relative}");
+    let metadata =
+        std::fs::symlink_metadata(&path).map_err(|e| format!("Could not attach {target}: {e}"))?;
+    // A link could point back at the host dir's state.
+    if !metadata.is_file() {
+        return Err(format!("Could not attach {target}: it is not a file"));
+    }
+    let size = metadata.len();
+    if size > MAX_ATTACHMENT_BYTES as u64 {
+        return Err(format!(
+            "Could not attach {target}: it is larger than {} MB",
+            MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        ));
+    }
+    let bytes = std::fs::read(&path).map_err(|e| format!("Could not attach {target}: {e}"))?;
+    Ok(Media {
+        mime_type: mime_type(&name).to_string(),
+        name,
+        data: MediaData::Bytes(bytes),
+    })
+}
+
+fn location(value: &str) -> Result<Location, String> {
+    let (coordinates, label) = value.split_once('|').unwrap_or((value, ""));
+    let parsed = coordinates
+        .split_once(',')
+        .and_then(|(lat, lon)| Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?)));
+    match parsed {
+        Some((latitude, longitude))
+            if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) =>
+        {
+            Ok(Location {
+                latitude,
+                longitude,
+                label: label.trim().to_string(),
+            })
+        }
+        _ => Err(format!("Could not share location {value}")),
+    }
+}
+
+/// The last path segment of a path or URL, without any query.
+fn file_name(target: &str) -> String {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    path.trim_end_matches('/')
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn mime_type(name: &str) -> &'static str {
+    let extension = name.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        Some("txt" | "log") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("csv") => "text/csv",
+        Some("html" | "htm") => "text/html",
+        Some("json") => "application/json",
+        Some("zip") => "application/zip",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg" | "opus") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("m4a") => "audio/mp4",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives_are_taken_out_of_the_text() {
+        let dir = std::env::temp_dir().join(format!("asterbot-core-reply-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(OUTBOX_DIR)).unwrap();
+        std::fs::write(dir.join("outbox/june.pdf"), b"%PDF").unwrap();
+        let dir = dir.to_str().unwrap();
+        let reply = "Here's the report.\n\n\
+            [attach: outbox/june.pdf]\n\
+            [attach: https://example.com/chart.png?size=2]\n\
+            [Buttons: Looks good | Change it | ]\n\
+            [location: 41.1496, -8.6109 | Ribeira]";
+        let rich = parse(reply, dir);
+        assert_eq!(rich.text, "Here's the report.");
+        assert_eq!(rich.attachments.len(), 2);
+        assert_eq!(rich.attachments[0].name, "june.pdf");
+        assert_eq!(rich.attachments[0].mime_type, "application/pdf");
+        assert_eq!(rich.attachments[0].data, MediaData::Bytes(b"%PDF".to_vec()));
+        assert_eq!(rich.attachments[1].name, "chart.png");
+        assert_eq!(
+            rich.attachments[1].data,
+            MediaData::Url("https://example.com/chart.png?size=2".to_string())
+        );
+        assert_eq!(rich.buttons, ["Looks good", "Change it"]);
+        assert_eq!(
+            rich.location,
+            Some(Location {
+                latitude: 41.1496,
+                longitude: -8.6109,
+                label: "Ribeira".to_string(),
+            })
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_directives_leave_a_note() {
+        let rich = parse(
+            "Done.\n[attach: ../secrets.txt]\n[attach: outbox/missing.txt]\n[location: north]",
+            "/nonexistent",
+        );
+        let lines: Vec<&str> = rich.text.lines().collect();
+        assert_eq!(lines[0], "Done.");
+        assert_eq!(
+            lines[1],
+            "[Could not attach ../secrets.txt: only files in outbox/ can be attached]"
+        );
+        assert!(lines[2].starts_with("[Could not attach outbox/missing.txt: "));
+        assert_eq!(lines[3], "[Could not share location north]");
+        assert!(rich.attachments.is_empty());
+    }
+
+    #[test]
+    fn only_outbox_files_are_attached() {
+        let root =
+            std::env::temp_dir().join(format!("asterbot-core-outbox-{}", std::process::id()));
+        let dir = root.join("host");
+        std::fs::create_dir_all(dir.join(OUTBOX_DIR)).unwrap();
+        std::fs::create_dir_all(root.join("host2/outbox")).unwrap();
+        std::fs::write(dir.join("contacts.json"), "{}").unwrap();
+        std::fs::write(dir.join("outbox/chart.png"), "png").unwrap();
+        std::fs::write(root.join("host2/outbox/chart.png"), "png").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("contacts.json"), dir.join("outbox/link.json"))
+            .unwrap();
+        let dir = dir.to_str().unwrap();
+        let absolute = format!("{dir}/outbox/chart.png");
+        assert!(attachment(&absolute, dir).is_ok());
+        for target in [
+            "contacts.json".to_string(),
+            "outbox".to_string(),
+            "outbox/../contacts.json".to_string(),
+            format!("{dir}/contacts.json"),
+            format!("{dir}2/outbox/chart.png"),
+        ] {
+            let note = attachment(&target, dir).unwrap_err();
+            assert!(
+                note.ends_with("only files in outbox/ can be attached"),
+                "{note}"
+            );
+        }
+        #[cfg(unix)]
+        assert_eq!(
+            attachment("outbox/link.json", dir).unwrap_err(),
+            "Could not attach outbox/link.json: it is not a file"
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn other_brackets_and_code_are_left_alone() {
+        let reply = "[note: not a directive]\n```\n[buttons: a | b]\n```";
+        let rich = parse(reply, "/nonexistent");
+        assert_eq!(rich.text, reply);
+        assert!(rich.buttons.is_empty());
+    }
+}
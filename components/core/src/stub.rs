@@ -0,0 +1,69 @@
+//! A scripted stand-in for the LLM, selected with
+//! `ASTERBOT_MODEL=asterbot/stub`.
+//!
+//! Each request pops the next response from `stub-llm.json` in the
+//! host dir, a JSON array of chat responses such as
+//! `[{"toolCalls": [{"id": "1", "name": "…", "argumentsJson": "{}"}]}, {"content": "Done."}]`.
+//! This lets `asterbot:eval` scenarios run the whole agent offline.
+
+use crate::error::{AgentError, ErrorKind};
+use crate::host::{ChatMessage, ChatResponse, Host, HostError, ToolDefinition};
+
+pub const STUB_MODEL: &str = "asterbot/stub";
+#[cfg(not(test))]
+pub const STUB_FILENAME: &str = "stub-llm.json";
+
+/// Serves scripted LLM responses, passing component calls
+/// through to the real host.
+pub struct StubLlm<'a> {
+    inner: &'a dyn Host,
+    path: &'a str,
+}
+
+impl<'a> StubLlm<'a> {
+    pub fn new(inner: &'a dyn Host, path: &'a str) -> Self {
+        StubLlm { inner, path }
+    }
+}
+
+impl Host for StubLlm<'_> {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, HostError> {
+        self.inner
+            .call_component_function(component, function, args_json)
+    }
+
+    fn chat(
+        &self,
+        _messages: &[ChatMessage],
+        _tools: &[ToolDefinition],
+        _model: &str,
+    ) -> Result<ChatResponse, AgentError> {
+        let path = self.path;
+        let mut script: Vec<ChatResponse> = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                AgentError::new(ErrorKind::Llm, format!("invalid stub script {path}: {e}"))
+            })?,
+            Err(_) => Vec::new(),
+        };
+        if script.is_empty() {
+            return Err(AgentError::new(
+                ErrorKind::Llm,
+                format!("stub LLM has no scripted responses left in {path}"),
+            ));
+        }
+        let response = script.remove(0);
+        let rest = serde_json::to_string_pretty(&script).unwrap_or_else(|_| "[]".to_string());
+        if let Err(e) = std::fs::write(path, rest) {
+            return Err(AgentError::new(
+                ErrorKind::Llm,
+                format!("failed to write {path}: {e}"),
+            ));
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::host::{ChatMessage, ChatResponse, ToolDefinition};
+use crate::input::Media;
+use crate::reply::Location;
+
+const TRACE_RESULT_PREVIEW_CHARS: usize = 500;
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Mirrors `asterbot:types/types.tool-invocation`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolInvocation {
+    pub name: String,
+    pub args_json: String,
+    pub result: String,
+    pub duration_ms: u64,
+    pub is_error: bool,
+}
+
+/// Mirrors `asterbot:types/types.usage`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub rounds: u32,
+    pub llm_calls: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Mirrors `asterbot:types/types.stop-reason`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Completed,
+    MaxToolRounds,
+}
+
+/// Mirrors `asterbot:types/types.converse-response`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConverseResponse {
+    pub reply: String,
+    pub attachments: Vec<Media>,
+    pub buttons: Vec<String>,
+    pub location: Option<Location>,
+    pub tool_invocations: Vec<ToolInvocation>,
+    pub usage: Usage,
+    pub model: String,
+    pub stop_reason: StopReason,
+    pub turn_id: String,
+}
+
+impl ToolInvocation {
+    pub fn new(
+        name: &str,
+        args_json: &str,
+        result: &str,
+        duration_ms: u64,
+        is_error: bool,
+    ) -> Self {
+        ToolInvocation {
+            name: name.to_string(),
+            args_json: args_json.to_string(),
+            result: truncate_str(result, TRACE_RESULT_PREVIEW_CHARS),
+            duration_ms,
+            is_error,
+        }
+    }
+}
+
+impl Usage {
+    /// Record one LLM request/response pair, estimating token
+    /// counts from the request and response sizes.
+    pub fn record_chat(
+        &mut self,
+        messages: &[ChatMessage],
+        tools: &[ToolDefinition],
+        response: &ChatResponse,
+    ) {
+        self.llm_calls += 1;
+        let input_chars: usize = messages
+            .iter()
+            .map(|m| {
+                m.content.len()
+                    + m.tool_calls
+                        .iter()
+                        .map(|tc| tc.name.len() + tc.arguments_json.len())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+            + tools
+                .iter()
+                .map(|t| t.name.len() + t.description.len() + t.parameters_json_schema.len())
+                .sum::<usize>();
+        let output_chars = response.content.len()
+            + response
+                .tool_calls
+                .iter()
+                .map(|tc| tc.name.len() + tc.arguments_json.len())
+                .sum::<usize>();
+        self.input_tokens += estimate_tokens(input_chars);
+        self.output_tokens += estimate_tokens(output_chars);
+    }
+}
+
+pub fn estimate_tokens(chars: usize) -> u64 {
+    chars.div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+fn truncate_str(s: &str, max: usize) -> String {
+    if s.len() <= max {
+        return s.to_string();
+    }
+    let mut end = max;
+    while end > 0 && !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &s[..end])
+}
@@ -1,9 +1,20 @@
 use crate::bindings::asterai::discord::api;
-use crate::bindings::asterai::discord::types::{Message, User};
+use crate::bindings::asterai::discord::types::Message;
+use crate::bindings::asterai::host::api as host_api;
 use crate::bindings::asterbot::types::agent;
+use crate::bindings::asterbot::types::types::{
+    AgentError, ConverseRequest, ConverseResponse, ErrorKind, InputPart, MediaData,
+};
 use crate::bindings::exports::asterai::discord::incoming_handler::Guest;
+use crate::bindings::exports::asterbot::types::delivery::Guest as DeliveryGuest;
+use gateway_common::{
+    Commands, Contact, DiscordAccess, DiscordContext, EventLog, Format, Host, IdKind, Origin,
+    RateLimit, SessionKey, Splitter, Verdict, reply, resolve_host_dir,
+};
+use std::sync::LazyLock;
+use std::time::Instant;
 
-const DISCORD_MAX_CHARS: usize = 2000;
+const COMPONENT: &str = "asterbot:discord-gateway";
 
 #[allow(warnings)]
 mod bindings {
@@ -14,43 +25,194 @@ mod bindings {
     });
 }
 
+static ACCESS: LazyLock<DiscordAccess> = LazyLock::new(DiscordAccess::from_env);
+
+static COMMANDS: LazyLock<Commands> =
+    LazyLock::new(|| Commands::from_env("DISCORD", IdKind::User, ACCESS.allowed_users()));
+
+static LIMITS: LazyLock<RateLimit> = LazyLock::new(|| RateLimit::from_env("DISCORD", IdKind::User));
+
+static SPLITTER: LazyLock<Splitter> =
+    LazyLock::new(|| Splitter::from_env("DISCORD", Splitter::DISCORD));
+
+static FORMAT: LazyLock<Format> = LazyLock::new(|| Format::from_env("DISCORD", Format::Markdown));
+
 struct Component;
 
+struct WasmHost;
+
+impl Host for WasmHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String> {
+        host_api::call_component_function(component, function, args_json).map_err(|e| e.message)
+    }
+}
+
 impl Guest for Component {
     fn on_message(message: Message) {
         let self_user = api::get_self();
-        if !check_should_proceed(&message, &self_user) {
+        if message.author.id == self_user.id {
+            // Do not proceed if message is from self.
+            return;
+        }
+        let ctx = DiscordContext {
+            user_id: &message.author.id,
+            channel_id: &message.channel_id,
+            guild_id: message.guild_id.as_deref(),
+            role_ids: &message.member_roles,
+        };
+        let mention = format!("<@{}>", self_user.id);
+        if !ACCESS.should_respond(&ctx, message.content.contains(&mention)) {
+            return;
+        }
+        let events = EventLog::from_env(COMPONENT);
+        if !ACCESS.allows(&ctx) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({ "sender": message.author.id }),
+            );
             return;
         }
-        println!("processing message {message:#?}");
-        let response = agent::converse(&message.content);
-        let response = truncate_to_discord_limit(&response);
-        api::send_message(&response, &message.channel_id);
+        let is_admin = COMMANDS.is_admin(&message.author.id);
+        if !is_admin && LIMITS.is_blocked(&message.author.id) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({
+                    "sender": message.author.id,
+                    "reason": "blocked",
+                }),
+            );
+            return;
+        }
+        let session = match &message.guild_id {
+            Some(_) => SessionKey::group("discord", &message.channel_id),
+            None => SessionKey::direct("discord", &message.author.id),
+        };
+        let started = Instant::now();
+        // Commands follow the mention: "@asterbot /reset".
+        let text = message.content.replacen(&mention, "", 1);
+        if let Some(handled) = COMMANDS.handle(&WasmHost, &text, &message.author.id, &session) {
+            for part in SPLITTER.split(&handled.reply) {
+                api::send_message(&FORMAT.render(&part), &message.channel_id);
+            }
+            events.emit(
+                "command.handled",
+                serde_json::json!({
+                    "sender": message.author.id,
+                    "session": session.as_str(),
+                    "command": handled.command,
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+            );
+            return;
+        }
+        let verdict = match is_admin {
+            true => Verdict::Allowed,
+            false => LIMITS.check(&message.author.id, &message.content),
+        };
+        if verdict != Verdict::Allowed {
+            if let Some(reply) = verdict.reply() {
+                api::send_message(&FORMAT.render(&reply), &message.channel_id);
+            }
+            events.emit(
+                "message.limited",
+                serde_json::json!({
+                    "sender": message.author.id,
+                    "session": session.as_str(),
+                    "reason": verdict.reason(),
+                }),
+            );
+            return;
+        }
+        let host_dir = resolve_host_dir();
+        Origin::new(COMPONENT, &message.channel_id).record(&host_dir, session.as_str());
+        if message.guild_id.is_none() {
+            let (author, channel) = (&message.author, &message.channel_id);
+            Contact::new("discord", &author.id, &author.username, COMPONENT, channel)
+                .learn(&host_dir);
+        }
+        let response = converse(&message.content, &session);
+        let text = match &response {
+            Ok(response) => reply_text(response),
+            Err(e) => format!("error: {}", e.message),
+        };
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            api::send_message(&FORMAT.render(part), &message.channel_id);
+        }
+        events.emit(
+            "message.handled",
+            serde_json::json!({
+                "turnId": response.as_ref().ok().map(|r| &r.turn_id),
+                "sender": message.author.id,
+                "session": session.as_str(),
+                "durationMs": started.elapsed().as_millis() as u64,
+                "parts": parts.len(),
+                "replyAttachments": response.as_ref().map_or(0, |r| r.attachments.len()),
+                "input": events.content(&message.content),
+                "reply": events.content(&text),
+            }),
+        );
     }
 }
 
-fn check_should_proceed(message: &Message, self_user: &User) -> bool {
-    if message.author.id == self_user.id {
-        // Do not proceed if message is from self.
-        return false;
+impl DeliveryGuest for Component {
+    /// Sends a message the agent wrote on its own, e.g. a scheduled
+    /// reminder. `address` is the channel id.
+    fn deliver(address: String, text: String) -> Result<(), AgentError> {
+        if address.is_empty() {
+            return Err(AgentError {
+                kind: ErrorKind::Config,
+                message: "no channel id".to_string(),
+            });
+        }
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            api::send_message(&FORMAT.render(part), &address);
+        }
+        EventLog::from_env(COMPONENT).emit(
+            "message.delivered",
+            serde_json::json!({ "channel": address, "parts": parts.len() }),
+        );
+        Ok(())
     }
-    let mention = format!("<@{}>", self_user.id);
-    let has_mention = message.content.contains(&mention);
-    has_mention
 }
 
-fn truncate_to_discord_limit(text: &str) -> String {
-    if text.len() <= DISCORD_MAX_CHARS {
-        return text.to_string();
+/// Sends the message to the agent in `session`.
+fn converse(text: &str, session: &SessionKey) -> Result<ConverseResponse, AgentError> {
+    agent::converse(&ConverseRequest {
+        session: Some(session.as_str().to_string()),
+        parts: vec![InputPart::Text(text.to_string())],
+        progress: None,
+    })
+}
+
+/// The reply's text, followed by what Discord's API can't
+/// send: files, quick replies and the location.
+fn reply_text(response: &ConverseResponse) -> String {
+    let mut lines: Vec<String> = response
+        .attachments
+        .iter()
+        .map(|media| match &media.data {
+            MediaData::Url(url) => reply::link_text(&media.name, url),
+            MediaData::Bytes(_) => format!("({} can't be sent here)", media.name),
+        })
+        .collect();
+    if !response.buttons.is_empty() {
+        lines.push(reply::options_text(&response.buttons));
+    }
+    if let Some(location) = &response.location {
+        lines.push(reply::location_text(
+            location.latitude,
+            location.longitude,
+            &location.label,
+        ));
     }
-    let truncated = &text[..DISCORD_MAX_CHARS - 3];
-    // Avoid splitting a multi-byte char.
-    let end = truncated
-        .char_indices()
-        .last()
-        .map(|(i, c)| i + c.len_utf8())
-        .unwrap_or(0);
-    format!("{}...", &text[..end])
+    reply::compose(&response.reply, &lines)
 }
 
 bindings::export!(Component with_types_in bindings);
@@ -0,0 +1,371 @@
+#[cfg(not(test))]
+use crate::bindings::asterai::host::api;
+#[cfg(not(test))]
+use crate::bindings::asterbot::types::types::{AgentError, ErrorKind};
+#[cfg(not(test))]
+use crate::bindings::exports::asterbot::types::eval::Guest;
+#[cfg(not(test))]
+use crate::report::{compare, Report, ScenarioResult};
+use crate::scenario::{Scenario, TurnOutcome};
+#[cfg(not(test))]
+use serde_json::json;
+use serde_json::Value;
+#[cfg(not(test))]
+use std::time::Instant;
+
+mod report;
+mod scenario;
+
+/// Read by core's stub LLM (`ASTERBOT_MODEL=asterbot/stub`).
+#[cfg(not(test))]
+const STUB_FILENAME: &str = "stub-llm.json";
+const REPORTS_DIR: &str = "reports";
+
+#[cfg(not(test))]
+#[allow(warnings)]
+mod bindings {
+    wit_bindgen::generate!({
+        path: "wit/package.wasm",
+        world: "component",
+        generate_all,
+    });
+}
+
+#[cfg(not(test))]
+struct Component;
+
+#[cfg(not(test))]
+impl Guest for Component {
+    fn run(suite: String, label: String) -> Result<String, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let suite_dir = resolve_path(&host_dir, &suite);
+        let label = match label.is_empty() {
+            true => std::env::var("ASTERBOT_MODEL").unwrap_or_default(),
+            false => label,
+        };
+        let scenarios = load_suite(&suite_dir).map_err(config_error)?;
+        let mut report = Report {
+            suite,
+            label,
+            scenarios: Vec::new(),
+        };
+        for (file, scenario) in scenarios {
+            let mut result = run_scenario(&host_dir, &scenario);
+            result.file = file;
+            report.scenarios.push(result);
+        }
+        let _ = std::fs::remove_file(format!("{host_dir}/{STUB_FILENAME}"));
+        let path = report_path(&suite_dir, &report.label);
+        let json = serde_json::to_string_pretty(&report).unwrap_or_default();
+        let _ = std::fs::create_dir_all(format!("{suite_dir}/{REPORTS_DIR}"));
+        std::fs::write(&path, json).map_err(|e| AgentError {
+            kind: ErrorKind::Storage,
+            message: format!("failed to write {path}: {e}"),
+        })?;
+        Ok(report.summary())
+    }
+
+    fn compare(suite: String, baseline: String, candidate: String) -> Result<String, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let suite_dir = resolve_path(&host_dir, &suite);
+        let baseline = load_report(&report_path(&suite_dir, &baseline)).map_err(config_error)?;
+        let candidate = load_report(&report_path(&suite_dir, &candidate)).map_err(config_error)?;
+        Ok(compare(&baseline, &candidate))
+    }
+}
+
+/// Runs a scenario from a clean conversation and memory.
+#[cfg(not(test))]
+fn run_scenario(host_dir: &str, scenario: &Scenario) -> ScenarioResult {
+    let mut result = ScenarioResult {
+        name: scenario.name.clone(),
+        ..Default::default()
+    };
+    if let Err(e) = reset_state(scenario) {
+        result.failures.push(format!("setup failed: {e}"));
+        return result;
+    }
+    let stub_path = format!("{host_dir}/{STUB_FILENAME}");
+    for (i, turn) in scenario.turns.iter().enumerate() {
+        let stub = serde_json::to_string_pretty(&turn.stub).unwrap_or_default();
+        if let Err(e) = std::fs::write(&stub_path, stub) {
+            result
+                .failures
+                .push(format!("failed to write {stub_path}: {e}"));
+            return result;
+        }
+        let started = Instant::now();
+        let request = json!({
+            "session": null,
+            "parts": [{ "text": turn.input }],
+            "progress": null,
+        });
+        let output = call("asterbot:agent", "agent/converse", &json!([request]));
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let outcome = match output {
+            Ok(output) => decode_outcome(&turn.input, &output, duration_ms),
+            Err(message) => TurnOutcome {
+                input: turn.input.clone(),
+                reply: message,
+                error: Some("config".to_string()),
+                duration_ms,
+                ..Default::default()
+            },
+        };
+        for failure in turn.expect.check(&outcome) {
+            result.failures.push(format!("turn {}: {failure}", i + 1));
+        }
+        result.turns.push(outcome);
+    }
+    for (name, expected) in &scenario.expect_memory {
+        let content = call("asterbot:memory", "memory/get", &json!([name]))
+            .map(|json| serde_json::from_str::<String>(&json).unwrap_or(json))
+            .unwrap_or_default();
+        if let Some(failure) = check_contains(&format!("memory {name}"), &content, expected) {
+            result.failures.push(failure);
+        }
+    }
+    for (file, expected) in &scenario.expect_files {
+        let content = std::fs::read_to_string(format!("{host_dir}/{file}")).unwrap_or_default();
+        if let Some(failure) = check_contains(&format!("file {file}"), &content, expected) {
+            result.failures.push(failure);
+        }
+    }
+    result
+}
+
+/// Clears history and memory, then seeds the scenario's memories.
+#[cfg(not(test))]
+fn reset_state(scenario: &Scenario) -> Result<(), String> {
+    call("asterbot:history", "history/clear", &json!([]))?;
+    let names = call("asterbot:memory", "memory/list-all", &json!([]))?;
+    let names: Vec<String> = serde_json::from_str(&names).unwrap_or_default();
+    for name in &names {
+        call("asterbot:memory", "memory/remove", &json!([name]))?;
+    }
+    for (name, content) in &scenario.memory {
+        call("asterbot:memory", "memory/set", &json!([name, content]))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn call(component: &str, function: &str, args: &Value) -> Result<String, String> {
+    api::call_component_function(component, function, &args.to_string())
+        .map_err(|e| format!("{component}/{function} failed: {}", e.message))
+}
+
+/// Decodes the JSON encoding of the agent's
+/// `result<converse-response, agent-error>`.
+fn decode_outcome(input: &str, output: &str, duration_ms: u64) -> TurnOutcome {
+    let value: Value = serde_json::from_str(output).unwrap_or(Value::Null);
+    let str_field = |v: &Value, key: &str| {
+        v.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut outcome = TurnOutcome {
+        input: input.to_string(),
+        duration_ms,
+        ..Default::default()
+    };
+    if let Some(err) = value.get("err") {
+        outcome.reply = str_field(err, "message");
+        outcome.error = Some(str_field(err, "kind"));
+        return outcome;
+    }
+    let Some(ok) = value.get("ok") else {
+        outcome.reply = format!("unexpected agent output: {output}");
+        outcome.error = Some("config".to_string());
+        return outcome;
+    };
+    let usage = ok.get("usage").cloned().unwrap_or(Value::Null);
+    outcome.reply = str_field(ok, "reply");
+    outcome.tool_calls = ok
+        .get("tool-invocations")
+        .and_then(Value::as_array)
+        .map(|items| items.iter().map(|t| str_field(t, "name")).collect())
+        .unwrap_or_default();
+    outcome.rounds = usage.get("rounds").and_then(Value::as_u64).unwrap_or(0) as u32;
+    outcome.cost_usd = usage.get("cost-usd").and_then(Value::as_f64).unwrap_or(0.0);
+    outcome.stop_reason = str_field(ok, "stop-reason");
+    outcome
+}
+
+/// An empty `expected` asserts the content is empty.
+fn check_contains(what: &str, content: &str, expected: &str) -> Option<String> {
+    match expected.is_empty() {
+        true if !content.trim().is_empty() => Some(format!("{what} should be empty")),
+        false if !content.to_lowercase().contains(&expected.to_lowercase()) => {
+            Some(format!("{what} does not contain \"{expected}\""))
+        }
+        _ => None,
+    }
+}
+
+/// Loads every `*.json` scenario in the suite directory,
+/// sorted by file name.
+fn load_suite(dir: &str) -> Result<Vec<(String, Scenario)>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("failed to read suite {dir}: {e}"))?;
+    let mut files: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".json"))
+        .collect();
+    files.sort();
+    let mut scenarios = Vec::new();
+    for file in files {
+        let path = format!("{dir}/{file}");
+        let json =
+            std::fs::read_to_string(&path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        let scenario: Scenario =
+            serde_json::from_str(&json).map_err(|e| format!("invalid scenario {path}: {e}"))?;
+        scenarios.push((file, scenario));
+    }
+    if scenarios.is_empty() {
+        return Err(format!("no scenarios in {dir}"));
+    }
+    Ok(scenarios)
+}
+
+#[cfg(not(test))]
+fn load_report(path: &str) -> Result<Report, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read report {path}: {e}"))?;
+    serde_json::from_str(&json).map_err(|e| format!("invalid report {path}: {e}"))
+}
+
+/// Labels are usually model names, so "/" is replaced.
+fn report_path(suite_dir: &str, label: &str) -> String {
+    let name: String = label
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '-',
+            c => c,
+        })
+        .collect();
+    format!("{suite_dir}/{REPORTS_DIR}/{name}.json")
+}
+
+fn resolve_path(host_dir: &str, path: &str) -> String {
+    match path.starts_with('/') {
+        true => path.trim_end_matches('/').to_string(),
+        false => format!("{host_dir}/{}", path.trim_end_matches('/')),
+    }
+}
+
+#[cfg(not(test))]
+fn resolve_host_dir() -> Result<String, AgentError> {
+    if let Ok(v) = std::env::var("ASTERBOT_HOST_DIR") {
+        if !v.is_empty() {
+            return Ok(v);
+        }
+    }
+    if let Ok(dirs) = std::env::var("ASTERAI_ALLOWED_DIRS") {
+        if let Some(first) = dirs.split(':').next() {
+            if !first.is_empty() {
+                return Ok(first.to_string());
+            }
+        }
+    }
+    Err(config_error(
+        "no state directory available — pass --allow-dir to grant filesystem access".to_string(),
+    ))
+}
+
+#[cfg(not(test))]
+fn config_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Config,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_successful_turns() {
+        let output = r#"{"ok":{
+            "reply":"Porto.",
+            "tool-invocations":[{"name":"asterbot-memory--memory-get","args-json":"{}",
+                "result":"Lisbon","duration-ms":3,"is-error":false}],
+            "usage":{"rounds":1,"llm-calls":2,"input-tokens":10,"output-tokens":5,"cost-usd":0.25},
+            "model":"asterbot/stub",
+            "stop-reason":"completed"}}"#;
+        assert_eq!(
+            decode_outcome("where?", output, 12),
+            TurnOutcome {
+                input: "where?".to_string(),
+                reply: "Porto.".to_string(),
+                error: None,
+                tool_calls: vec!["asterbot-memory--memory-get".to_string()],
+                rounds: 1,
+                stop_reason: "completed".to_string(),
+                cost_usd: 0.25,
+                duration_ms: 12,
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_failed_turns() {
+        let outcome = decode_outcome(
+            "hi",
+            r#"{"err":{"kind":"limit","message":"daily budget exceeded"}}"#,
+            0,
+        );
+        assert_eq!(outcome.error.as_deref(), Some("limit"));
+        assert_eq!(outcome.reply, "daily budget exceeded");
+        let outcome = decode_outcome("hi", "not json", 0);
+        assert_eq!(outcome.error.as_deref(), Some("config"));
+    }
+
+    #[test]
+    fn contents_are_checked() {
+        assert_eq!(check_contains("memory city", "Lisbon", "lisbon"), None);
+        assert_eq!(
+            check_contains("memory city", "", "Lisbon"),
+            Some("memory city does not contain \"Lisbon\"".to_string())
+        );
+        assert_eq!(check_contains("memory city", " \n", ""), None);
+        assert_eq!(
+            check_contains("memory city", "Lisbon", ""),
+            Some("memory city should be empty".to_string())
+        );
+    }
+
+    #[test]
+    fn report_paths_are_sanitised() {
+        assert_eq!(
+            report_path("/h/evals", "anthropic/claude-sonnet-4-5"),
+            "/h/evals/reports/anthropic-claude-sonnet-4-5.json"
+        );
+        assert_eq!(resolve_path("/h", "evals/"), "/h/evals");
+        assert_eq!(resolve_path("/h", "/abs/evals"), "/abs/evals");
+    }
+
+    #[test]
+    fn example_suite_loads() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios");
+        let scenarios = load_suite(dir).unwrap();
+        assert!(!scenarios.is_empty());
+        assert!(scenarios.iter().all(|(_, s)| !s.turns.is_empty()));
+        let (file, saves) = &scenarios[2];
+        assert_eq!(file, "saves-memory.json");
+        assert_eq!(saves.name, "saves a preference");
+        assert!(saves.memory.is_empty());
+        assert!(saves.turns[0].input.contains("Rust"));
+        assert_eq!(saves.expect_memory["user_favourite_language"], "rust");
+        assert_eq!(
+            saves.expect_files["memory/user_favourite_language.md"],
+            "Rust"
+        );
+        assert!(load_suite("/nonexistent").is_err());
+    }
+}
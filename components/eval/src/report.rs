@@ -0,0 +1,209 @@
+//! Suite reports and comparison between configurations.
+
+use crate::scenario::TurnOutcome;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub suite: String,
+    /// Names the configuration the suite ran against.
+    pub label: String,
+    pub scenarios: Vec<ScenarioResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioResult {
+    pub name: String,
+    pub file: String,
+    pub failures: Vec<String>,
+    pub turns: Vec<TurnOutcome>,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.scenarios.iter().filter(|s| s.passed()).count()
+    }
+
+    pub fn cost_usd(&self) -> f64 {
+        self.turns().map(|t| t.cost_usd).sum()
+    }
+
+    /// Mean turn latency in milliseconds.
+    pub fn mean_turn_ms(&self) -> u64 {
+        let count = self.turns().count() as u64;
+        match count {
+            0 => 0,
+            _ => self.turns().map(|t| t.duration_ms).sum::<u64>() / count,
+        }
+    }
+
+    fn turns(&self) -> impl Iterator<Item = &TurnOutcome> {
+        self.scenarios.iter().flat_map(|s| s.turns.iter())
+    }
+
+    fn totals(&self) -> String {
+        format!(
+            "{}/{} passed, ${:.4}, {} ms/turn",
+            self.passed(),
+            self.scenarios.len(),
+            self.cost_usd(),
+            self.mean_turn_ms()
+        )
+    }
+
+    pub fn summary(&self) -> String {
+        let mut out = format!("{} ({}): {}\n", self.suite, self.label, self.totals());
+        for scenario in &self.scenarios {
+            let status = if scenario.passed() { "PASS" } else { "FAIL" };
+            out.push_str(&format!("{status} {}\n", scenario.name));
+            for failure in &scenario.failures {
+                out.push_str(&format!("  - {failure}\n"));
+            }
+        }
+        out
+    }
+}
+
+/// Lists scenarios whose outcome differs between two reports.
+pub fn compare(baseline: &Report, candidate: &Report) -> String {
+    let mut out = format!(
+        "baseline ({}): {}\ncandidate ({}): {}\n",
+        baseline.label,
+        baseline.totals(),
+        candidate.label,
+        candidate.totals()
+    );
+    let find = |report: &Report, name: &str| -> Option<bool> {
+        report
+            .scenarios
+            .iter()
+            .find(|s| s.name == name)
+            .map(ScenarioResult::passed)
+    };
+    let mut regressed = Vec::new();
+    let mut fixed = Vec::new();
+    let mut added = Vec::new();
+    for scenario in &candidate.scenarios {
+        match (find(baseline, &scenario.name), scenario.passed()) {
+            (Some(true), false) => regressed.push(scenario.name.as_str()),
+            (Some(false), true) => fixed.push(scenario.name.as_str()),
+            (None, _) => added.push(scenario.name.as_str()),
+            _ => {}
+        }
+    }
+    let removed: Vec<&str> = baseline
+        .scenarios
+        .iter()
+        .filter(|s| find(candidate, &s.name).is_none())
+        .map(|s| s.name.as_str())
+        .collect();
+    let sections = [
+        ("regressed", regressed),
+        ("fixed", fixed),
+        ("only in candidate", added),
+        ("only in baseline", removed),
+    ];
+    if sections.iter().all(|(_, names)| names.is_empty()) {
+        out.push_str("no scenario outcomes changed\n");
+    }
+    for (title, names) in sections {
+        if names.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("{title}:\n"));
+        for name in names {
+            out.push_str(&format!("  - {name}\n"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, failures: &[&str], cost_usd: f64, duration_ms: u64) -> ScenarioResult {
+        ScenarioResult {
+            name: name.to_string(),
+            file: format!("{name}.json"),
+            failures: failures.iter().map(|f| f.to_string()).collect(),
+            turns: vec![TurnOutcome {
+                cost_usd,
+                duration_ms,
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn report(label: &str, scenarios: Vec<ScenarioResult>) -> Report {
+        Report {
+            suite: "evals".to_string(),
+            label: label.to_string(),
+            scenarios,
+        }
+    }
+
+    #[test]
+    fn summary_lists_failures() {
+        let report = report(
+            "sonnet",
+            vec![
+                result("greets", &[], 0.01, 100),
+                result("remembers", &["turn 1: expected tool call x--y"], 0.02, 300),
+            ],
+        );
+        assert_eq!(
+            report.summary(),
+            "evals (sonnet): 1/2 passed, $0.0300, 200 ms/turn\n\
+             PASS greets\n\
+             FAIL remembers\n  \
+             - turn 1: expected tool call x--y\n"
+        );
+    }
+
+    #[test]
+    fn compare_reports_regressions_and_fixes() {
+        let baseline = report(
+            "a",
+            vec![
+                result("greets", &[], 0.0, 0),
+                result("remembers", &["nope"], 0.0, 0),
+                result("dropped", &[], 0.0, 0),
+            ],
+        );
+        let candidate = report(
+            "b",
+            vec![
+                result("greets", &["nope"], 0.0, 0),
+                result("remembers", &[], 0.0, 0),
+                result("new", &[], 0.0, 0),
+            ],
+        );
+        assert_eq!(
+            compare(&baseline, &candidate),
+            "baseline (a): 2/3 passed, $0.0000, 0 ms/turn\n\
+             candidate (b): 2/3 passed, $0.0000, 0 ms/turn\n\
+             regressed:\n  - greets\n\
+             fixed:\n  - remembers\n\
+             only in candidate:\n  - new\n\
+             only in baseline:\n  - dropped\n"
+        );
+        assert!(compare(&baseline, &baseline).ends_with("no scenario outcomes changed\n"));
+    }
+
+    #[test]
+    fn report_round_trips() {
+        let report = report("a", vec![result("greets", &[], 0.5, 10)]);
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"costUsd\":0.5"));
+        assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), report);
+    }
+}
@@ -0,0 +1,250 @@
+//! Scenario files and the checks run against each turn.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A scripted conversation with expectations, loaded from
+/// a `.json` file in a suite directory.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Scenario {
+    pub name: String,
+    /// Memories to create before the first turn.
+    #[serde(default)]
+    pub memory: BTreeMap<String, String>,
+    pub turns: Vec<ScenarioTurn>,
+    /// Memories that must contain the given text after the
+    /// last turn. An empty string asserts the memory is absent.
+    #[serde(default)]
+    pub expect_memory: BTreeMap<String, String>,
+    /// Files under the host dir that must contain the given
+    /// text after the last turn.
+    #[serde(default)]
+    pub expect_files: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioTurn {
+    pub input: String,
+    /// Responses for the stub LLM (`asterbot/stub`), in the format
+    /// of core's `stub-llm.json`. Ignored by real models.
+    #[serde(default)]
+    pub stub: Vec<Value>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Expect {
+    /// Tools that must be called, in this order. Names are either
+    /// as the LLM sees them ("asterbot-memory--memory-get") or as
+    /// "component/function" ("asterbot:memory/memory/get").
+    #[serde(default)]
+    pub tool_calls: Vec<String>,
+    #[serde(default)]
+    pub forbidden_tools: Vec<String>,
+    /// Case-insensitive substrings the reply must contain.
+    #[serde(default)]
+    pub reply_contains: Vec<String>,
+    #[serde(default)]
+    pub reply_not_contains: Vec<String>,
+    pub max_rounds: Option<u32>,
+    /// "completed" or "max-tool-rounds".
+    pub stop_reason: Option<String>,
+    /// Error kind the turn must fail with (e.g. "limit").
+    pub error: Option<String>,
+}
+
+/// What the agent did in a turn.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TurnOutcome {
+    pub input: String,
+    /// The reply, or the error message if the turn failed.
+    pub reply: String,
+    /// Error kind if the turn failed.
+    pub error: Option<String>,
+    pub tool_calls: Vec<String>,
+    pub rounds: u32,
+    pub stop_reason: String,
+    pub cost_usd: f64,
+    pub duration_ms: u64,
+}
+
+impl Expect {
+    /// Returns a description of every unmet expectation.
+    pub fn check(&self, outcome: &TurnOutcome) -> Vec<String> {
+        let mut failures = Vec::new();
+        match (&self.error, &outcome.error) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (Some(expected), Some(actual)) => {
+                failures.push(format!("expected a {expected} error, got {actual}"));
+            }
+            (Some(expected), None) => {
+                failures.push(format!("expected a {expected} error, got a reply"));
+            }
+            (None, Some(actual)) => {
+                failures.push(format!("turn failed ({actual}): {}", outcome.reply));
+                return failures;
+            }
+            (None, None) => {}
+        }
+        let mut called = outcome.tool_calls.iter();
+        for expected in &self.tool_calls {
+            let name = normalise_tool_name(expected);
+            if !called.any(|c| *c == name) {
+                failures.push(format!("expected tool call {name}"));
+                break;
+            }
+        }
+        for forbidden in &self.forbidden_tools {
+            let name = normalise_tool_name(forbidden);
+            if outcome.tool_calls.contains(&name) {
+                failures.push(format!("unexpected tool call {name}"));
+            }
+        }
+        let reply = outcome.reply.to_lowercase();
+        for text in &self.reply_contains {
+            if !reply.contains(&text.to_lowercase()) {
+                failures.push(format!("reply does not contain \"{text}\""));
+            }
+        }
+        for text in &self.reply_not_contains {
+            if reply.contains(&text.to_lowercase()) {
+                failures.push(format!("reply contains \"{text}\""));
+            }
+        }
+        if let Some(max) = self.max_rounds {
+            if outcome.rounds > max {
+                failures.push(format!("used {} tool rounds (max {max})", outcome.rounds));
+            }
+        }
+        if let Some(stop_reason) = &self.stop_reason {
+            if *stop_reason != outcome.stop_reason {
+                failures.push(format!(
+                    "stopped with {} (expected {stop_reason})",
+                    outcome.stop_reason
+                ));
+            }
+        }
+        failures
+    }
+}
+
+/// Converts "component/function" to the tool name core gives
+/// the LLM, e.g. "asterbot:memory/memory/get" →
+/// "asterbot-memory--memory-get". Other names are unchanged.
+pub fn normalise_tool_name(name: &str) -> String {
+    match name.split_once('/') {
+        Some((component, function)) if component.contains(':') => {
+            format!(
+                "{}--{}",
+                component.replace(':', "-"),
+                function.replace('/', "-")
+            )
+        }
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(reply: &str, tool_calls: &[&str]) -> TurnOutcome {
+        TurnOutcome {
+            input: "hi".to_string(),
+            reply: reply.to_string(),
+            tool_calls: tool_calls.iter().map(|s| s.to_string()).collect(),
+            rounds: tool_calls.len() as u32,
+            stop_reason: "completed".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scenario_parses_with_defaults() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{
+                "name": "greets",
+                "turns": [{"input": "hi", "expect": {"replyContains": ["hello"]}}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(scenario.turns.len(), 1);
+        assert!(scenario.memory.is_empty());
+        assert_eq!(scenario.turns[0].expect.reply_contains, ["hello"]);
+        assert!(scenario.turns[0].stub.is_empty());
+    }
+
+    #[test]
+    fn tool_names_are_normalised() {
+        assert_eq!(
+            normalise_tool_name("asterbot:memory/memory/get"),
+            "asterbot-memory--memory-get"
+        );
+        assert_eq!(
+            normalise_tool_name("asterbot-memory--memory-get"),
+            "asterbot-memory--memory-get"
+        );
+    }
+
+    #[test]
+    fn tool_calls_must_appear_in_order() {
+        let expect = Expect {
+            tool_calls: vec!["asterbot:memory/memory/list-all".into(), "a--b".into()],
+            ..Default::default()
+        };
+        let ok = outcome("", &["asterbot-memory--memory-list-all", "x--y", "a--b"]);
+        assert!(expect.check(&ok).is_empty());
+        let reversed = outcome("", &["a--b", "asterbot-memory--memory-list-all"]);
+        assert_eq!(expect.check(&reversed), ["expected tool call a--b"]);
+    }
+
+    #[test]
+    fn reply_and_limits_are_checked() {
+        let expect = Expect {
+            forbidden_tools: vec!["a--b".into()],
+            reply_contains: vec!["LISBON".into()],
+            reply_not_contains: vec!["sorry".into()],
+            max_rounds: Some(0),
+            stop_reason: Some("max-tool-rounds".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            expect.check(&outcome("Sorry, no idea.", &["a--b"])),
+            [
+                "unexpected tool call a--b",
+                "reply does not contain \"LISBON\"",
+                "reply contains \"sorry\"",
+                "used 1 tool rounds (max 0)",
+                "stopped with completed (expected max-tool-rounds)",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_must_be_expected() {
+        let failed = TurnOutcome {
+            reply: "daily budget exceeded".into(),
+            error: Some("limit".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Expect::default().check(&failed),
+            ["turn failed (limit): daily budget exceeded"]
+        );
+        let expect = Expect {
+            error: Some("limit".into()),
+            ..Default::default()
+        };
+        assert!(expect.check(&failed).is_empty());
+        assert_eq!(
+            expect.check(&outcome("fine", &[])),
+            ["expected a limit error, got a reply"]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Who may talk to the bot.
+//!
+//! Every gateway is disabled by default. An allowlist restricts
+//! access to the listed senders; `<PREFIX>_PUBLIC=true` allows
+//! everyone. If both are set, the allowlist wins.
+
+use crate::phone::normalise_phone;
+
+/// How sender ids are compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdKind {
+    /// Opaque platform user ids, compared as trimmed strings.
+    User,
+    /// Phone numbers, compared in E.164 form.
+    Phone,
+}
+
+impl IdKind {
+    pub fn normalise(self, id: &str) -> Option<String> {
+        match self {
+            IdKind::User => Some(id.trim().to_string()).filter(|s| !s.is_empty()),
+            IdKind::Phone => normalise_phone(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessMode {
+    AllowList(Vec<String>),
+    Public,
+    Disabled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    pub mode: AccessMode,
+    kind: IdKind,
+    /// Names the settings in the warning logged while disabled.
+    hint: String,
+}
+
+impl Access {
+    /// Reads the comma-separated allowlist from `list_var` and
+    /// the public flag from `public_var`.
+    pub fn from_env(list_var: &str, public_var: &str, kind: IdKind) -> Self {
+        let list = std::env::var(list_var).unwrap_or_default();
+        let is_public = std::env::var(public_var)
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let access = Access::new(&list, is_public, kind)
+            .with_hint(format!("set {list_var} or {public_var}=true"));
+        if matches!(access.mode, AccessMode::AllowList(_)) && is_public {
+            eprintln!("{list_var} and {public_var}=true are both set; using {list_var}");
+        }
+        access
+    }
+
+    /// Entries that are not valid ids are ignored.
+    pub fn new(list: &str, is_public: bool, kind: IdKind) -> Self {
+        let allowed: Vec<String> = list.split(',').filter_map(|s| kind.normalise(s)).collect();
+        let mode = match (allowed.is_empty(), is_public) {
+            (false, _) => AccessMode::AllowList(allowed),
+            (true, true) => AccessMode::Public,
+            (true, false) => AccessMode::Disabled,
+        };
+        Access {
+            mode,
+            kind,
+            hint: String::new(),
+        }
+    }
+
+    pub fn with_hint(mut self, hint: String) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    pub fn allows(&self, sender: &str) -> bool {
+        match &self.mode {
+            AccessMode::AllowList(ids) => self
+                .kind
+                .normalise(sender)
+                .is_some_and(|id| ids.contains(&id)),
+            AccessMode::Public => true,
+            AccessMode::Disabled => {
+                eprintln!("gateway disabled: {}", self.hint);
+                false
+            }
+        }
+    }
+
+    /// The allowlisted ids, empty unless in allowlist mode.
+    pub fn allowlist(&self) -> &[String] {
+        match &self.mode {
+            AccessMode::AllowList(ids) => ids,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_by_default() {
+        let access = Access::new("", false, IdKind::User);
+        assert_eq!(access.mode, AccessMode::Disabled);
+        assert!(!access.allows("123"));
+    }
+
+    #[test]
+    fn allowlist_takes_priority_over_public() {
+        let access = Access::new(" 123, ,456", true, IdKind::User);
+        assert_eq!(
+            access.mode,
+            AccessMode::AllowList(vec!["123".to_string(), "456".to_string()])
+        );
+        assert!(access.allows("456"));
+        assert!(!access.allows("789"));
+        assert!(Access::new("", true, IdKind::User).allows("789"));
+    }
+
+    #[test]
+    fn phone_allowlists_match_any_format() {
+        // WhatsApp sends digits only; Twilio sends E.164.
+        let access = Access::new("14155550123,+44 20 7946 0958", false, IdKind::Phone);
+        assert_eq!(access.allowlist(), ["+14155550123", "+442079460958"]);
+        assert!(access.allows("14155550123"));
+        assert!(access.allows("+14155550123"));
+        assert!(access.allows("442079460958"));
+        assert!(!access.allows("+14155550124"));
+        assert!(!access.allows("not a number"));
+    }
+
+    #[test]
+    fn invalid_entries_are_ignored() {
+        let access = Access::new("0612345678", false, IdKind::Phone);
+        assert_eq!(access.mode, AccessMode::Disabled);
+    }
+}
@@ -0,0 +1,319 @@
+//! Splitting replies that exceed a platform's message limit.
+
+/// Closes a code fence that had to be split across parts.
+const FENCE_CLOSE: &str = "\n```";
+
+/// Per-platform reply limits: the longest message the platform
+/// accepts, and how many messages one reply may be sent as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Splitter {
+    pub max_chars: usize,
+    pub max_parts: usize,
+}
+
+impl Splitter {
+    pub const DISCORD: Splitter = Splitter {
+        max_chars: 2000,
+        max_parts: 5,
+    };
+    pub const TELEGRAM: Splitter = Splitter {
+        max_chars: 4096,
+        max_parts: 5,
+    };
+    /// WhatsApp accepts 4096 characters per text message.
+    pub const WHATSAPP: Splitter = Splitter {
+        max_chars: 4096,
+        max_parts: 5,
+    };
+    /// Twilio joins long SMS into one message of up to 1600
+    /// characters, billed per segment, so fewer parts are sent.
+    pub const SMS: Splitter = Splitter {
+        max_chars: 1600,
+        max_parts: 3,
+    };
+
+    /// `default` with `max_parts` overridden by `<PREFIX>_MAX_PARTS`.
+    pub fn from_env(prefix: &str, default: Splitter) -> Self {
+        let max_parts = std::env::var(format!("{prefix}_MAX_PARTS"))
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&n: &usize| n > 0)
+            .unwrap_or(default.max_parts);
+        Splitter {
+            max_parts,
+            ..default
+        }
+    }
+
+    /// Splits `text` into at most `max_parts` messages. When there
+    /// is more than one, each ends with its number, e.g. " (2/3)",
+    /// and if the cap cuts the reply short the last is marked
+    /// " (3/3, truncated)".
+    pub fn split(&self, text: &str) -> Vec<String> {
+        if text.trim().chars().count() <= self.max_chars {
+            return chunk(text, self.max_chars);
+        }
+        let max_parts = self.max_parts.max(1);
+        let reserved = suffix(max_parts, max_parts, true).chars().count();
+        let mut parts = chunk(text, self.max_chars.saturating_sub(reserved));
+        let truncated = parts.len() > max_parts;
+        parts.truncate(max_parts);
+        let total = parts.len();
+        for (i, part) in parts.iter_mut().enumerate() {
+            let suffix = suffix(i + 1, total, truncated && i + 1 == total);
+            // Text after a closing fence would stop it closing.
+            match part.ends_with("```") {
+                true => part.push_str(&format!("\n{}", suffix.trim_start())),
+                false => part.push_str(&suffix),
+            }
+        }
+        parts
+    }
+}
+
+fn suffix(n: usize, total: usize, truncated: bool) -> String {
+    match truncated {
+        true => format!(" ({n}/{total}, truncated)"),
+        false => format!(" ({n}/{total})"),
+    }
+}
+
+/// Splits `text` into parts of at most `max_chars` characters.
+///
+/// Breaks go at the last paragraph break, line break, sentence
+/// end or space before the limit, in that order of preference,
+/// as long as that keeps at least half the limit in the part.
+/// Code fences are kept whole where possible; a fence longer than
+/// the limit is closed at a line break and reopened in the next
+/// part. Words longer than the limit are cut.
+pub fn chunk(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut parts = Vec::new();
+    let mut rest = text.trim().to_string();
+    while rest.chars().count() > max_chars {
+        let fences = fences(&rest);
+        let limit = byte_index(&rest, max_chars);
+        let inside = |i: usize| fences.iter().any(|f| f.start < i && i < f.end);
+        if let Some(cut) = best_cut(&rest[..limit], inside) {
+            parts.push(rest[..cut].trim_end().to_string());
+            rest = rest[cut..].trim_start().to_string();
+            continue;
+        }
+        let fence = fences.iter().find(|f| f.start < limit && limit < f.end);
+        let close_at = byte_index(&rest, max_chars.saturating_sub(FENCE_CLOSE.len()));
+        match fence {
+            Some(fence) if fence.start == 0 && close_at > fence.body + 1 => {
+                let cut = rest[..close_at]
+                    .rfind('\n')
+                    .filter(|&i| i > fence.body)
+                    .unwrap_or(close_at);
+                parts.push(format!("{}{FENCE_CLOSE}", rest[..cut].trim_end()));
+                rest = format!("{}\n{}", fence.header, rest[cut..].trim_start_matches('\n'));
+            }
+            _ => {
+                parts.push(rest[..limit].to_string());
+                rest = rest[limit..].trim_start().to_string();
+            }
+        }
+    }
+    if !rest.is_empty() || parts.is_empty() {
+        parts.push(rest);
+    }
+    parts
+}
+
+/// A fenced code block in the text being split.
+struct Fence {
+    /// Start of the opening fence line.
+    start: usize,
+    /// End of the opening fence line.
+    body: usize,
+    /// End of the closing fence line, or of the text if unclosed.
+    end: usize,
+    /// The opening fence line, used to reopen the block.
+    header: String,
+}
+
+fn fences(text: &str) -> Vec<Fence> {
+    let mut fences = Vec::new();
+    let mut open: Option<(usize, usize, &str)> = None;
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let line_end = offset + line.len();
+        if line.trim_start().starts_with("```") {
+            match open.take() {
+                Some((start, body, header)) => fences.push(Fence {
+                    start,
+                    body,
+                    end: line_end,
+                    header: header.to_string(),
+                }),
+                None => open = Some((offset, line_end, line.trim())),
+            }
+        }
+        offset = line_end + 1;
+    }
+    if let Some((start, body, header)) = open {
+        fences.push(Fence {
+            start,
+            body,
+            end: text.len(),
+            header: header.to_string(),
+        });
+    }
+    fences
+}
+
+/// The best place to break `window`, outside code fences.
+fn best_cut(window: &str, inside: impl Fn(usize) -> bool) -> Option<usize> {
+    // Last paragraph break, line break, sentence end and space.
+    let mut last = [0usize; 4];
+    let mut prev = '\0';
+    for (i, c) in window.char_indices() {
+        let rank = match c {
+            '\n' if window[i + 1..].starts_with('\n') => Some(0),
+            '\n' => Some(1),
+            ' ' if matches!(prev, '.' | '!' | '?') => Some(2),
+            ' ' => Some(3),
+            _ => None,
+        };
+        prev = c;
+        if let Some(rank) = rank
+            && i > 0
+            && !inside(i)
+        {
+            last[rank] = i;
+        }
+    }
+    let half = window.len() / 2;
+    last.iter()
+        .copied()
+        .find(|&i| i >= half && i > 0)
+        .or_else(|| last.iter().copied().find(|&i| i > 0))
+}
+
+fn byte_index(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_one_part() {
+        assert_eq!(chunk("hello", 10), ["hello"]);
+        assert_eq!(chunk("", 10), [""]);
+    }
+
+    #[test]
+    fn splits_at_newlines_then_spaces() {
+        assert_eq!(chunk("one two\nthree four", 12), ["one two", "three four"]);
+        assert_eq!(chunk("one two three four", 9), ["one two", "three", "four"]);
+    }
+
+    #[test]
+    fn prefers_paragraphs_then_sentences() {
+        let text = "The first paragraph.\n\nSecond one. It goes on";
+        assert_eq!(
+            chunk(text, 40),
+            ["The first paragraph.", "Second one. It goes on"]
+        );
+        let text = "One sentence here. Another one follows it";
+        assert_eq!(
+            chunk(text, 30),
+            ["One sentence here.", "Another one follows it"]
+        );
+    }
+
+    #[test]
+    fn long_words_are_cut() {
+        assert_eq!(chunk("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn counts_chars_not_bytes() {
+        let parts = chunk("héllo wörld ça va", 6);
+        assert_eq!(parts, ["héllo", "wörld", "ça va"]);
+        assert!(parts.iter().all(|p| p.chars().count() <= 6));
+    }
+
+    #[test]
+    fn code_fences_stay_whole() {
+        let text = "Try this:\n```sh\nls -la /tmp\necho done\n```\nThat's all.";
+        assert_eq!(
+            chunk(text, 40),
+            [
+                "Try this:",
+                "```sh\nls -la /tmp\necho done\n```",
+                "That's all."
+            ]
+        );
+    }
+
+    #[test]
+    fn long_code_fences_are_reopened() {
+        let code: Vec<String> = (0..8).map(|i| format!("let x{i} = {i};")).collect();
+        let text = format!("```rust\n{}\n```", code.join("\n"));
+        let parts = chunk(&text, 60);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.starts_with("```rust\n"), "{part}");
+            assert!(part.ends_with("\n```"), "{part}");
+            assert!(part.chars().count() <= 60, "{part}");
+        }
+        let body: Vec<&str> = parts
+            .iter()
+            .flat_map(|p| p.lines().filter(|l| !l.starts_with("```")))
+            .collect();
+        assert_eq!(body, code);
+    }
+
+    #[test]
+    fn parts_are_numbered() {
+        let splitter = Splitter {
+            max_chars: 40,
+            max_parts: 5,
+        };
+        assert_eq!(splitter.split("short"), ["short"]);
+        let parts = splitter.split("One two three. Four five six. Seven eight nine.");
+        assert_eq!(
+            parts,
+            [
+                "One two three. (1/3)",
+                "Four five six. (2/3)",
+                "Seven eight nine. (3/3)"
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_follow_closing_fences() {
+        let splitter = Splitter {
+            max_chars: 40,
+            max_parts: 5,
+        };
+        let parts = splitter.split("```\nsome code here\n```\nAnd the explanation after.");
+        assert_eq!(parts[0], "```\nsome code here\n```\n(1/3)");
+    }
+
+    #[test]
+    fn parts_are_capped() {
+        let splitter = Splitter {
+            max_chars: 40,
+            max_parts: 2,
+        };
+        let parts = splitter.split("aaaa bbbb cccc dddd eeee ffff gggg hhhh iiii");
+        assert_eq!(
+            parts,
+            [
+                "aaaa bbbb cccc dddd (1/2)",
+                "eeee ffff gggg hhhh (2/2, truncated)"
+            ]
+        );
+        assert!(parts.iter().all(|p| p.chars().count() <= 40));
+    }
+}
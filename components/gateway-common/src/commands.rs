@@ -0,0 +1,747 @@
+//! Slash commands handled by the gateway before a message
+//! reaches the agent, e.g. `/reset` or `/model <name>`.
+//!
+//! Commands act on the shared asterbot state through dynamic
+//! calls to `asterbot:history`, `asterbot:memory` and
+//! `asterbot:toolkit`, and on `MODEL.txt` in the host dir, which
+//! asterbot:core reads in place of `ASTERBOT_MODEL` for every
+//! session until `/model default` removes it. `/reset`, `/undo`
+//! and `/status` act on the sender's session only; in group
+//! sessions, which everyone in the chat shares, only admins may
+//! reset or undo. `/status` adds the memory count and the day's
+//! usage across all sessions for admins only. `/block` and
+//! `/unblock` edit the gateway's [`Blocklist`].
+
+use crate::access::IdKind;
+use crate::limit::Blocklist;
+use crate::session::SessionKey;
+use crate::{Host, resolve_host_dir};
+use asterbot_common::usage::{Ledger, ModelUsage, USAGE_FILENAME};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+const MODEL_FILENAME: &str = "MODEL.txt";
+
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub description: &'static str,
+    /// Only admins may run it.
+    pub admin_only: bool,
+    /// Only admins may run it in group sessions, where it acts
+    /// on everyone's conversation.
+    pub admin_only_in_groups: bool,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "",
+        description: "List the available commands",
+        admin_only: false,
+        admin_only_in_groups: false,
+    },
+    Command {
+        name: "status",
+        args: "",
+        description: "Show the model, conversation length and usage",
+        admin_only: false,
+        admin_only_in_groups: false,
+    },
+    Command {
+        name: "reset",
+        args: "",
+        description: "Clear this conversation's history",
+        admin_only: false,
+        admin_only_in_groups: true,
+    },
+    Command {
+        name: "undo",
+        args: "",
+        description: "Remove the last message and its reply",
+        admin_only: false,
+        admin_only_in_groups: true,
+    },
+    Command {
+        name: "tools",
+        args: "",
+        description: "List the tools the agent can use",
+        admin_only: false,
+        admin_only_in_groups: false,
+    },
+    Command {
+        name: "memory",
+        args: "list",
+        description: "List stored memories",
+        admin_only: true,
+        admin_only_in_groups: true,
+    },
+    Command {
+        name: "model",
+        args: "[name|default]",
+        description: "Show or switch the model",
+        admin_only: true,
+        admin_only_in_groups: true,
+    },
+    Command {
+        name: "block",
+        args: "[id]",
+        description: "Block a sender, or list blocked senders",
+        admin_only: true,
+        admin_only_in_groups: true,
+    },
+    Command {
+        name: "unblock",
+        args: "<id>",
+        description: "Unblock a sender",
+        admin_only: true,
+        admin_only_in_groups: true,
+    },
+];
+
+/// A command that was handled, and the reply to send.
+pub struct Handled {
+    pub command: String,
+    pub reply: String,
+}
+
+/// The commands a gateway accepts and who may run admin-only ones.
+pub struct Commands {
+    enabled: Vec<&'static Command>,
+    admins: Vec<String>,
+    kind: IdKind,
+    host_dir: String,
+    blocklist: Option<Blocklist>,
+}
+
+impl Commands {
+    /// Reads `{prefix}_COMMANDS` (comma-separated names to enable,
+    /// all by default, "none" to pass every message to the agent)
+    /// and `{prefix}_ADMIN_IDS`. Without admin ids, `default_admins`
+    /// (the gateway's normalised allowlist) are admins.
+    pub fn from_env(prefix: &str, kind: IdKind, default_admins: &[String]) -> Self {
+        let names = std::env::var(format!("{prefix}_COMMANDS")).unwrap_or_default();
+        let admins = std::env::var(format!("{prefix}_ADMIN_IDS")).unwrap_or_default();
+        let mut commands = Commands::new(&names, &admins, kind, resolve_host_dir())
+            .with_blocklist(Blocklist::from_env(prefix, kind));
+        if commands.admins.is_empty() {
+            commands.admins = default_admins.to_vec();
+        }
+        commands
+    }
+
+    /// `names` and `admins` are comma-separated, as in the env vars.
+    pub fn new(names: &str, admins: &str, kind: IdKind, host_dir: String) -> Self {
+        let names: Vec<&str> = names
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        let enabled = COMMANDS
+            .iter()
+            .filter(|c| names.is_empty() || names.contains(&c.name))
+            .collect();
+        Commands {
+            enabled,
+            admins: admins
+                .split(',')
+                .filter_map(|s| kind.normalise(s))
+                .collect(),
+            kind,
+            host_dir,
+            blocklist: None,
+        }
+    }
+
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = Some(blocklist);
+        self
+    }
+
+    pub fn is_admin(&self, sender: &str) -> bool {
+        self.kind
+            .normalise(sender)
+            .is_some_and(|id| self.admins.contains(&id))
+    }
+
+    /// Whether `sender` may run `command` in `session`.
+    fn may_run(&self, command: &Command, sender: &str, session: &SessionKey) -> bool {
+        let is_restricted =
+            command.admin_only || (command.admin_only_in_groups && session.is_group());
+        !is_restricted || self.is_admin(sender)
+    }
+
+    /// Runs `text` if it is a command, sent by `sender` in
+    /// `session`. Returns `None` for messages that should go to
+    /// the agent.
+    pub fn handle(
+        &self,
+        host: &dyn Host,
+        text: &str,
+        sender: &str,
+        session: &SessionKey,
+    ) -> Option<Handled> {
+        if self.enabled.is_empty() {
+            return None;
+        }
+        let (name, args) = parse(text)?;
+        let reply = match self.enabled.iter().find(|c| c.name == name) {
+            None => format!("Unknown command /{name}. Send /help for a list."),
+            Some(c) if !self.may_run(c, sender, session) => match c.admin_only {
+                true => format!("/{name} is restricted to admins."),
+                false => format!("/{name} is restricted to admins in groups."),
+            },
+            Some(_) => self
+                .run(host, &name, args, sender, session)
+                .unwrap_or_else(|e| format!("/{name} failed: {e}")),
+        };
+        Some(Handled {
+            command: name,
+            reply,
+        })
+    }
+
+    fn run(
+        &self,
+        host: &dyn Host,
+        name: &str,
+        args: &str,
+        sender: &str,
+        session: &SessionKey,
+    ) -> Result<String, String> {
+        match name {
+            "help" => Ok(self.help(sender, session)),
+            "status" => Ok(self.status(host, sender, session)),
+            "reset" => {
+                let args = serde_json::json!([session.as_str()]).to_string();
+                call(host, "asterbot:history", "history/clear-session", &args)?;
+                Ok("Conversation cleared.".to_string())
+            }
+            "undo" => undo(host, session),
+            "tools" => list_tools(host),
+            "memory" => match args {
+                "" | "list" => list_memories(host),
+                _ => Ok("Usage: /memory list".to_string()),
+            },
+            "model" => self.switch_model(args),
+            "block" => self.block(args),
+            "unblock" => self.unblock(args),
+            _ => Err("not implemented".to_string()),
+        }
+    }
+
+    fn help(&self, sender: &str, session: &SessionKey) -> String {
+        let mut out = String::from("Commands:");
+        for c in self
+            .enabled
+            .iter()
+            .filter(|c| self.may_run(c, sender, session))
+        {
+            let usage = match c.args.is_empty() {
+                true => format!("/{}", c.name),
+                false => format!("/{} {}", c.name, c.args),
+            };
+            out.push_str(&format!("\n{usage} - {}", c.description));
+        }
+        out.push_str("\nAnything else is sent to the agent.");
+        out
+    }
+
+    /// The model `/model` set, if any.
+    fn chosen_model(&self) -> Option<String> {
+        std::fs::read_to_string(format!("{}/{MODEL_FILENAME}", self.host_dir))
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// "Model: <name>", saying where it was set.
+    fn model_line(&self) -> String {
+        match self.chosen_model() {
+            Some(model) => format!("Model: {model} (set with /model, in place of ASTERBOT_MODEL)"),
+            None => format!(
+                "Model: {} (ASTERBOT_MODEL)",
+                std::env::var("ASTERBOT_MODEL").unwrap_or_default()
+            ),
+        }
+    }
+
+    fn switch_model(&self, args: &str) -> Result<String, String> {
+        if self.host_dir.is_empty() {
+            return Err("no host dir to store the model in".to_string());
+        }
+        let path = format!("{}/{MODEL_FILENAME}", self.host_dir);
+        match args {
+            "" => Ok(self.model_line()),
+            "default" => {
+                let _ = std::fs::remove_file(&path);
+                Ok(format!("Model reset. {}", self.model_line()))
+            }
+            model if model.contains('/') && !model.contains(char::is_whitespace) => {
+                std::fs::write(&path, model).map_err(|e| format!("failed to write {path}: {e}"))?;
+                Ok(format!(
+                    "Model set to {model} for every conversation, in place of \
+                     ASTERBOT_MODEL. Send /model default to go back."
+                ))
+            }
+            _ => Ok("Usage: /model <provider/model>, e.g. /model openai/gpt-4o".to_string()),
+        }
+    }
+
+    fn blocklist(&self) -> Result<&Blocklist, String> {
+        self.blocklist
+            .as_ref()
+            .ok_or_else(|| "no blocklist".to_string())
+    }
+
+    fn block(&self, args: &str) -> Result<String, String> {
+        let blocklist = self.blocklist()?;
+        if args.is_empty() {
+            let ids = blocklist.ids();
+            return Ok(match ids.is_empty() {
+                true => "No senders blocked.".to_string(),
+                false => format!("Blocked: {}", ids.join(", ")),
+            });
+        }
+        if self.is_admin(args) {
+            return Ok(format!("{args} is an admin and can't be blocked."));
+        }
+        Ok(match blocklist.block(args)? {
+            true => format!("Blocked {args}."),
+            false => format!("{args} is already blocked."),
+        })
+    }
+
+    fn unblock(&self, args: &str) -> Result<String, String> {
+        if args.is_empty() {
+            return Ok("Usage: /unblock <id>".to_string());
+        }
+        Ok(match self.blocklist()?.unblock(args)? {
+            true => format!("Unblocked {args}."),
+            false => format!("{args} isn't blocked."),
+        })
+    }
+
+    /// The model and `session`'s length and usage. Admins also
+    /// see what spans every session: the memory count and the
+    /// day's usage.
+    fn status(&self, host: &dyn Host, sender: &str, session: &SessionKey) -> String {
+        let is_admin = self.is_admin(sender);
+        let mut out = self.model_line();
+        match load_history(host, session) {
+            Ok(messages) => out.push_str(&format!("\nConversation: {} messages", messages.len())),
+            Err(e) => out.push_str(&format!("\nConversation: unavailable ({e})")),
+        }
+        if is_admin && let Ok(json) = call(host, "asterbot:memory", "memory/list-all", "[]") {
+            let count = serde_json::from_str::<Vec<String>>(&json)
+                .map(|names| names.len())
+                .unwrap_or(0);
+            out.push_str(&format!("\nMemories: {count}"));
+        }
+        let ledger = Ledger::load(&format!("{}/{USAGE_FILENAME}", self.host_dir));
+        if is_admin && let Some((day, models)) = ledger.days.iter().next_back() {
+            out.push_str(&format!("\nUsage on {day}: {}", usage_totals(models)));
+        }
+        if let Some(models) = ledger.sessions.get(session.as_str()) {
+            out.push_str(&format!(
+                "\nUsage in this conversation: {}",
+                usage_totals(models)
+            ));
+        }
+        out
+    }
+}
+
+/// Calls and tokens summed over a ledger entry's models.
+fn usage_totals(models: &BTreeMap<String, ModelUsage>) -> String {
+    let total = |field: fn(&ModelUsage) -> u64| -> u64 { models.values().map(field).sum() };
+    format!(
+        "{} LLM calls, {} input / {} output tokens",
+        total(|u| u.calls),
+        total(|u| u.input_tokens),
+        total(|u| u.output_tokens)
+    )
+}
+
+/// Splits "/name args" into a lowercase name and its arguments.
+/// A "@bot" suffix on the name ("/reset@mybot", as Telegram sends
+/// in groups) is dropped. Text such as "/usr/bin" is not a command.
+pub fn parse(text: &str) -> Option<(String, &str)> {
+    let rest = text.trim().strip_prefix('/')?;
+    let (word, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let name = word.split('@').next().unwrap_or_default();
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    is_name.then(|| (name.to_ascii_lowercase(), args.trim()))
+}
+
+fn call(host: &dyn Host, component: &str, function: &str, args: &str) -> Result<String, String> {
+    host.call_component_function(component, function, args)
+        .map_err(|e| format!("{component}/{function}: {e}"))
+}
+
+fn load_history(host: &dyn Host, session: &SessionKey) -> Result<Vec<Value>, String> {
+    let args = serde_json::json!([session.as_str()]).to_string();
+    let json = call(host, "asterbot:history", "history/load-session", &args)?;
+    serde_json::from_str(&json).map_err(|e| format!("invalid history: {e}"))
+}
+
+/// Drops the last user message and everything after it.
+fn undo(host: &dyn Host, session: &SessionKey) -> Result<String, String> {
+    let mut messages = load_history(host, session)?;
+    let Some(last) = messages
+        .iter()
+        .rposition(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+    else {
+        return Ok("Nothing to undo.".to_string());
+    };
+    messages.truncate(last);
+    let args = serde_json::json!([session.as_str(), messages]).to_string();
+    call(host, "asterbot:history", "history/save-session", &args)?;
+    Ok("Removed the last message and its reply.".to_string())
+}
+
+fn list_memories(host: &dyn Host) -> Result<String, String> {
+    let json = call(host, "asterbot:memory", "memory/list-all", "[]")?;
+    let names: Vec<String> =
+        serde_json::from_str(&json).map_err(|e| format!("invalid memory list: {e}"))?;
+    if names.is_empty() {
+        return Ok("No memories stored.".to_string());
+    }
+    let mut out = format!("{} memories:", names.len());
+    for name in names {
+        out.push_str(&format!("\n- {name}"));
+    }
+    Ok(out)
+}
+
+/// One line per tool function: its component, function and the
+/// first line of its description.
+fn list_tools(host: &dyn Host) -> Result<String, String> {
+    let json = call(host, "asterbot:toolkit", "toolkit/list-tools", "[]")?;
+    let tools: Vec<Value> =
+        serde_json::from_str(&json).map_err(|e| format!("invalid tool list: {e}"))?;
+    if tools.is_empty() {
+        return Ok("No tools configured.".to_string());
+    }
+    let field = |tool: &Value, key: &str| {
+        tool.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut out = format!("{} tools:", tools.len());
+    for tool in &tools {
+        out.push_str(&format!(
+            "\n- {} {}",
+            field(tool, "component-name"),
+            field(tool, "function-name")
+        ));
+        let description = field(tool, "description");
+        if let Some(line) = description.lines().map(str::trim).find(|l| !l.is_empty()) {
+            out.push_str(&format!(": {line}"));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Serves a fixed history and memory list, recording calls.
+    struct FakeHost {
+        history: &'static str,
+        memories: &'static str,
+        calls: RefCell<Vec<(String, String)>>,
+    }
+
+    impl FakeHost {
+        fn new(history: &'static str) -> Self {
+            FakeHost {
+                history,
+                memories: r#"["user_city","user_name"]"#,
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Host for FakeHost {
+        fn call_component_function(
+            &self,
+            _component: &str,
+            function: &str,
+            args_json: &str,
+        ) -> Result<String, String> {
+            self.calls
+                .borrow_mut()
+                .push((function.to_string(), args_json.to_string()));
+            match function {
+                "history/load-session" => Ok(self.history.to_string()),
+                "memory/list-all" => Ok(self.memories.to_string()),
+                "toolkit/list-tools" => Ok(TOOLS.to_string()),
+                "history/clear-session" | "history/save-session" => Ok("null".to_string()),
+                _ => Err(format!("unknown function {function}")),
+            }
+        }
+    }
+
+    const HISTORY: &str = r#"[
+        {"role":"user","content":"hi"},
+        {"role":"assistant","content":"hello"},
+        {"role":"user","content":"weather?"},
+        {"role":"assistant","content":"","tool-calls":[{"id":"1","name":"w","arguments-json":"{}"}]},
+        {"role":"tool","content":"sunny","tool-call-id":"1"},
+        {"role":"assistant","content":"Sunny."}
+    ]"#;
+
+    const TOOLS: &str = r#"[
+        {"component-name":"asterbot:memory","function-name":"memory/get",
+         "description":"Read a memory.\nReturns an empty string if missing.",
+         "params":[{"name":"name","type-name":"string"}],"return-type":"string"},
+        {"component-name":"asterai:cli","function-name":"cli/run",
+         "description":"","params":[],"return-type":"string"}
+    ]"#;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("gateway-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    fn session(sender: &str) -> SessionKey {
+        SessionKey::direct("test", sender)
+    }
+
+    fn reply(commands: &Commands, host: &FakeHost, text: &str, sender: &str) -> String {
+        commands
+            .handle(host, text, sender, &session(sender))
+            .unwrap()
+            .reply
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("/reset"), Some(("reset".to_string(), "")));
+        assert_eq!(parse("  /Reset@mybot  "), Some(("reset".to_string(), "")));
+        assert_eq!(
+            parse("/model  openai/gpt-4o "),
+            Some(("model".to_string(), "openai/gpt-4o"))
+        );
+        assert_eq!(parse("/usr/bin is a directory"), None);
+        assert_eq!(parse("what does / mean?"), None);
+        assert_eq!(parse("/"), None);
+    }
+
+    #[test]
+    fn ordinary_messages_go_to_the_agent() {
+        let commands = Commands::new("", "", IdKind::User, String::new());
+        assert!(
+            commands
+                .handle(&FakeHost::new("[]"), "hello", "1", &session("1"))
+                .is_none()
+        );
+        let disabled = Commands::new("none", "", IdKind::User, String::new());
+        assert!(
+            disabled
+                .handle(&FakeHost::new("[]"), "/reset", "1", &session("1"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn admin_only_commands_are_checked() {
+        let commands = Commands::new("", "+14155550123", IdKind::Phone, String::new());
+        let host = FakeHost::new("[]");
+        assert_eq!(
+            reply(&commands, &host, "/memory list", "+15550000000"),
+            "/memory is restricted to admins."
+        );
+        assert_eq!(
+            reply(&commands, &host, "/memory list", "14155550123"),
+            "2 memories:\n- user_city\n- user_name"
+        );
+        let help = reply(&commands, &host, "/help", "+15550000000");
+        assert!(help.contains("/reset - Clear this conversation's history"));
+        assert!(!help.contains("/model"));
+        assert!(reply(&commands, &host, "/help", "+14155550123").contains("/model"));
+    }
+
+    #[test]
+    fn group_resets_are_restricted_to_admins() {
+        let commands = Commands::new("", "1", IdKind::User, String::new());
+        let host = FakeHost::new(HISTORY);
+        let group = SessionKey::group("test", "-100123");
+        let run =
+            |text: &str, sender: &str| commands.handle(&host, text, sender, &group).unwrap().reply;
+        assert_eq!(
+            run("/reset", "2"),
+            "/reset is restricted to admins in groups."
+        );
+        assert_eq!(
+            run("/undo", "2"),
+            "/undo is restricted to admins in groups."
+        );
+        assert!(!run("/help", "2").contains("/reset"));
+        assert_eq!(run("/reset", "1"), "Conversation cleared.");
+        // Anyone may reset their own private conversation.
+        assert_eq!(
+            reply(&commands, &host, "/reset", "2"),
+            "Conversation cleared."
+        );
+    }
+
+    #[test]
+    fn unknown_and_disabled_commands_are_reported() {
+        let commands = Commands::new("help,reset", "", IdKind::User, String::new());
+        let host = FakeHost::new("[]");
+        assert_eq!(
+            reply(&commands, &host, "/undo", "1"),
+            "Unknown command /undo. Send /help for a list."
+        );
+        assert_eq!(
+            reply(&commands, &host, "/reset", "1"),
+            "Conversation cleared."
+        );
+        let calls = host.calls.borrow();
+        assert_eq!(calls[0].0, "history/clear-session");
+        assert_eq!(calls[0].1, r#"["test:dm:1"]"#);
+    }
+
+    #[test]
+    fn undo_drops_the_last_exchange() {
+        let commands = Commands::new("", "", IdKind::User, String::new());
+        let host = FakeHost::new(HISTORY);
+        assert_eq!(
+            reply(&commands, &host, "/undo", "1"),
+            "Removed the last message and its reply."
+        );
+        let calls = host.calls.borrow();
+        let (function, args) = &calls[1];
+        assert_eq!(function, "history/save-session");
+        let args: Value = serde_json::from_str(args).unwrap();
+        assert_eq!(
+            args,
+            serde_json::json!([
+                "test:dm:1",
+                [
+                    {"role":"user","content":"hi"},
+                    {"role":"assistant","content":"hello"}
+                ]
+            ])
+        );
+        assert_eq!(
+            reply(&commands, &FakeHost::new("[]"), "/undo", "1"),
+            "Nothing to undo."
+        );
+    }
+
+    #[test]
+    fn tools_are_listed() {
+        let commands = Commands::new("", "", IdKind::User, String::new());
+        assert_eq!(
+            reply(&commands, &FakeHost::new("[]"), "/tools", "1"),
+            "2 tools:\n\
+             - asterbot:memory memory/get: Read a memory.\n\
+             - asterai:cli cli/run"
+        );
+    }
+
+    #[test]
+    fn model_is_switched_through_the_host_dir() {
+        let dir = temp_dir("model");
+        let commands = Commands::new("", "1", IdKind::User, dir.clone());
+        let host = FakeHost::new("[]");
+        assert_eq!(
+            reply(&commands, &host, "/model openai/gpt-4o", "1"),
+            "Model set to openai/gpt-4o for every conversation, in place of \
+             ASTERBOT_MODEL. Send /model default to go back."
+        );
+        assert_eq!(
+            std::fs::read_to_string(format!("{dir}/{MODEL_FILENAME}")).unwrap(),
+            "openai/gpt-4o"
+        );
+        assert_eq!(
+            reply(&commands, &host, "/model", "1"),
+            "Model: openai/gpt-4o (set with /model, in place of ASTERBOT_MODEL)"
+        );
+        assert!(reply(&commands, &host, "/model gpt 4", "1").starts_with("Usage:"));
+        reply(&commands, &host, "/model default", "1");
+        assert!(!std::path::Path::new(&format!("{dir}/{MODEL_FILENAME}")).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn admins_edit_the_blocklist() {
+        let dir = temp_dir("block");
+        let commands = Commands::new("", "1", IdKind::User, String::new())
+            .with_blocklist(Blocklist::new("telegram", IdKind::User, &dir));
+        let host = FakeHost::new("[]");
+        assert_eq!(
+            reply(&commands, &host, "/block 42", "2"),
+            "/block is restricted to admins."
+        );
+        assert_eq!(
+            reply(&commands, &host, "/block", "1"),
+            "No senders blocked."
+        );
+        assert_eq!(reply(&commands, &host, "/block 42", "1"), "Blocked 42.");
+        assert_eq!(
+            reply(&commands, &host, "/block 42", "1"),
+            "42 is already blocked."
+        );
+        assert_eq!(
+            reply(&commands, &host, "/block 1", "1"),
+            "1 is an admin and can't be blocked."
+        );
+        assert_eq!(reply(&commands, &host, "/block", "1"), "Blocked: 42");
+        assert_eq!(reply(&commands, &host, "/unblock 42", "1"), "Unblocked 42.");
+        assert_eq!(
+            reply(&commands, &host, "/unblock 42", "1"),
+            "42 isn't blocked."
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn status_summarises_state() {
+        let dir = temp_dir("status");
+        std::fs::write(format!("{dir}/{MODEL_FILENAME}"), "openai/gpt-4o\n").unwrap();
+        std::fs::write(
+            format!("{dir}/{USAGE_FILENAME}"),
+            r#"{"days":{
+                "2026-03-13":{"openai/gpt-4o":{"calls":9,"inputTokens":9,"outputTokens":9}},
+                "2026-03-14":{
+                    "openai/gpt-4o":{"calls":2,"inputTokens":100,"outputTokens":20},
+                    "openai/gpt-4o-mini":{"calls":1,"inputTokens":50,"outputTokens":5}
+                }
+            },"sessions":{
+                "test:dm:1":{"openai/gpt-4o":{"calls":4,"inputTokens":40,"outputTokens":8}},
+                "test:dm:2":{"openai/gpt-4o":{"calls":7,"inputTokens":70,"outputTokens":7}}
+            }}"#,
+        )
+        .unwrap();
+        let commands = Commands::new("", "1", IdKind::User, dir.clone());
+        let host = FakeHost::new(HISTORY);
+        assert_eq!(
+            reply(&commands, &host, "/status", "1"),
+            "Model: openai/gpt-4o (set with /model, in place of ASTERBOT_MODEL)\n\
+             Conversation: 6 messages\n\
+             Memories: 2\n\
+             Usage on 2026-03-14: 3 LLM calls, 150 input / 25 output tokens\n\
+             Usage in this conversation: 4 LLM calls, 40 input / 8 output tokens"
+        );
+        // Other senders see their own conversation only.
+        assert_eq!(
+            reply(&commands, &host, "/status", "2"),
+            "Model: openai/gpt-4o (set with /model, in place of ASTERBOT_MODEL)\n\
+             Conversation: 6 messages\n\
+             Usage in this conversation: 7 LLM calls, 70 input / 7 output tokens"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! The people the gateways have talked to, and how to reach them.
+//!
+//! When a gateway answers a direct message, it records the sender
+//! in `contacts.json` in the host dir. A contact has an id made
+//! of the gateway and the sender ("telegram:42"), the name the
+//! platform shows, and the gateway and address its `delivery`
+//! export takes to message them. asterbot:contacts reads the file
+//! so the agent can message them later with its `notify` tool.
+
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CONTACTS_FILENAME: &str = "contacts.json";
+
+/// Longest name kept; platforms allow long display names.
+const MAX_NAME_CHARS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    /// The gateway and the sender's id on it, e.g. "telegram:42".
+    pub id: String,
+    /// The sender's name on the platform, or the sender's id.
+    pub name: String,
+    /// The gateway, e.g. "asterbot:telegram-gateway".
+    pub component: String,
+    /// What the gateway's `deliver` takes to reach the sender: a
+    /// chat id, channel id, phone number or address.
+    pub address: String,
+}
+
+impl Contact {
+    pub fn new(gateway: &str, sender: &str, name: &str, component: &str, address: &str) -> Self {
+        let name: String = name
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(MAX_NAME_CHARS)
+            .collect();
+        Contact {
+            id: format!("{gateway}:{}", sender.trim()),
+            name: match name.is_empty() {
+                true => sender.trim().to_string(),
+                false => name,
+            },
+            component: component.to_string(),
+            address: address.to_string(),
+        }
+    }
+
+    /// Adds this contact to the registry in the host dir, or
+    /// updates its name, address and when it was last seen.
+    /// Nothing is recorded without a host dir.
+    pub fn learn(&self, host_dir: &str) {
+        if host_dir.is_empty() {
+            return;
+        }
+        let path = format!("{host_dir}/{CONTACTS_FILENAME}");
+        let mut contacts: Vec<Value> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let contact = json!({
+            "id": self.id,
+            "name": self.name,
+            "component": self.component,
+            "address": self.address,
+            "lastSeen": now,
+        });
+        match contacts.iter_mut().find(|c| c["id"] == self.id.as_str()) {
+            Some(known) => *known = contact,
+            None => contacts.push(contact),
+        }
+        contacts.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        let json = serde_json::to_string_pretty(&contacts).unwrap_or_default();
+        if let Err(e) = std::fs::write(&path, json) {
+            eprintln!("failed to write {path}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contacts_are_learned_in_the_host_dir() {
+        let dir = std::env::temp_dir().join(format!("gateway-contacts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+        let telegram = "asterbot:telegram-gateway";
+        Contact::new("telegram", "42", " Alice\n ", telegram, "42").learn(&dir);
+        Contact::new(
+            "email",
+            "bob@example.org",
+            "",
+            "asterbot:email-gateway",
+            "bob@example.org",
+        )
+        .learn(&dir);
+        Contact::new("telegram", "42", "Alice S.", telegram, "42").learn(&dir);
+        let json = std::fs::read_to_string(format!("{dir}/{CONTACTS_FILENAME}")).unwrap();
+        let contacts: Vec<Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0]["id"], "email:bob@example.org");
+        assert_eq!(contacts[0]["name"], "bob@example.org");
+        assert_eq!(contacts[1]["id"], "telegram:42");
+        assert_eq!(contacts[1]["name"], "Alice S.");
+        assert_eq!(contacts[1]["component"], telegram);
+        assert_eq!(contacts[1]["address"], "42");
+        assert!(contacts[1]["lastSeen"].as_u64().unwrap() > 1_700_000_000);
+        std::fs::remove_dir_all(&dir).unwrap();
+        Contact::new("telegram", "42", "Alice", telegram, "42").learn("");
+    }
+
+    #[test]
+    fn long_names_are_cut() {
+        let contact = Contact::new(
+            "discord",
+            "U1",
+            &"a".repeat(100),
+            "asterbot:discord-gateway",
+            "D1",
+        );
+        assert_eq!(contact.name.len(), MAX_NAME_CHARS);
+    }
+}
@@ -0,0 +1,226 @@
+//! Discord access rules.
+//!
+//! Discord messages come from users, in channels, in guilds
+//! (servers), from members with roles, so access can be limited
+//! along each of these. Like the other gateways, Discord is
+//! disabled until an allowlist or `DISCORD_PUBLIC=true` is set.
+
+/// Where a Discord message came from.
+pub struct DiscordContext<'a> {
+    pub user_id: &'a str,
+    pub channel_id: &'a str,
+    /// `None` for direct messages.
+    pub guild_id: Option<&'a str>,
+    /// The sender's roles in the guild.
+    pub role_ids: &'a [String],
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Rule {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl Rule {
+    fn new(allow: &str, deny: &str) -> Self {
+        Rule {
+            allow: parse_ids(allow),
+            deny: parse_ids(deny),
+        }
+    }
+
+    fn from_env(name: &str) -> Self {
+        let var = |kind: &str| std::env::var(format!("DISCORD_{kind}_{name}")).unwrap_or_default();
+        Rule::new(&var("ALLOWED"), &var("DENIED"))
+    }
+
+    fn denies<'a>(&self, mut ids: impl Iterator<Item = &'a str>) -> bool {
+        ids.any(|id| self.deny.iter().any(|d| d == id))
+    }
+
+    /// An empty allowlist does not restrict.
+    fn allows<'a>(&self, mut ids: impl Iterator<Item = &'a str>) -> bool {
+        self.allow.is_empty() || ids.any(|id| self.allow.iter().any(|a| a == id))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscordAccess {
+    users: Rule,
+    guilds: Rule,
+    channels: Rule,
+    roles: Rule,
+    public: bool,
+    /// Respond to every direct message, not just mentions.
+    pub dm_without_mention: bool,
+}
+
+impl DiscordAccess {
+    /// Reads `DISCORD_{ALLOWED,DENIED}_{USER,GUILD,CHANNEL,ROLE}_IDS`,
+    /// `DISCORD_PUBLIC` and `DISCORD_DM_WITHOUT_MENTION`.
+    pub fn from_env() -> Self {
+        let flag = |var: &str| {
+            std::env::var(var)
+                .map(|v| v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false)
+        };
+        let access = DiscordAccess {
+            users: Rule::from_env("USER_IDS"),
+            guilds: Rule::from_env("GUILD_IDS"),
+            channels: Rule::from_env("CHANNEL_IDS"),
+            roles: Rule::from_env("ROLE_IDS"),
+            public: flag("DISCORD_PUBLIC"),
+            dm_without_mention: flag("DISCORD_DM_WITHOUT_MENTION"),
+        };
+        if access.public && access.has_allowlist() {
+            eprintln!("DISCORD_PUBLIC=true is ignored while Discord allowlists are set");
+        }
+        access
+    }
+
+    fn has_allowlist(&self) -> bool {
+        [&self.users, &self.guilds, &self.channels, &self.roles]
+            .iter()
+            .any(|r| !r.allow.is_empty())
+    }
+
+    /// Denylists always apply. Otherwise every configured allowlist
+    /// must match, or with none configured, `DISCORD_PUBLIC` must be
+    /// set. Direct messages have no guild or roles, so while any
+    /// allowlist is set only allowlisted users can DM the bot.
+    pub fn allows(&self, ctx: &DiscordContext) -> bool {
+        let roles = || ctx.role_ids.iter().map(String::as_str);
+        let denied = self.users.denies([ctx.user_id].into_iter())
+            || self.channels.denies([ctx.channel_id].into_iter())
+            || self.guilds.denies(ctx.guild_id.into_iter())
+            || self.roles.denies(roles());
+        if denied {
+            return false;
+        }
+        if !self.has_allowlist() {
+            return self.public;
+        }
+        match ctx.guild_id {
+            None => {
+                !self.users.allow.is_empty()
+                    && self.users.allows([ctx.user_id].into_iter())
+                    && self.channels.allows([ctx.channel_id].into_iter())
+            }
+            Some(guild) => {
+                self.users.allows([ctx.user_id].into_iter())
+                    && self.guilds.allows([guild].into_iter())
+                    && self.channels.allows([ctx.channel_id].into_iter())
+                    && self.roles.allows(roles())
+            }
+        }
+    }
+
+    /// The allowlisted user ids, who are admins for commands
+    /// unless `DISCORD_ADMIN_IDS` is set.
+    pub fn allowed_users(&self) -> &[String] {
+        &self.users.allow
+    }
+
+    /// Whether to answer: mentions always, and with
+    /// `DISCORD_DM_WITHOUT_MENTION=true` any direct message.
+    pub fn should_respond(&self, ctx: &DiscordContext, mentioned: bool) -> bool {
+        mentioned || (self.dm_without_mention && ctx.guild_id.is_none())
+    }
+}
+
+fn parse_ids(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx<'a>(guild: Option<&'a str>, roles: &'a [String]) -> DiscordContext<'a> {
+        DiscordContext {
+            user_id: "u1",
+            channel_id: "c1",
+            guild_id: guild,
+            role_ids: roles,
+        }
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let access = DiscordAccess::default();
+        assert!(!access.allows(&ctx(Some("g1"), &[])));
+        assert!(!access.allows(&ctx(None, &[])));
+        let public = DiscordAccess {
+            public: true,
+            ..Default::default()
+        };
+        assert!(public.allows(&ctx(Some("g1"), &[])));
+        assert!(public.allows(&ctx(None, &[])));
+    }
+
+    #[test]
+    fn every_allowlist_must_match() {
+        let access = DiscordAccess {
+            guilds: Rule::new("g1", ""),
+            roles: Rule::new("mods, admins", ""),
+            public: true,
+            ..Default::default()
+        };
+        let roles = ["members".to_string(), "mods".to_string()];
+        assert!(access.allows(&ctx(Some("g1"), &roles)));
+        assert!(!access.allows(&ctx(Some("g2"), &roles)));
+        assert!(!access.allows(&ctx(Some("g1"), &roles[..1])));
+        // No user allowlist, so DMs are refused.
+        assert!(!access.allows(&ctx(None, &[])));
+    }
+
+    #[test]
+    fn denylists_always_apply() {
+        let access = DiscordAccess {
+            channels: Rule::new("", "c1"),
+            public: true,
+            ..Default::default()
+        };
+        assert!(!access.allows(&ctx(Some("g1"), &[])));
+        let access = DiscordAccess {
+            guilds: Rule::new("g1", ""),
+            roles: Rule::new("", "muted"),
+            ..Default::default()
+        };
+        assert!(access.allows(&ctx(Some("g1"), &[])));
+        assert!(!access.allows(&ctx(Some("g1"), &["muted".to_string()])));
+    }
+
+    #[test]
+    fn allowlisted_users_can_dm() {
+        let access = DiscordAccess {
+            users: Rule::new("u1", ""),
+            ..Default::default()
+        };
+        assert!(access.allows(&ctx(None, &[])));
+        assert!(access.allows(&ctx(Some("g1"), &[])));
+        assert_eq!(access.allowed_users(), ["u1"]);
+        let other = DiscordContext {
+            user_id: "u2",
+            ..ctx(None, &[])
+        };
+        assert!(!access.allows(&other));
+    }
+
+    #[test]
+    fn dms_need_a_mention_unless_opted_in() {
+        let access = DiscordAccess::default();
+        assert!(!access.should_respond(&ctx(None, &[]), false));
+        assert!(access.should_respond(&ctx(Some("g1"), &[]), true));
+        let opted_in = DiscordAccess {
+            dm_without_mention: true,
+            ..Default::default()
+        };
+        assert!(opted_in.should_respond(&ctx(None, &[]), false));
+        assert!(!opted_in.should_respond(&ctx(Some("g1"), &[]), false));
+    }
+}
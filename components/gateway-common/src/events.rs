@@ -0,0 +1,4 @@
+//! The structured event log, shared with asterbot:core. See
+//! `asterbot_common::events` for the settings.
+
+pub use asterbot_common::events::{ContentMode, EventLog};
@@ -0,0 +1,194 @@
+//! Deciding whether to answer in group chats.
+//!
+//! In a busy group the bot should only speak when spoken to: when
+//! its handle is mentioned, or when a message starts with its
+//! name ("Aster, ...").
+
+/// How a gateway behaves in group chats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupMode {
+    /// Answer only messages that address the bot (the default).
+    Mentions,
+    /// Answer every message, like in a private chat.
+    All,
+    /// Ignore group chats entirely.
+    Off,
+}
+
+/// The handles and names a group message can address the bot by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupPolicy {
+    pub mode: GroupMode,
+    /// Handles without the "@", e.g. the bot's username.
+    handles: Vec<String>,
+    names: Vec<String>,
+}
+
+impl GroupPolicy {
+    pub fn new(mode: GroupMode, handles: &[&str], names: &[&str]) -> Self {
+        let clean = |list: &[&str]| {
+            list.iter()
+                .map(|s| s.trim().trim_start_matches('@').to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+        GroupPolicy {
+            mode,
+            handles: clean(handles),
+            names: clean(names),
+        }
+    }
+
+    /// Reads `<PREFIX>_GROUPS` (`mentions`, `all` or `off`) and
+    /// extra names from `<PREFIX>_BOT_NAMES` (comma-separated).
+    pub fn from_env(prefix: &str, handles: &[&str], names: &[&str]) -> Self {
+        let var = format!("{prefix}_GROUPS");
+        let mode = match std::env::var(&var).unwrap_or_default().trim() {
+            "" | "mentions" => GroupMode::Mentions,
+            "all" => GroupMode::All,
+            "off" => GroupMode::Off,
+            other => {
+                eprintln!("unknown {var} {other:?}, only answering mentions");
+                GroupMode::Mentions
+            }
+        };
+        let extra = std::env::var(format!("{prefix}_BOT_NAMES")).unwrap_or_default();
+        let names: Vec<&str> = names.iter().copied().chain(extra.split(',')).collect();
+        GroupPolicy::new(mode, handles, &names)
+    }
+
+    /// The input to send to the agent for a group message, with
+    /// the mention or name removed, or `None` if the bot should
+    /// stay quiet.
+    pub fn input(&self, text: &str) -> Option<String> {
+        let (stripped, addressed) = self.strip(text);
+        match self.mode {
+            GroupMode::Off => None,
+            GroupMode::All => Some(stripped),
+            GroupMode::Mentions => addressed.then_some(stripped),
+        }
+    }
+
+    /// Removes "@handle" mentions anywhere in `text`, or failing
+    /// that a leading name, returning whether either was found.
+    fn strip(&self, text: &str) -> (String, bool) {
+        let mut out = String::with_capacity(text.len());
+        let mut addressed = false;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let handle = rest.strip_prefix('@').and_then(|after| {
+                self.handles
+                    .iter()
+                    .find_map(|h| prefix_len(after, h).map(|len| len + 1))
+            });
+            match handle {
+                Some(len) => {
+                    addressed = true;
+                    rest = &rest[len..];
+                    // "hey @bot, help" becomes "hey, help", and
+                    // "hey @bot help" "hey help".
+                    if rest.starts_with(|c: char| c.is_ascii_punctuation()) {
+                        out.truncate(out.trim_end_matches([' ', '\t']).len());
+                    } else if out.is_empty() || out.ends_with([' ', '\t']) {
+                        rest = rest.trim_start_matches([' ', '\t']);
+                    }
+                }
+                None => {
+                    out.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        // Only the mention goes: line breaks, lists and code
+        // blocks in the rest of the message are kept.
+        let mut text = out.trim_start();
+        if !addressed && let Some(len) = self.names.iter().find_map(|n| prefix_len(text, n)) {
+            addressed = true;
+            text = &text[len..];
+        }
+        let text = text.trim_start_matches([',', ':', '!', ' ']).trim();
+        (text.to_string(), addressed)
+    }
+}
+
+/// The byte length of `word` at the start of `text`, compared
+/// case-insensitively, if it is not followed by more of a word.
+fn prefix_len(text: &str, word: &str) -> Option<usize> {
+    let mut len = 0;
+    let mut chars = text.chars();
+    for expected in word.chars() {
+        let c = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+        len += c.len_utf8();
+    }
+    let ends_word = !chars
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_');
+    ends_word.then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: GroupMode) -> GroupPolicy {
+        GroupPolicy::new(mode, &["aster_bot"], &["Aster"])
+    }
+
+    #[test]
+    fn mentions_are_answered_and_stripped() {
+        let policy = policy(GroupMode::Mentions);
+        assert_eq!(
+            policy.input("@aster_bot what's the weather?").as_deref(),
+            Some("what's the weather?")
+        );
+        assert_eq!(
+            policy.input("hey @Aster_Bot, help").as_deref(),
+            Some("hey, help")
+        );
+        assert_eq!(policy.input("/reset@aster_bot").as_deref(), Some("/reset"));
+        assert_eq!(
+            policy.input("tell @aster_bot hi").as_deref(),
+            Some("tell hi")
+        );
+        assert_eq!(policy.input("@aster_botty hi"), None);
+        assert_eq!(policy.input("just chatting"), None);
+    }
+
+    #[test]
+    fn stripping_keeps_the_message_layout() {
+        let policy = policy(GroupMode::Mentions);
+        let text =
+            "@aster_bot fix this:\n\n```\nfn  main() {\n    run();\n}\n```\n- first\n- second\n";
+        assert_eq!(
+            policy.input(text).as_deref(),
+            Some("fix this:\n\n```\nfn  main() {\n    run();\n}\n```\n- first\n- second")
+        );
+        assert_eq!(
+            policy.input("Aster:\n1. eggs\n2. milk").as_deref(),
+            Some("1. eggs\n2. milk")
+        );
+    }
+
+    #[test]
+    fn names_address_the_bot_at_the_start() {
+        let policy = policy(GroupMode::Mentions);
+        assert_eq!(
+            policy.input("aster, remind me at 9").as_deref(),
+            Some("remind me at 9")
+        );
+        assert_eq!(policy.input("Asteroids are cool"), None);
+        assert_eq!(policy.input("ask Aster later"), None);
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(
+            policy(GroupMode::All).input("anything").as_deref(),
+            Some("anything")
+        );
+        assert_eq!(policy(GroupMode::Off).input("@aster_bot hi"), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! Behaviour shared by the asterbot gateway components: access
+//! policy, rate limits, phone number normalisation, message
+//! chunking and formatting, SMS segments, rich reply fallbacks,
+//! slash commands, group chats, session keys, turn origins, the
+//! contact registry, the event log and Twilio webhook signatures.
+//!
+//! This is a plain Rust library linked into each gateway, so it
+//! has no WIT bindings of its own. Calls into other components go
+//! through the [`Host`] trait, which each gateway implements with
+//! its `asterai:host/api` import.
+
+pub mod access;
+pub mod chunk;
+pub mod commands;
+pub mod contacts;
+pub mod discord;
+pub mod events;
+pub mod group;
+pub mod limit;
+pub mod markdown;
+pub mod origin;
+pub mod phone;
+pub mod reply;
+pub mod session;
+pub mod sms;
+pub mod twilio;
+
+pub use access::{Access, AccessMode, IdKind};
+pub use chunk::{Splitter, chunk};
+pub use commands::{Commands, Handled};
+pub use contacts::Contact;
+pub use discord::{DiscordAccess, DiscordContext};
+pub use events::EventLog;
+pub use group::{GroupMode, GroupPolicy};
+pub use limit::{Blocklist, RateLimit, Verdict};
+pub use markdown::Format;
+pub use origin::Origin;
+pub use phone::normalise_phone;
+pub use session::SessionKey;
+pub use sms::{Keyword, SmsSplitter};
+
+/// Dynamic calls to other components in the environment.
+pub trait Host {
+    /// Calls `function` on `component` with a JSON array of
+    /// arguments, returning the JSON-encoded result or an
+    /// error message.
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String>;
+}
+
+/// The asterbot state directory: `ASTERBOT_HOST_DIR`, or the
+/// first directory granted with `--allow-dir`. Empty if neither
+/// is available.
+pub fn resolve_host_dir() -> String {
+    std::env::var("ASTERBOT_HOST_DIR")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .or_else(|| {
+            std::env::var("ASTERAI_ALLOWED_DIRS")
+                .ok()
+                .and_then(|dirs| dirs.split(':').next().map(str::to_string))
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,532 @@
+//! Rate limits, message length caps and the blocklist, which keep
+//! a public bot from running up unlimited LLM calls.
+//!
+//! Each sender has a token bucket holding `<PREFIX>_RATE_LIMIT`
+//! messages, refilled evenly over its period, and all senders of
+//! a gateway share one holding `<PREFIX>_GLOBAL_RATE_LIMIT`. Every
+//! message reaching the agent takes a token from both. Buckets are
+//! kept in the host dir, as each message may be handled by a new
+//! instance; concurrent messages can race, so limits are
+//! approximate.
+//!
+//! Blocked senders are listed one per line in a file next to the
+//! buckets, which admins edit with `/block` and `/unblock`.
+
+use crate::access::IdKind;
+use crate::resolve_host_dir;
+use serde_json::{Map, Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where buckets and blocklists are kept, under the host dir.
+pub const LIMITS_DIR: &str = "limits";
+
+const DEFAULT_RATE: Rate = Rate {
+    messages: 30,
+    period_secs: 3600,
+};
+const DEFAULT_MAX_CHARS: usize = 4000;
+
+/// `messages` per `period_secs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub messages: u32,
+    pub period_secs: u64,
+}
+
+impl Rate {
+    /// Parses "<messages>/<period>", e.g. "30/hour" or "5/min".
+    pub fn parse(s: &str) -> Option<Rate> {
+        let (messages, period) = s.split_once('/')?;
+        let period_secs = match period.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 3600,
+            "d" | "day" => 86400,
+            _ => return None,
+        };
+        let messages = messages.trim().parse().ok().filter(|&n| n > 0)?;
+        Some(Rate {
+            messages,
+            period_secs,
+        })
+    }
+
+    /// Reads `var`: a rate, or "off" for no limit.
+    fn from_env(var: &str, default: Option<Rate>) -> Option<Rate> {
+        let value = std::env::var(var).unwrap_or_default();
+        match value.trim() {
+            "" => default,
+            v if v.eq_ignore_ascii_case("off") => None,
+            v => Rate::parse(v).or_else(|| {
+                eprintln!("invalid {var} {v:?}, expected e.g. \"30/hour\" or \"off\"");
+                default
+            }),
+        }
+    }
+
+    /// Tokens refilled over `secs`.
+    fn refill(self, secs: f64) -> f64 {
+        secs * self.messages as f64 / self.period_secs as f64
+    }
+
+    /// Seconds to refill `tokens`.
+    fn secs_for(self, tokens: f64) -> f64 {
+        tokens * self.period_secs as f64 / self.messages as f64
+    }
+}
+
+/// Whether a message may go to the agent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allowed,
+    TooLong {
+        chars: usize,
+        max_chars: usize,
+    },
+    /// Out of messages for `retry_after_secs`. `notify` is set
+    /// for the first message refused, so senders are told once
+    /// rather than answered every time.
+    Limited {
+        global: bool,
+        retry_after_secs: u64,
+        notify: bool,
+    },
+}
+
+impl Verdict {
+    /// Names the verdict in events.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Verdict::Allowed => "allowed",
+            Verdict::TooLong { .. } => "too-long",
+            Verdict::Limited { global: false, .. } => "rate-limit",
+            Verdict::Limited { global: true, .. } => "global-rate-limit",
+        }
+    }
+
+    /// The reply to send the sender, if any.
+    pub fn reply(&self) -> Option<String> {
+        match *self {
+            Verdict::Allowed => None,
+            Verdict::TooLong { chars, max_chars } => Some(format!(
+                "Messages are limited to {max_chars} characters; yours has {chars}."
+            )),
+            Verdict::Limited { notify: false, .. } => None,
+            Verdict::Limited {
+                global,
+                retry_after_secs,
+                ..
+            } => Some(format!(
+                "{} Try again in {}.",
+                match global {
+                    true => "Too many messages are being sent to the bot right now.",
+                    false => "You're sending messages too quickly.",
+                },
+                duration(retry_after_secs)
+            )),
+        }
+    }
+}
+
+/// A gateway's limits. Admins are exempt; the gateway checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    pub per_sender: Option<Rate>,
+    pub global: Option<Rate>,
+    /// Longest message passed to the agent, 0 for no cap.
+    pub max_chars: usize,
+    pub blocklist: Blocklist,
+    kind: IdKind,
+    path: Option<String>,
+}
+
+impl RateLimit {
+    /// Default limits, with buckets kept under `host_dir`.
+    pub fn new(gateway: &str, kind: IdKind, host_dir: &str) -> Self {
+        RateLimit {
+            per_sender: Some(DEFAULT_RATE),
+            global: None,
+            max_chars: DEFAULT_MAX_CHARS,
+            blocklist: Blocklist::new(gateway, kind, host_dir),
+            kind,
+            path: (!host_dir.is_empty()).then(|| format!("{host_dir}/{LIMITS_DIR}/{gateway}.json")),
+        }
+    }
+
+    /// Reads `<PREFIX>_RATE_LIMIT` (default "30/hour"),
+    /// `<PREFIX>_GLOBAL_RATE_LIMIT` (default "off") and
+    /// `<PREFIX>_MAX_MESSAGE_CHARS` (default 4000, 0 for no cap).
+    pub fn from_env(prefix: &str, kind: IdKind) -> Self {
+        let mut limit = RateLimit::new(&prefix.to_ascii_lowercase(), kind, &resolve_host_dir());
+        limit.per_sender = Rate::from_env(&format!("{prefix}_RATE_LIMIT"), limit.per_sender);
+        limit.global = Rate::from_env(&format!("{prefix}_GLOBAL_RATE_LIMIT"), limit.global);
+        let var = format!("{prefix}_MAX_MESSAGE_CHARS");
+        if let Ok(value) = std::env::var(&var) {
+            match value.trim().parse() {
+                Ok(max_chars) => limit.max_chars = max_chars,
+                Err(_) => eprintln!("invalid {var} {value:?}, using {}", limit.max_chars),
+            }
+        }
+        limit
+    }
+
+    pub fn is_blocked(&self, sender: &str) -> bool {
+        self.blocklist.contains(sender)
+    }
+
+    /// Checks `text` from `sender` and, if it is allowed, takes
+    /// a token from the sender's and the global bucket.
+    pub fn check(&self, sender: &str, text: &str) -> Verdict {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        self.check_at(sender, text, now)
+    }
+
+    fn check_at(&self, sender: &str, text: &str, now: f64) -> Verdict {
+        let chars = text.chars().count();
+        if self.max_chars > 0 && chars > self.max_chars {
+            return Verdict::TooLong {
+                chars,
+                max_chars: self.max_chars,
+            };
+        }
+        if self.per_sender.is_none() && self.global.is_none() {
+            return Verdict::Allowed;
+        }
+        let Some(path) = &self.path else {
+            eprintln!("rate limits are off: no host dir to keep them in");
+            return Verdict::Allowed;
+        };
+        let mut state: Value = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_else(|| json!({}));
+        let key = self
+            .kind
+            .normalise(sender)
+            .unwrap_or_else(|| sender.to_string());
+        let mut mine = Bucket::load(&state["senders"][&key], self.per_sender, now);
+        let mut all = Bucket::load(&state["global"], self.global, now);
+        let sender_wait = mine.wait(self.per_sender);
+        let global_wait = all.wait(self.global);
+        let verdict = match sender_wait.max(global_wait) {
+            0 => {
+                mine.take(self.per_sender);
+                all.take(self.global);
+                Verdict::Allowed
+            }
+            wait => {
+                let notify = now >= mine.quiet_until;
+                mine.quiet_until = mine.quiet_until.max(now + wait as f64);
+                Verdict::Limited {
+                    global: sender_wait == 0,
+                    retry_after_secs: wait,
+                    notify,
+                }
+            }
+        };
+        let mut senders = match state["senders"].take() {
+            Value::Object(senders) => senders,
+            _ => Map::new(),
+        };
+        // Buckets that have refilled are dropped, so the file only
+        // holds recent senders.
+        senders.retain(|_, bucket| {
+            !Bucket::load(bucket, self.per_sender, now).is_idle(self.per_sender, now)
+        });
+        senders.insert(key, mine.to_json());
+        state = json!({ "global": all.to_json(), "senders": senders });
+        if let Err(e) = write(path, &state.to_string()) {
+            eprintln!("failed to write {path}: {e}");
+        }
+        verdict
+    }
+}
+
+/// A token bucket, refilled up to `now` when loaded.
+struct Bucket {
+    tokens: f64,
+    /// Refused messages aren't answered again before this time.
+    quiet_until: f64,
+    now: f64,
+}
+
+impl Bucket {
+    fn load(json: &Value, rate: Option<Rate>, now: f64) -> Self {
+        let capacity = rate.map_or(0.0, |r| r.messages as f64);
+        let tokens = match (json["tokens"].as_f64(), json["updated"].as_f64(), rate) {
+            (Some(tokens), Some(updated), Some(rate)) => {
+                (tokens + rate.refill((now - updated).max(0.0))).min(capacity)
+            }
+            _ => capacity,
+        };
+        Bucket {
+            tokens,
+            quiet_until: json["quietUntil"].as_f64().unwrap_or(0.0),
+            now,
+        }
+    }
+
+    /// Seconds until a token is available, 0 if one is now.
+    fn wait(&self, rate: Option<Rate>) -> u64 {
+        match rate {
+            Some(rate) if self.tokens < 1.0 => rate.secs_for(1.0 - self.tokens).ceil() as u64,
+            _ => 0,
+        }
+    }
+
+    fn take(&mut self, rate: Option<Rate>) {
+        if rate.is_some() {
+            self.tokens -= 1.0;
+        }
+    }
+
+    fn is_idle(&self, rate: Option<Rate>, now: f64) -> bool {
+        let capacity = rate.map_or(0.0, |r| r.messages as f64);
+        self.tokens >= capacity && now >= self.quiet_until
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "tokens": self.tokens, "updated": self.now, "quietUntil": self.quiet_until })
+    }
+}
+
+/// Senders whose messages are ignored, kept in the host dir so
+/// admins can change it at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blocklist {
+    kind: IdKind,
+    path: Option<String>,
+}
+
+impl Blocklist {
+    pub fn new(gateway: &str, kind: IdKind, host_dir: &str) -> Self {
+        Blocklist::named(&format!("{gateway}-blocked"), kind, host_dir)
+    }
+
+    /// A list kept in `{name}.txt` next to the blocklists, e.g. of
+    /// senders who opted out of messages.
+    pub fn named(name: &str, kind: IdKind, host_dir: &str) -> Self {
+        Blocklist {
+            kind,
+            path: (!host_dir.is_empty()).then(|| format!("{host_dir}/{LIMITS_DIR}/{name}.txt")),
+        }
+    }
+
+    pub fn from_env(prefix: &str, kind: IdKind) -> Self {
+        Blocklist::new(&prefix.to_ascii_lowercase(), kind, &resolve_host_dir())
+    }
+
+    /// The blocked ids, normalised.
+    pub fn ids(&self) -> Vec<String> {
+        let Some(path) = &self.path else {
+            return Vec::new();
+        };
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| self.kind.normalise(line))
+            .collect()
+    }
+
+    pub fn contains(&self, sender: &str) -> bool {
+        self.kind
+            .normalise(sender)
+            .is_some_and(|id| self.ids().contains(&id))
+    }
+
+    /// Adds `id`, returning whether it wasn't blocked already.
+    pub fn block(&self, id: &str) -> Result<bool, String> {
+        let id = self.normalise(id)?;
+        let mut ids = self.ids();
+        if ids.contains(&id) {
+            return Ok(false);
+        }
+        ids.push(id);
+        self.save(&ids).map(|_| true)
+    }
+
+    /// Removes `id`, returning whether it was blocked.
+    pub fn unblock(&self, id: &str) -> Result<bool, String> {
+        let id = self.normalise(id)?;
+        let mut ids = self.ids();
+        let before = ids.len();
+        ids.retain(|blocked| *blocked != id);
+        match ids.len() == before {
+            true => Ok(false),
+            false => self.save(&ids).map(|_| true),
+        }
+    }
+
+    fn normalise(&self, id: &str) -> Result<String, String> {
+        self.kind
+            .normalise(id)
+            .ok_or_else(|| format!("{id:?} is not a valid sender id"))
+    }
+
+    fn save(&self, ids: &[String]) -> Result<(), String> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or("no host dir to store the blocklist in")?;
+        let mut contents = ids.join("\n");
+        contents.push('\n');
+        write(path, &contents).map_err(|e| format!("failed to write {path}: {e}"))
+    }
+}
+
+/// Writes `contents` to `path`, creating its directory.
+fn write(path: &str, contents: &str) -> std::io::Result<()> {
+    if let Some((dir, _)) = path.rsplit_once('/') {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)
+}
+
+/// "45 seconds", "3 minutes" or "2 hours", rounded up.
+fn duration(secs: u64) -> String {
+    let (n, unit) = match secs {
+        0..60 => (secs.max(1), "second"),
+        60..3600 => (secs.div_ceil(60), "minute"),
+        _ => (secs.div_ceil(3600), "hour"),
+    };
+    match n {
+        1 => format!("1 {unit}"),
+        n => format!("{n} {unit}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("gateway-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(
+            Rate::parse("30/hour"),
+            Some(Rate {
+                messages: 30,
+                period_secs: 3600
+            })
+        );
+        assert_eq!(
+            Rate::parse(" 5 / Min "),
+            Some(Rate {
+                messages: 5,
+                period_secs: 60
+            })
+        );
+        assert_eq!(Rate::parse("0/day"), None);
+        assert_eq!(Rate::parse("10/week"), None);
+        assert_eq!(Rate::parse("10"), None);
+    }
+
+    #[test]
+    fn senders_are_limited_and_told_once() {
+        let dir = temp_dir("limit-sender");
+        let mut limit = RateLimit::new("telegram", IdKind::User, &dir);
+        limit.per_sender = Rate::parse("2/min");
+        assert_eq!(limit.check_at("1", "hi", 1000.0), Verdict::Allowed);
+        assert_eq!(limit.check_at("1", "hi", 1000.0), Verdict::Allowed);
+        let limited = limit.check_at("1", "hi", 1000.0);
+        assert_eq!(
+            limited,
+            Verdict::Limited {
+                global: false,
+                retry_after_secs: 30,
+                notify: true
+            }
+        );
+        assert_eq!(
+            limited.reply().unwrap(),
+            "You're sending messages too quickly. Try again in 30 seconds."
+        );
+        let again = limit.check_at("1", "hi", 1010.0);
+        assert!(matches!(again, Verdict::Limited { notify: false, .. }));
+        assert_eq!(again.reply(), None);
+        // Other senders have their own bucket.
+        assert_eq!(limit.check_at("2", "hi", 1010.0), Verdict::Allowed);
+        // One token refills every 30 seconds, and a sender who
+        // runs out again is told again.
+        assert_eq!(limit.check_at("1", "hi", 1031.0), Verdict::Allowed);
+        assert!(matches!(
+            limit.check_at("1", "hi", 1032.0),
+            Verdict::Limited { notify: true, .. }
+        ));
+        assert_eq!(limit.check_at("1", "hi", 1100.0), Verdict::Allowed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn global_limit_is_shared_by_senders() {
+        let dir = temp_dir("limit-global");
+        let mut limit = RateLimit::new("whatsapp", IdKind::Phone, &dir);
+        limit.per_sender = None;
+        limit.global = Rate::parse("2/hour");
+        assert_eq!(limit.check_at("+14155550123", "a", 0.0), Verdict::Allowed);
+        assert_eq!(limit.check_at("+14155550124", "b", 0.0), Verdict::Allowed);
+        let limited = limit.check_at("14155550123", "c", 0.0);
+        assert_eq!(
+            limited,
+            Verdict::Limited {
+                global: true,
+                retry_after_secs: 1800,
+                notify: true
+            }
+        );
+        assert_eq!(limited.reason(), "global-rate-limit");
+        assert!(
+            limited
+                .reply()
+                .unwrap()
+                .ends_with("Try again in 30 minutes.")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_messages_are_refused() {
+        let mut limit = RateLimit::new("discord", IdKind::User, "");
+        limit.max_chars = 5;
+        assert_eq!(limit.check_at("1", "héllo", 0.0), Verdict::Allowed);
+        let verdict = limit.check_at("1", "hello!", 0.0);
+        assert_eq!(
+            verdict.reply().unwrap(),
+            "Messages are limited to 5 characters; yours has 6."
+        );
+    }
+
+    #[test]
+    fn blocklist_is_kept_in_the_host_dir() {
+        let dir = temp_dir("blocklist");
+        let blocklist = Blocklist::new("whatsapp", IdKind::Phone, &dir);
+        assert!(!blocklist.contains("+14155550123"));
+        assert_eq!(blocklist.block("1 (415) 555-0123"), Ok(true));
+        assert_eq!(blocklist.block("+14155550123"), Ok(false));
+        assert!(blocklist.contains("14155550123"));
+        assert!(blocklist.block("not a number").is_err());
+        let limit = RateLimit::new("whatsapp", IdKind::Phone, &dir);
+        assert!(limit.is_blocked("+14155550123"));
+        assert!(!RateLimit::new("twilio", IdKind::Phone, &dir).is_blocked("+14155550123"));
+        assert_eq!(blocklist.unblock("+14155550123"), Ok(true));
+        assert_eq!(blocklist.unblock("+14155550123"), Ok(false));
+        assert!(blocklist.ids().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn durations_round_up() {
+        assert_eq!(duration(0), "1 second");
+        assert_eq!(duration(59), "59 seconds");
+        assert_eq!(duration(61), "2 minutes");
+        assert_eq!(duration(3600), "1 hour");
+    }
+}
@@ -0,0 +1,601 @@
+//! Rendering the agent's Markdown for each platform.
+//!
+//! The agent replies in Markdown, which only Discord displays.
+//! Replies are parsed line by line into blocks (code fences,
+//! tables, headings, lists, quotes) and inline spans (bold,
+//! italic, strikethrough, code, links), then written out in the
+//! platform's own formatting with its escaping rules.
+
+/// A platform's message formatting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Discord's Markdown, which has no tables.
+    Markdown,
+    /// Telegram's HTML parse mode.
+    Html,
+    /// WhatsApp's `*bold*`, `_italic_`, `~strike~` and ``` ``` ```.
+    WhatsApp,
+    /// No formatting, e.g. for SMS.
+    Plain,
+    /// ANSI escape codes, for the terminal REPL.
+    Ansi,
+}
+
+impl Format {
+    /// `default`, or the format named by `<PREFIX>_FORMAT`
+    /// (`markdown`, `html`, `whatsapp`, `plain` or `ansi`).
+    pub fn from_env(prefix: &str, default: Format) -> Self {
+        let var = format!("{prefix}_FORMAT");
+        let Ok(name) = std::env::var(&var) else {
+            return default;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" => default,
+            "markdown" => Format::Markdown,
+            "html" => Format::Html,
+            "whatsapp" => Format::WhatsApp,
+            "plain" => Format::Plain,
+            "ansi" | "terminal" => Format::Ansi,
+            other => {
+                eprintln!("unknown {var} {other:?}, using {default:?}");
+                default
+            }
+        }
+    }
+
+    /// Converts the agent's Markdown to this format.
+    pub fn render(self, markdown: &str) -> String {
+        parse_blocks(markdown)
+            .iter()
+            .map(|block| self.block(block))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn block(self, block: &Block) -> String {
+        match block {
+            Block::Line(text) => self.inline(text),
+            Block::Heading(level, text) => match self {
+                Format::Markdown => format!("{} {}", "#".repeat(*level), self.inline(text)),
+                Format::Html => format!("<b>{}</b>", self.inline(text)),
+                Format::WhatsApp => format!("*{}*", self.inline(text)),
+                Format::Plain => self.inline(text),
+                Format::Ansi => format!("{BOLD}{UNDERLINE}{}{RESET}", self.inline(text)),
+            },
+            Block::Item(indent, marker, text) => {
+                let marker = match (self, marker.as_str()) {
+                    (_, m) if m.ends_with(['.', ')']) => m,
+                    (Format::Html | Format::Ansi, _) => "•",
+                    _ => "-",
+                };
+                format!("{indent}{marker} {}", self.inline(text))
+            }
+            Block::Quote(lines) => {
+                let lines: Vec<String> = lines.iter().map(|l| self.inline(l)).collect();
+                match self {
+                    Format::Html => format!("<blockquote>{}</blockquote>", lines.join("\n")),
+                    Format::Ansi => lines
+                        .iter()
+                        .map(|l| format!("{DIM}│{NORMAL} {l}"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    _ => lines
+                        .iter()
+                        .map(|l| format!("> {l}"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }
+            }
+            Block::Rule => match self {
+                Format::Plain => "----------".to_string(),
+                _ => "──────────".to_string(),
+            },
+            Block::Code(lang, code) => self.code_block(lang, code),
+            Block::Table(rows) => match self {
+                Format::Plain => flatten_table(rows),
+                Format::Ansi => align_table(rows),
+                _ => self.code_block("", &align_table(rows)),
+            },
+        }
+    }
+
+    fn code_block(self, lang: &str, code: &str) -> String {
+        match self {
+            Format::Markdown => format!("```{lang}\n{code}\n```"),
+            Format::Html if lang.is_empty() => format!("<pre>{}</pre>", escape_html(code)),
+            Format::Html => format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>",
+                escape_html(lang),
+                escape_html(code)
+            ),
+            Format::WhatsApp => format!("```{code}```"),
+            Format::Plain => code.to_string(),
+            // Indented, as a terminal can't shade the block.
+            Format::Ansi => code
+                .lines()
+                .map(|line| format!("  {CYAN}{}{DEFAULT}", self.escape(line)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    fn inline(self, text: &str) -> String {
+        self.spans(&parse_inline(text))
+    }
+
+    fn spans(self, spans: &[Span]) -> String {
+        spans.iter().map(|span| self.span(span)).collect()
+    }
+
+    fn span(self, span: &Span) -> String {
+        let wrap =
+            |open: &str, inner: &[Span], close: &str| format!("{open}{}{close}", self.spans(inner));
+        match (self, span) {
+            (_, Span::Text(text)) => self.escape(text),
+            (Format::Markdown, Span::Code(code)) => format!("`{code}`"),
+            (Format::Html, Span::Code(code)) => format!("<code>{}</code>", escape_html(code)),
+            (Format::WhatsApp, Span::Code(code)) => format!("`{code}`"),
+            (Format::Plain, Span::Code(code)) => code.clone(),
+            (Format::Ansi, Span::Code(code)) => format!("{CYAN}{}{DEFAULT}", self.escape(code)),
+            (Format::Markdown, Span::Bold(s)) => wrap("**", s, "**"),
+            (Format::Html, Span::Bold(s)) => wrap("<b>", s, "</b>"),
+            (Format::WhatsApp, Span::Bold(s)) => wrap("*", s, "*"),
+            (Format::Markdown, Span::Italic(s)) => wrap("*", s, "*"),
+            (Format::Html, Span::Italic(s)) => wrap("<i>", s, "</i>"),
+            (Format::WhatsApp, Span::Italic(s)) => wrap("_", s, "_"),
+            (Format::Markdown, Span::Strike(s)) => wrap("~~", s, "~~"),
+            (Format::Html, Span::Strike(s)) => wrap("<s>", s, "</s>"),
+            (Format::WhatsApp, Span::Strike(s)) => wrap("~", s, "~"),
+            (Format::Plain, Span::Bold(s) | Span::Italic(s) | Span::Strike(s)) => self.spans(s),
+            (Format::Ansi, Span::Bold(s)) => wrap(BOLD, s, NORMAL),
+            (Format::Ansi, Span::Italic(s)) => wrap("\x1b[3m", s, "\x1b[23m"),
+            (Format::Ansi, Span::Strike(s)) => wrap("\x1b[9m", s, "\x1b[29m"),
+            (Format::Markdown, Span::Link(text, url)) => {
+                format!("[{}]({url})", self.spans(text))
+            }
+            (Format::Html, Span::Link(text, url)) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url).replace('"', "&quot;"),
+                self.spans(text)
+            ),
+            (Format::Ansi, Span::Link(text, url)) => {
+                let underlined = wrap(UNDERLINE, text, "\x1b[24m");
+                match Format::Plain.spans(text) == *url {
+                    true => underlined,
+                    false => format!("{underlined} {DIM}({}){NORMAL}", self.escape(url)),
+                }
+            }
+            (Format::WhatsApp | Format::Plain, Span::Link(text, url)) => {
+                let text = self.spans(text);
+                match text == *url {
+                    true => text,
+                    false => format!("{text} ({url})"),
+                }
+            }
+        }
+    }
+
+    fn escape(self, text: &str) -> String {
+        match self {
+            Format::Markdown => {
+                let mut out = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|') {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out
+            }
+            Format::Html => escape_html(text),
+            // WhatsApp has no escape character.
+            Format::WhatsApp | Format::Plain => text.to_string(),
+            // Control characters could move the cursor or recolour
+            // the terminal, so only tabs are kept.
+            Format::Ansi => text
+                .chars()
+                .filter(|&c| c == '\t' || !c.is_control())
+                .collect(),
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+/// Ends bold and dim.
+const NORMAL: &str = "\x1b[22m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+const DEFAULT: &str = "\x1b[39m";
+const RESET: &str = "\x1b[0m";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Line(String),
+    Heading(usize, String),
+    /// Indentation, marker ("-", "1." ...) and text.
+    Item(String, String, String),
+    Quote(Vec<String>),
+    Rule,
+    /// Language and code.
+    Code(String, String),
+    /// Rows of cells, the first being the header.
+    Table(Vec<Vec<String>>),
+}
+
+fn parse_blocks(markdown: &str) -> Vec<Block> {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            let end = (i + 1..lines.len())
+                .find(|&j| lines[j].trim() == "```")
+                .unwrap_or(lines.len());
+            let code = lines[i + 1..end].join("\n");
+            blocks.push(Block::Code(lang.trim().to_string(), code));
+            i = end + 1;
+            continue;
+        }
+        if is_table_row(trimmed) && lines.get(i + 1).is_some_and(|l| is_table_rule(l.trim())) {
+            let mut rows = vec![table_cells(trimmed)];
+            i += 2;
+            while i < lines.len() && is_table_row(lines[i].trim()) {
+                rows.push(table_cells(lines[i].trim()));
+                i += 1;
+            }
+            blocks.push(Block::Table(rows));
+            continue;
+        }
+        if trimmed.starts_with('>') {
+            let mut quoted = Vec::new();
+            while i < lines.len() {
+                let Some(rest) = lines[i].trim().strip_prefix('>') else {
+                    break;
+                };
+                quoted.push(rest.trim_start().to_string());
+                i += 1;
+            }
+            blocks.push(Block::Quote(quoted));
+            continue;
+        }
+        blocks.push(parse_line(line));
+        i += 1;
+    }
+    blocks
+}
+
+fn parse_line(line: &str) -> Block {
+    let trimmed = line.trim();
+    if matches!(trimmed, "---" | "***" | "___") {
+        return Block::Rule;
+    }
+    let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        return Block::Heading(hashes, trimmed[hashes..].trim().to_string());
+    }
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    if let Some((marker, text)) = body.split_once(' ') {
+        let digits = marker.trim_end_matches(['.', ')']);
+        let numbered = digits.len() + 1 == marker.len()
+            && !digits.is_empty()
+            && digits.chars().all(|c| c.is_ascii_digit());
+        if matches!(marker, "-" | "*" | "+") || numbered {
+            return Block::Item(indent.to_string(), marker.to_string(), text.to_string());
+        }
+    }
+    Block::Line(line.to_string())
+}
+
+fn is_table_row(line: &str) -> bool {
+    line.len() > 1 && line.starts_with('|') && line.ends_with('|')
+}
+
+fn is_table_rule(line: &str) -> bool {
+    is_table_row(line)
+        && line.contains('-')
+        && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    line.trim_matches('|')
+        .split('|')
+        .map(|cell| Format::Plain.inline(cell.trim()))
+        .collect()
+}
+
+/// Rows padded into columns, for a monospace block.
+fn align_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            rows.iter()
+                .filter_map(|row| row.get(c))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |row: &[String]| {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(c, &width)| {
+                let cell = row.get(c).map(String::as_str).unwrap_or("");
+                format!("{cell}{}", " ".repeat(width - cell.chars().count()))
+            })
+            .collect();
+        cells.join(" | ").trim_end().to_string()
+    };
+    let mut out = vec![line(&rows[0])];
+    let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+    out.push(rule.join("-+-"));
+    out.extend(rows[1..].iter().map(|row| line(row)));
+    out.join("\n")
+}
+
+/// One line per row, each cell labelled with its header:
+/// "Name: Ada, Role: admin".
+fn flatten_table(rows: &[Vec<String>]) -> String {
+    let header = &rows[0];
+    if rows.len() == 1 {
+        return header.join(", ");
+    }
+    rows[1..]
+        .iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(c, cell)| match header.get(c).filter(|h| !h.is_empty()) {
+                    Some(h) => format!("{h}: {cell}"),
+                    None => cell.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Span {
+    Text(String),
+    Code(String),
+    Bold(Vec<Span>),
+    Italic(Vec<Span>),
+    Strike(Vec<Span>),
+    /// Link text and URL.
+    Link(Vec<Span>, String),
+}
+
+fn parse_inline(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\'
+            && let Some(escaped) = rest[1..].chars().next().filter(char::is_ascii_punctuation)
+        {
+            plain.push(escaped);
+            rest = &rest[1 + escaped.len_utf8()..];
+            continue;
+        }
+        let prev = plain.chars().last();
+        if let Some((span, len)) = parse_span(rest, prev) {
+            if !plain.is_empty() {
+                spans.push(Span::Text(std::mem::take(&mut plain)));
+            }
+            spans.push(span);
+            rest = &rest[len..];
+            continue;
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !plain.is_empty() {
+        spans.push(Span::Text(plain));
+    }
+    spans
+}
+
+/// The span starting at `text`, and its length in bytes.
+fn parse_span(text: &str, prev: Option<char>) -> Option<(Span, usize)> {
+    if let Some(rest) = text.strip_prefix('`') {
+        let end = rest.find('`').filter(|&end| end > 0)?;
+        return Some((Span::Code(rest[..end].to_string()), end + 2));
+    }
+    if text.starts_with('[') {
+        let close = text.find("](")?;
+        let end = close + 2 + text[close + 2..].find(')')?;
+        let url = text[close + 2..end].trim().to_string();
+        return Some((Span::Link(parse_inline(&text[1..close]), url), end + 1));
+    }
+    for (delim, wrap) in [
+        ("**", Span::Bold as fn(Vec<Span>) -> Span),
+        ("__", Span::Bold),
+        ("~~", Span::Strike),
+        ("*", Span::Italic),
+        ("_", Span::Italic),
+    ] {
+        let Some(rest) = text.strip_prefix(delim) else {
+            continue;
+        };
+        // `snake_case` and `2 * 3 * 4` are not emphasis.
+        if rest.starts_with(char::is_whitespace)
+            || (delim.starts_with('_') && prev.is_some_and(char::is_alphanumeric))
+        {
+            return None;
+        }
+        let end = closing(rest, delim)?;
+        return Some((wrap(parse_inline(&rest[..end])), end + 2 * delim.len()));
+    }
+    None
+}
+
+/// Where `delim` closes emphasis in `text`: not after whitespace,
+/// and for `_` not inside a word.
+fn closing(text: &str, delim: &str) -> Option<usize> {
+    text.match_indices(delim).map(|(i, _)| i).find(|&i| {
+        let before = text[..i].chars().last();
+        let after = text[i + delim.len()..].chars().next();
+        let in_word = delim.starts_with('_') && after.is_some_and(char::is_alphanumeric);
+        let double = delim == "*" && after == Some('*');
+        i > 0 && !before.is_some_and(char::is_whitespace) && !double && !in_word
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "## Result\n\
+        Use **bold**, *italic*, ~~old~~ and `a < b`.\n\
+        - see [docs](https://example.com/?a=1&b=2)\n\
+        1. first";
+
+    #[test]
+    fn renders_html_for_telegram() {
+        assert_eq!(
+            Format::Html.render(SAMPLE),
+            "<b>Result</b>\n\
+             Use <b>bold</b>, <i>italic</i>, <s>old</s> and <code>a &lt; b</code>.\n\
+             • see <a href=\"https://example.com/?a=1&amp;b=2\">docs</a>\n\
+             1. first"
+        );
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            Format::Html.render("if a<b && c>d"),
+            "if a&lt;b &amp;&amp; c&gt;d"
+        );
+        assert_eq!(
+            Format::Html.render("```html\n<p class=\"x\">&</p>\n```"),
+            "<pre><code class=\"language-html\">&lt;p class=\"x\"&gt;&amp;&lt;/p&gt;</code></pre>"
+        );
+        assert_eq!(
+            Format::Html.render("[x](https://e.com/\"><script>)"),
+            "<a href=\"https://e.com/&quot;&gt;&lt;script&gt;\">x</a>"
+        );
+        assert_eq!(
+            Format::Html.render("> quoted <b>\n> more"),
+            "<blockquote>quoted &lt;b&gt;\nmore</blockquote>"
+        );
+    }
+
+    #[test]
+    fn renders_whatsapp() {
+        assert_eq!(
+            Format::WhatsApp.render(SAMPLE),
+            "*Result*\n\
+             Use *bold*, _italic_, ~old~ and `a < b`.\n\
+             - see docs (https://example.com/?a=1&b=2)\n\
+             1. first"
+        );
+        assert_eq!(
+            Format::WhatsApp.render("```\nlet x = 1;\n```"),
+            "```let x = 1;```"
+        );
+    }
+
+    #[test]
+    fn renders_plain_text() {
+        assert_eq!(
+            Format::Plain.render(SAMPLE),
+            "Result\n\
+             Use bold, italic, old and a < b.\n\
+             - see docs (https://example.com/?a=1&b=2)\n\
+             1. first"
+        );
+        assert_eq!(
+            Format::Plain.render("<https://e.com> [https://e.com](https://e.com)"),
+            "<https://e.com> https://e.com"
+        );
+    }
+
+    #[test]
+    fn renders_ansi_for_the_terminal() {
+        assert_eq!(
+            Format::Ansi.render(SAMPLE),
+            "\x1b[1m\x1b[4mResult\x1b[0m\n\
+             Use \x1b[1mbold\x1b[22m, \x1b[3mitalic\x1b[23m, \x1b[9mold\x1b[29m and \
+             \x1b[36ma < b\x1b[39m.\n\
+             • see \x1b[4mdocs\x1b[24m \x1b[2m(https://example.com/?a=1&b=2)\x1b[22m\n\
+             1. first"
+        );
+        assert_eq!(
+            Format::Ansi.render("```\nfn main() {}\n```\n> quoted"),
+            "  \x1b[36mfn main() {}\x1b[39m\n\x1b[2m│\x1b[22m quoted"
+        );
+        assert_eq!(
+            Format::Ansi.render("[https://e.com](https://e.com)"),
+            "\x1b[4mhttps://e.com\x1b[24m"
+        );
+    }
+
+    #[test]
+    fn ansi_drops_control_characters() {
+        assert_eq!(
+            Format::Ansi.render("a\x1b[2Jb\tc `\x07x`"),
+            "a[2Jb\tc \x1b[36mx\x1b[39m"
+        );
+    }
+
+    #[test]
+    fn markdown_keeps_formatting_and_escapes_literals() {
+        assert_eq!(Format::Markdown.render(SAMPLE), SAMPLE);
+        assert_eq!(
+            Format::Markdown.render(r"a \*literal\* star and snake_case"),
+            r"a \*literal\* star and snake\_case"
+        );
+        assert_eq!(
+            Format::Plain.render(r"a \*literal\* star"),
+            "a *literal* star"
+        );
+    }
+
+    #[test]
+    fn emphasis_needs_matching_delimiters() {
+        assert_eq!(Format::Html.render("2 * 3 * 4"), "2 * 3 * 4");
+        assert_eq!(Format::Html.render("snake_case_name"), "snake_case_name");
+        assert_eq!(Format::Html.render("**unclosed"), "**unclosed");
+        assert_eq!(
+            Format::Html.render("**bold _and italic_**"),
+            "<b>bold <i>and italic</i></b>"
+        );
+    }
+
+    #[test]
+    fn tables_are_aligned_or_flattened() {
+        let table = "| Name | Role |\n|---|:---:|\n| Ada | **admin** |\n| Bob | user |";
+        assert_eq!(
+            Format::Markdown.render(table),
+            "```\nName | Role\n-----+------\nAda  | admin\nBob  | user\n```"
+        );
+        assert_eq!(
+            Format::Html.render(table),
+            "<pre>Name | Role\n-----+------\nAda  | admin\nBob  | user</pre>"
+        );
+        assert_eq!(
+            Format::Plain.render(table),
+            "Name: Ada, Role: admin\nName: Bob, Role: user"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_not_formatted() {
+        let text = "```rust\nlet s = \"**not bold**\";\n```";
+        assert_eq!(Format::Markdown.render(text), text);
+        assert_eq!(Format::Plain.render(text), "let s = \"**not bold**\";");
+    }
+}
@@ -0,0 +1,4 @@
+//! Where a session's turns come from, shared with
+//! asterbot:scheduler. See `asterbot_common::origin`.
+
+pub use asterbot_common::origin::Origin;
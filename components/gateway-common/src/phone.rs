@@ -0,0 +1,64 @@
+//! Phone number normalisation.
+
+/// Normalises a phone number to E.164 ("+" followed by the
+/// country code and number, 15 digits at most).
+///
+/// Spaces, dashes, dots and parentheses are ignored, a leading
+/// "00" international prefix is read as "+", and a "whatsapp:"
+/// scheme (as Twilio sends for WhatsApp senders) is dropped.
+/// Numbers in national format (leading "0") have no country code
+/// and are rejected, as is anything else that is not a number.
+pub fn normalise_phone(raw: &str) -> Option<String> {
+    let s = raw.trim();
+    let s = s.strip_prefix("whatsapp:").unwrap_or(s);
+    let s = match s.strip_prefix('+') {
+        Some(rest) => rest,
+        None => s.strip_prefix("00").unwrap_or(s),
+    };
+    let mut digits = String::new();
+    for c in s.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '(' | ')' => {}
+            _ => return None,
+        }
+    }
+    let valid = (7..=15).contains(&digits.len()) && !digits.starts_with('0');
+    valid.then(|| format!("+{digits}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_are_normalised() {
+        for raw in [
+            "+14155550123",
+            "14155550123",
+            " +1 (415) 555-0123 ",
+            "001.415.555.0123",
+            "whatsapp:+14155550123",
+        ] {
+            assert_eq!(
+                normalise_phone(raw).as_deref(),
+                Some("+14155550123"),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_numbers_are_rejected() {
+        for raw in [
+            "",
+            "+",
+            "0612345678",
+            "12345",
+            "+1234567890123456",
+            "+1 415 CALL NOW",
+        ] {
+            assert_eq!(normalise_phone(raw), None, "{raw}");
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Text for the parts of a rich reply the platform APIs can't
+//! send: quick replies, locations and linked files.
+
+/// A link to `latitude`, `longitude` on a map.
+pub fn location_link(latitude: f64, longitude: f64) -> String {
+    format!("https://maps.google.com/?q={latitude},{longitude}")
+}
+
+/// A line sharing a location, e.g. "Location: Ribeira,
+/// https://maps.google.com/?q=41.1496,-8.6109".
+pub fn location_text(latitude: f64, longitude: f64, label: &str) -> String {
+    let link = location_link(latitude, longitude);
+    match label.trim() {
+        "" => format!("Location: {link}"),
+        label => format!("Location: {label}, {link}"),
+    }
+}
+
+/// A line listing quick replies the user can type back.
+pub fn options_text(buttons: &[String]) -> String {
+    format!("Reply with: {}", buttons.join(" / "))
+}
+
+/// A line linking to an attached file.
+pub fn link_text(name: &str, url: &str) -> String {
+    match name.trim() {
+        "" => url.to_string(),
+        name => format!("{name}: {url}"),
+    }
+}
+
+/// The text to send for a reply: `text`, then any fallback
+/// `lines`.
+pub fn compose(text: &str, lines: &[String]) -> String {
+    let lines = lines.join("\n");
+    match (text.trim(), lines.as_str()) {
+        (text, "") => text.to_string(),
+        ("", lines) => lines.to_string(),
+        (text, lines) => format!("{text}\n\n{lines}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallbacks() {
+        assert_eq!(
+            location_text(41.1496, -8.6109, "Ribeira"),
+            "Location: Ribeira, https://maps.google.com/?q=41.1496,-8.6109"
+        );
+        assert_eq!(
+            location_text(0.5, 1.0, " "),
+            "Location: https://maps.google.com/?q=0.5,1"
+        );
+        let buttons = vec!["Yes".to_string(), "Not now".to_string()];
+        assert_eq!(options_text(&buttons), "Reply with: Yes / Not now");
+        assert_eq!(
+            link_text("chart.png", "https://x.test/c.png"),
+            "chart.png: https://x.test/c.png"
+        );
+        assert_eq!(link_text("", "https://x.test/c"), "https://x.test/c");
+    }
+
+    #[test]
+    fn replies_are_composed_with_fallbacks() {
+        let lines = vec!["Reply with: Yes / No".to_string()];
+        assert_eq!(
+            compose("Book it?", &lines),
+            "Book it?\n\nReply with: Yes / No"
+        );
+        assert_eq!(compose(" ", &lines), "Reply with: Yes / No");
+        assert_eq!(compose("", &[]), "");
+    }
+}
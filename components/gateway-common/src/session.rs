@@ -0,0 +1,51 @@
+//! Session keys identify a conversation across messages. The
+//! gateways pass them to `agent.converse`, and
+//! asterbot:history keeps each session's messages and summaries
+//! in a file of its own.
+
+use std::fmt;
+
+/// "<gateway>:dm:<sender>" for private chats and
+/// "<gateway>:group:<chat>" for group chats and channels.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionKey(String);
+
+impl SessionKey {
+    pub fn direct(gateway: &str, sender: &str) -> Self {
+        SessionKey(format!("{gateway}:dm:{}", sender.trim()))
+    }
+
+    pub fn group(gateway: &str, chat: &str) -> Self {
+        SessionKey(format!("{gateway}:group:{}", chat.trim()))
+    }
+
+    /// Whether everyone in a group chat or channel shares it.
+    pub fn is_group(&self) -> bool {
+        self.0.split(':').nth(1) == Some("group")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_distinguish_private_and_group_chats() {
+        let direct = SessionKey::direct("telegram", "123");
+        let group = SessionKey::group("telegram", "123");
+        assert_eq!(direct.as_str(), "telegram:dm:123");
+        assert_eq!(group.to_string(), "telegram:group:123");
+        assert_ne!(direct, group);
+        assert!(group.is_group() && !direct.is_group());
+    }
+}
@@ -0,0 +1,292 @@
+//! SMS segments, "reply MORE to continue" and carrier keywords.
+//!
+//! Long SMS are sent as segments billed one by one: 160 GSM-7
+//! characters, or 70 UCS-2 code units once any character is
+//! outside the GSM-7 alphabet (e.g. an emoji). Concatenated
+//! messages lose a few characters per segment to the header, so
+//! each holds 153 or 67. [`SmsSplitter`] sends a reply as one
+//! message of at most `max_segments` segments and keeps the rest
+//! in the host dir until the sender replies MORE.
+
+use crate::chunk::chunk;
+use crate::resolve_host_dir;
+
+/// Where replies waiting for MORE are kept, under the host dir.
+pub const MORE_DIR: &str = "more";
+/// Twilio rejects message bodies longer than this.
+pub const MAX_BODY_CHARS: usize = 1600;
+
+const DEFAULT_MAX_SEGMENTS: usize = 4;
+const MORE_NOTE: &str = "\n\n(Reply MORE for the rest)";
+const TRUNCATED_NOTE: &str = "\n\n(truncated)";
+
+/// The GSM 03.38 basic alphabet.
+const GSM7: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+    ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+/// Characters sent as an escape and a second septet.
+const GSM7_EXTENSION: &str = "^{}\\[~]|€";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gsm7,
+    Ucs2,
+}
+
+impl Encoding {
+    pub fn of(text: &str) -> Self {
+        match text
+            .chars()
+            .all(|c| GSM7.contains(c) || GSM7_EXTENSION.contains(c))
+        {
+            true => Encoding::Gsm7,
+            false => Encoding::Ucs2,
+        }
+    }
+
+    /// Characters in one segment when the message fits in one,
+    /// and per segment when it doesn't.
+    fn segment_sizes(self) -> (usize, usize) {
+        match self {
+            Encoding::Gsm7 => (160, 153),
+            Encoding::Ucs2 => (70, 67),
+        }
+    }
+}
+
+/// How many segments `text` is sent as.
+pub fn segments(text: &str) -> usize {
+    let encoding = Encoding::of(text);
+    let units = match encoding {
+        Encoding::Gsm7 => text
+            .chars()
+            .map(|c| 1 + GSM7_EXTENSION.contains(c) as usize)
+            .sum(),
+        Encoding::Ucs2 => text.encode_utf16().count(),
+    };
+    let (single, multi) = encoding.segment_sizes();
+    match units {
+        0 => 0,
+        n if n <= single => 1,
+        n => n.div_ceil(multi),
+    }
+}
+
+/// Carrier keywords, matched against the whole message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    /// Opt out of messages from the bot.
+    Stop,
+    /// Opt back in.
+    Start,
+    Help,
+    More,
+}
+
+impl Keyword {
+    pub fn of(text: &str) -> Option<Keyword> {
+        match text.trim().to_ascii_uppercase().as_str() {
+            "STOP" | "STOPALL" | "UNSUBSCRIBE" | "CANCEL" | "END" | "QUIT" | "OPTOUT" => {
+                Some(Keyword::Stop)
+            }
+            "START" | "YES" | "UNSTOP" => Some(Keyword::Start),
+            "HELP" | "INFO" => Some(Keyword::Help),
+            "MORE" => Some(Keyword::More),
+            _ => None,
+        }
+    }
+}
+
+/// Sends replies as single messages of a few segments each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmsSplitter {
+    pub max_segments: usize,
+    /// Keep what doesn't fit for MORE, rather than dropping it.
+    pub more: bool,
+    /// Prefixes pending reply files, as gateways share the host dir.
+    gateway: String,
+    dir: Option<String>,
+}
+
+impl SmsSplitter {
+    pub fn new(gateway: &str, max_segments: usize, more: bool, host_dir: &str) -> Self {
+        SmsSplitter {
+            max_segments: max_segments.max(1),
+            more,
+            gateway: gateway.to_string(),
+            dir: (!host_dir.is_empty()).then(|| format!("{host_dir}/{MORE_DIR}")),
+        }
+    }
+
+    /// Reads `<PREFIX>_MAX_SEGMENTS` (default 4) and `<PREFIX>_MORE`
+    /// ("false" drops what doesn't fit).
+    pub fn from_env(prefix: &str) -> Self {
+        let max_segments = std::env::var(format!("{prefix}_MAX_SEGMENTS"))
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&n: &usize| n > 0)
+            .unwrap_or(DEFAULT_MAX_SEGMENTS);
+        let more = !std::env::var(format!("{prefix}_MORE"))
+            .is_ok_and(|v| v.trim().eq_ignore_ascii_case("false"));
+        SmsSplitter::new(
+            &prefix.to_ascii_lowercase(),
+            max_segments,
+            more,
+            &resolve_host_dir(),
+        )
+    }
+
+    /// The message to send `to` for a new reply. Anything that
+    /// doesn't fit is kept for MORE, replacing what was kept for
+    /// the previous reply.
+    pub fn first(&self, to: &str, text: &str) -> String {
+        let (message, rest) = self.fit(text);
+        self.keep(to, rest.as_deref());
+        message
+    }
+
+    /// The next message of the reply kept for `to`, if any.
+    pub fn next(&self, to: &str) -> Option<String> {
+        let text = std::fs::read_to_string(self.path(to)?).ok()?;
+        Some(self.first(to, &text))
+    }
+
+    /// Splits `text` into a message within the segment cap and
+    /// the rest, if it doesn't fit.
+    fn fit(&self, text: &str) -> (String, Option<String>) {
+        let text = text.trim();
+        if self.fits(text) {
+            return (text.to_string(), None);
+        }
+        let keeps_rest = self.more && self.dir.is_some();
+        let note = match keeps_rest {
+            true => MORE_NOTE,
+            false => TRUNCATED_NOTE,
+        };
+        let (_, per_segment) = Encoding::of(text).segment_sizes();
+        let mut budget = (per_segment * self.max_segments).min(MAX_BODY_CHARS);
+        // Extension characters and emoji take more than one unit,
+        // so the budget shrinks until the message fits.
+        let (head, rest) = loop {
+            let parts = chunk(text, budget.saturating_sub(note.chars().count()).max(1));
+            let head = parts[0].clone();
+            if self.fits(&format!("{head}{note}")) || budget <= note.len() {
+                let rest = match text.strip_prefix(head.as_str()) {
+                    Some(rest) => rest.trim().to_string(),
+                    None => parts[1..].join("\n"),
+                };
+                break (head, rest);
+            }
+            budget -= budget / 10 + 1;
+        };
+        match (rest.is_empty(), keeps_rest) {
+            (true, _) => (head, None),
+            (false, true) => (format!("{head}{note}"), Some(rest)),
+            (false, false) => (format!("{head}{note}"), None),
+        }
+    }
+
+    fn fits(&self, message: &str) -> bool {
+        segments(message) <= self.max_segments && message.chars().count() <= MAX_BODY_CHARS
+    }
+
+    fn keep(&self, to: &str, rest: Option<&str>) {
+        let Some(path) = self.path(to) else {
+            return;
+        };
+        let result = match rest {
+            Some(rest) => self
+                .dir
+                .as_ref()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&path, rest)),
+            None => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        };
+        if let Err(e) = result {
+            eprintln!("failed to update {path}: {e}");
+        }
+    }
+
+    fn path(&self, to: &str) -> Option<String> {
+        let name: String = format!("{}-{to}", self.gateway)
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '+' => c,
+                _ => '_',
+            })
+            .collect();
+        Some(format!("{}/{name}", self.dir.as_ref()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_depend_on_the_encoding() {
+        assert_eq!(segments(""), 0);
+        assert_eq!(segments(&"a".repeat(160)), 1);
+        assert_eq!(segments(&"a".repeat(161)), 2);
+        assert_eq!(segments(&"a".repeat(306)), 2);
+        assert_eq!(segments(&"a".repeat(307)), 3);
+        // Extension characters take two septets.
+        assert_eq!(Encoding::of("a{b}€"), Encoding::Gsm7);
+        assert_eq!(segments(&"€".repeat(80)), 1);
+        assert_eq!(segments(&"€".repeat(81)), 2);
+        // One character outside GSM-7 switches the whole message.
+        assert_eq!(Encoding::of("café ✓"), Encoding::Ucs2);
+        assert_eq!(segments(&format!("{}✓", "a".repeat(69))), 1);
+        assert_eq!(segments(&format!("{}✓", "a".repeat(70))), 2);
+        // Emoji are two UTF-16 code units.
+        assert_eq!(segments(&"🙂".repeat(35)), 1);
+        assert_eq!(segments(&"🙂".repeat(36)), 2);
+    }
+
+    #[test]
+    fn keywords_match_whole_messages() {
+        assert_eq!(Keyword::of(" stop "), Some(Keyword::Stop));
+        assert_eq!(Keyword::of("Unsubscribe"), Some(Keyword::Stop));
+        assert_eq!(Keyword::of("START"), Some(Keyword::Start));
+        assert_eq!(Keyword::of("help"), Some(Keyword::Help));
+        assert_eq!(Keyword::of("more"), Some(Keyword::More));
+        assert_eq!(Keyword::of("stop the timer"), None);
+        assert_eq!(Keyword::of("tell me more"), None);
+    }
+
+    #[test]
+    fn long_replies_continue_on_more() {
+        let dir = std::env::temp_dir().join(format!("gateway-sms-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let sms = SmsSplitter::new("twilio", 2, true, dir.to_str().unwrap());
+        let sentence = "This sentence is forty characters long. ";
+        let reply = sentence.repeat(20);
+        let first = sms.first("+14155550123", &reply);
+        assert!(first.ends_with("\n\n(Reply MORE for the rest)"));
+        assert!(segments(&first) <= 2);
+        let mut sent = first.trim_end_matches(MORE_NOTE).to_string();
+        while let Some(next) = sms.next("+14155550123") {
+            assert!(segments(&next) <= 2);
+            sent.push(' ');
+            sent.push_str(next.trim_end_matches(MORE_NOTE));
+        }
+        assert_eq!(sent, reply.trim());
+        // A new reply replaces what was kept.
+        sms.first("+14155550123", &reply);
+        assert_eq!(sms.first("+14155550123", "Short."), "Short.");
+        assert_eq!(sms.next("+14155550123"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replies_are_truncated_without_more() {
+        let sms = SmsSplitter::new("twilio", 1, true, "");
+        let reply = "Not GSM-7: ✓ ".repeat(10);
+        let first = sms.first("+14155550123", &reply);
+        assert!(first.ends_with("(truncated)"));
+        assert_eq!(segments(&first), 1);
+        assert_eq!(sms.next("+14155550123"), None);
+    }
+}
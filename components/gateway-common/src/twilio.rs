@@ -0,0 +1,131 @@
+//! Twilio webhook signature verification.
+//!
+//! Twilio signs each webhook request with the account's auth
+//! token: the `X-Twilio-Signature` header is the base64 HMAC-SHA1
+//! of the webhook URL followed by the POST parameters, sorted by
+//! name, each name directly followed by its value. Requests with a
+//! missing or wrong signature did not come from Twilio.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// Checks webhook signatures against the auth token. Without a
+/// token, no signature is valid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verifier {
+    auth_token: String,
+    /// The URL Twilio calls, when the host sees a different one
+    /// (e.g. behind a tunnel or proxy).
+    url: Option<String>,
+}
+
+impl Verifier {
+    pub fn new(auth_token: &str, url: Option<&str>) -> Self {
+        Verifier {
+            auth_token: auth_token.to_string(),
+            url: url.filter(|u| !u.is_empty()).map(str::to_string),
+        }
+    }
+
+    /// Reads `<PREFIX>_AUTH_TOKEN` and `<PREFIX>_WEBHOOK_URL`.
+    /// Returns `None`, so signatures are not checked, only if
+    /// `<PREFIX>_VERIFY_SIGNATURES=false`; without an auth token,
+    /// every request is refused.
+    pub fn from_env(prefix: &str) -> Option<Self> {
+        let is_off = std::env::var(format!("{prefix}_VERIFY_SIGNATURES"))
+            .is_ok_and(|v| v.trim().eq_ignore_ascii_case("false"));
+        if is_off {
+            eprintln!("Twilio webhook signatures are not verified");
+            return None;
+        }
+        let auth_token = std::env::var(format!("{prefix}_AUTH_TOKEN")).unwrap_or_default();
+        if auth_token.is_empty() {
+            eprintln!(
+                "refusing every Twilio webhook: set {prefix}_AUTH_TOKEN, \
+                 or {prefix}_VERIFY_SIGNATURES=false"
+            );
+        }
+        let url = std::env::var(format!("{prefix}_WEBHOOK_URL")).ok();
+        Some(Verifier::new(&auth_token, url.as_deref()))
+    }
+
+    /// Whether `signature` is Twilio's for a request to `url`
+    /// with `params`.
+    pub fn verify(&self, url: &str, params: &[(String, String)], signature: &str) -> bool {
+        if self.auth_token.is_empty() {
+            return false;
+        }
+        let Ok(signature) = BASE64.decode(signature.trim()) else {
+            return false;
+        };
+        let url = self.url.as_deref().unwrap_or(url);
+        // Compares in constant time, so signatures can't be
+        // guessed byte by byte.
+        mac(&self.auth_token, url, params)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+/// The signature Twilio sends for a request to `url` with `params`.
+pub fn sign(auth_token: &str, url: &str, params: &[(String, String)]) -> String {
+    BASE64.encode(mac(auth_token, url, params).finalize().into_bytes())
+}
+
+fn mac(auth_token: &str, url: &str, params: &[(String, String)]) -> Hmac<Sha1> {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(auth_token.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(url.as_bytes());
+    let mut params: Vec<&(String, String)> = params.iter().collect();
+    params.sort();
+    for (name, value) in params {
+        mac.update(name.as_bytes());
+        mac.update(value.as_bytes());
+    }
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> Vec<(String, String)> {
+        [
+            ("CallSid", "CA1234567890ABCDE"),
+            ("Caller", "+14158675310"),
+            ("Digits", "1234"),
+            ("From", "+14158675310"),
+            ("To", "+18005551212"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    /// The example in Twilio's webhook security docs.
+    #[test]
+    fn signatures_are_verified() {
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        let signature = "GvWf1cFY/Q7PnoempGyD5oXAezc=";
+        let mut shuffled = params();
+        shuffled.reverse();
+        assert_eq!(sign("12345", url, &shuffled), signature);
+        let verifier = Verifier::new("12345", None);
+        assert!(verifier.verify(url, &params(), signature));
+        assert!(!verifier.verify(url, &params(), "GvWf1cFY/Q7PnoempGyD5oXAezc"));
+        assert!(!verifier.verify("https://mycompany.com/myapp.php", &params(), signature));
+        assert!(!Verifier::new("54321", None).verify(url, &params(), signature));
+        // Twilio signs the public URL, which the host may not see.
+        let behind_proxy = Verifier::new("12345", Some(url));
+        assert!(behind_proxy.verify("http://localhost:8080/twilio", &params(), signature));
+    }
+
+    #[test]
+    fn nothing_is_verified_without_an_auth_token() {
+        let url = "https://mycompany.com/myapp.php";
+        let forged = sign("", url, &params());
+        assert!(!Verifier::new("", None).verify(url, &params(), &forged));
+    }
+}
@@ -1,13 +1,22 @@
 #[cfg(not(test))]
 use crate::bindings::asterai::fs::fs;
-use crate::bindings::asterai::llm::llm::ChatResponse;
 #[cfg(not(test))]
-use crate::bindings::asterai::llm::llm::{chat, ChatMessage, ChatRole, ToolCall, ToolDefinition};
+use crate::bindings::asterai::llm::llm::{
+    chat, ChatMessage, ChatResponse, ChatRole, ToolCall, ToolDefinition,
+};
 #[cfg(not(test))]
 use crate::bindings::exports::asterbot::types::history::Guest;
+#[cfg(not(test))]
+use asterbot_common::events::EventLog;
+#[cfg(not(test))]
+use asterbot_common::usage::{self, Ledger, ModelUsage};
 use serde::{Deserialize, Serialize};
 
+#[cfg(not(test))]
+const COMPONENT: &str = "asterbot:history";
 const HISTORY_FILENAME: &str = "conversation.json";
+#[cfg(not(test))]
+const CHARS_PER_TOKEN: usize = 4;
 const DEFAULT_COMPACTION_THRESHOLD: usize = 50;
 const TOOL_RESULT_PREVIEW_CHARS: usize = 200;
 
@@ -124,32 +133,19 @@ struct CompactionResult {
 #[cfg(not(test))]
 impl Guest for Component {
     fn load() -> Vec<ChatMessage> {
-        let state = read_state();
-        let start = state.compacted_through.min(state.history.len());
-        state.history[start..]
-            .iter()
-            .map(|m| m.to_chat_message())
-            .collect()
+        load_working_set("")
     }
 
     fn save(messages: Vec<ChatMessage>) {
-        let mut state = read_state();
-        let start = state.compacted_through.min(state.history.len());
-        // Keep the archived portion, replace the working set.
-        state.history.truncate(start);
-        state
-            .history
-            .extend(messages.iter().map(PersistedMessage::from_chat_message));
-        write_state(&state);
+        save_working_set("", messages);
     }
 
     fn clear() {
-        let _ = fs::rm(&history_path(), false);
+        let _ = fs::rm(&history_path(""), false);
     }
 
     fn get_context() -> String {
-        let state = read_state();
-        format_context(&state)
+        format_context(&read_state(""))
     }
 
     fn should_compact(message_count: u32) -> bool {
@@ -161,41 +157,129 @@ impl Guest for Component {
     }
 
     fn compact(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
-        let model = std::env::var("ASTERBOT_MODEL").unwrap_or_default();
-        if model.is_empty() || messages.is_empty() {
-            return messages;
+        compact_working_set("", None, messages)
+    }
+
+    fn compact_in_turn(turn_id: String, messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        compact_working_set("", Some(&turn_id), messages)
+    }
+
+    fn load_session(session: String) -> Vec<ChatMessage> {
+        load_working_set(&session)
+    }
+
+    fn save_session(session: String, messages: Vec<ChatMessage>) {
+        save_working_set(&session, messages);
+    }
+
+    fn clear_session(session: String) {
+        let _ = fs::rm(&history_path(&session), false);
+    }
+
+    fn get_session_context(session: String) -> String {
+        format_context(&read_state(&session))
+    }
+
+    fn compact_session(
+        session: String,
+        turn_id: String,
+        messages: Vec<ChatMessage>,
+    ) -> Vec<ChatMessage> {
+        compact_working_set(&session, Some(&turn_id), messages)
+    }
+}
+
+#[cfg(not(test))]
+fn load_working_set(session: &str) -> Vec<ChatMessage> {
+    let state = read_state(session);
+    let start = state.compacted_through.min(state.history.len());
+    state.history[start..]
+        .iter()
+        .map(|m| m.to_chat_message())
+        .collect()
+}
+
+#[cfg(not(test))]
+fn save_working_set(session: &str, messages: Vec<ChatMessage>) {
+    let mut state = read_state(session);
+    let start = state.compacted_through.min(state.history.len());
+    // Keep the archived portion, replace the working set.
+    state.history.truncate(start);
+    state
+        .history
+        .extend(messages.iter().map(PersistedMessage::from_chat_message));
+    write_state(session, &state);
+}
+
+/// Compacts a session's working set, tagging the compaction's
+/// event with the turn core passed in, if any.
+#[cfg(not(test))]
+fn compact_working_set(
+    session: &str,
+    turn_id: Option<&str>,
+    messages: Vec<ChatMessage>,
+) -> Vec<ChatMessage> {
+    let model = std::env::var("ASTERBOT_MODEL").unwrap_or_default();
+    if model.is_empty() || messages.is_empty() {
+        return messages;
+    }
+    let mut state = read_state(session);
+    // Compact the entire working set into summaries.
+    let old = messages;
+    let formatted = format_messages_for_summary(&old);
+    let prompt = build_compaction_prompt(
+        &formatted,
+        &state.conversation_summary,
+        &state.user_summary,
+        &state.bond_summary,
+    );
+    let tools = vec![build_compaction_tool()];
+    let events = EventLog::from_env(COMPONENT);
+    let started = std::time::Instant::now();
+    let response = chat(&prompt, &tools, &model);
+    // asterai:llm has no error channel: provider failures come
+    // back in-band as "error: ..." content.
+    match response.content.strip_prefix("error: ") {
+        Some(message) if response.tool_calls.is_empty() => {
+            eprintln!("error: compaction LLM call failed: {message}");
         }
-        let mut state = read_state();
-        // Compact the entire working set into summaries.
-        let old = messages;
-        let formatted = format_messages_for_summary(&old);
-        let prompt = build_compaction_prompt(
-            &formatted,
-            &state.conversation_summary,
-            &state.user_summary,
-            &state.bond_summary,
-        );
-        let tools = vec![build_compaction_tool()];
-        let response = chat(&prompt, &tools, &model);
-        // Parse the structured tool call response.
-        let did_succeed = apply_compaction_from_llm_response(response, &mut state);
-        // Fallback: raw text summary when LLM fails.
-        if !did_succeed {
-            let fallback = truncate_str(&formatted, 500);
-            if state.conversation_summary.is_empty() {
-                state.conversation_summary = fallback;
-            } else {
-                state.conversation_summary = format!(
-                    "{}\n\n[auto-compacted]\n{}",
-                    state.conversation_summary, fallback,
-                );
-            }
+        _ => record_compaction_usage(session, &model, &prompt, &tools, &response),
+    }
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let summary = match response.tool_calls.first() {
+        Some(tc) => events.content(&tc.arguments_json),
+        None => events.content(&response.content),
+    };
+    // Parse the structured tool call response.
+    let did_succeed = apply_compaction_from_llm_response(response, &mut state);
+    events.emit(
+        "compaction.summarised",
+        serde_json::json!({
+            "turnId": turn_id,
+            "session": session,
+            "model": model,
+            "messages": old.len(),
+            "durationMs": duration_ms,
+            "ok": did_succeed,
+            "summary": summary,
+        }),
+    );
+    // Fallback: raw text summary when LLM fails.
+    if !did_succeed {
+        let fallback = truncate_str(&formatted, 500);
+        if state.conversation_summary.is_empty() {
+            state.conversation_summary = fallback;
+        } else {
+            state.conversation_summary = format!(
+                "{}\n\n[auto-compacted]\n{}",
+                state.conversation_summary, fallback,
+            );
         }
-        // Advance cursor past all compacted messages.
-        state.compacted_through += old.len();
-        write_state(&state);
-        vec![]
     }
+    // Advance cursor past all compacted messages.
+    state.compacted_through += old.len();
+    write_state(session, &state);
+    vec![]
 }
 
 #[cfg(not(test))]
@@ -380,8 +464,94 @@ fn build_compaction_prompt(
     vec![system, user_msg]
 }
 
+/// Adds a compaction call to the usage ledger core keeps, with
+/// token counts estimated from its size.
+#[cfg(not(test))]
+fn record_compaction_usage(
+    session: &str,
+    model: &str,
+    prompt: &[ChatMessage],
+    tools: &[ToolDefinition],
+    response: &ChatResponse,
+) {
+    let (input_tokens, output_tokens) = estimate_usage(prompt, tools, response);
+    // Read afresh, so core's totals written since are kept.
+    let path = state_path(usage::USAGE_FILENAME);
+    let mut ledger = fs::read(&path)
+        .map(|bytes| Ledger::parse(&bytes))
+        .unwrap_or_default();
+    ledger.record(
+        &usage::today(),
+        session,
+        model,
+        ModelUsage {
+            calls: 1,
+            input_tokens,
+            output_tokens,
+        },
+    );
+    match serde_json::to_string_pretty(&ledger) {
+        Ok(json) => {
+            if let Err(e) = fs::write(&path, json.as_bytes()) {
+                eprintln!("error: failed to write {path}: {e}");
+            }
+        }
+        Err(e) => eprintln!("error: failed to serialise usage: {e}"),
+    }
+}
+
+/// Input and output tokens estimated from the call's size.
+#[cfg(not(test))]
+fn estimate_usage(
+    prompt: &[ChatMessage],
+    tools: &[ToolDefinition],
+    response: &ChatResponse,
+) -> (u64, u64) {
+    let input_chars: usize = prompt.iter().map(|m| m.content.len()).sum::<usize>()
+        + tools
+            .iter()
+            .map(|t| t.name.len() + t.description.len() + t.parameters_json_schema.len())
+            .sum::<usize>();
+    let output_chars = response.content.len()
+        + response
+            .tool_calls
+            .iter()
+            .map(|tc| tc.name.len() + tc.arguments_json.len())
+            .sum::<usize>();
+    (
+        input_chars.div_ceil(CHARS_PER_TOKEN) as u64,
+        output_chars.div_ceil(CHARS_PER_TOKEN) as u64,
+    )
+}
+
+#[cfg(not(test))]
+fn history_path(session: &str) -> String {
+    state_path(&history_filename(session))
+}
+
+/// `conversation.json` for the default conversation, and
+/// `conversation.<session>.json` for others, with ':' as '_' and
+/// characters other than letters, digits, '-' and '.' as %XX, so
+/// no two sessions share a file.
+fn history_filename(session: &str) -> String {
+    if session.is_empty() {
+        return HISTORY_FILENAME.to_string();
+    }
+    let mut name = String::from("conversation.");
+    for b in session.bytes() {
+        match b {
+            b':' => name.push('_'),
+            b'-' | b'.' => name.push(b as char),
+            b if b.is_ascii_alphanumeric() => name.push(b as char),
+            b => name.push_str(&format!("%{b:02X}")),
+        }
+    }
+    name.push_str(".json");
+    name
+}
+
 #[cfg(not(test))]
-fn history_path() -> String {
+fn state_path(filename: &str) -> String {
     let dir = std::env::var("ASTERBOT_HOST_DIR")
         .or_else(|_| {
             std::env::var("ASTERAI_ALLOWED_DIRS")
@@ -389,15 +559,15 @@ fn history_path() -> String {
         })
         .unwrap_or_default();
     if dir.is_empty() {
-        HISTORY_FILENAME.to_string()
+        filename.to_string()
     } else {
-        format!("{dir}/{HISTORY_FILENAME}")
+        format!("{dir}/{filename}")
     }
 }
 
 #[cfg(not(test))]
-fn read_state() -> ConversationState {
-    let path = history_path();
+fn read_state(session: &str) -> ConversationState {
+    let path = history_path(session);
     let bytes = match fs::read(&path) {
         Ok(b) => b,
         Err(_) => return ConversationState::default(),
@@ -414,8 +584,8 @@ fn read_state() -> ConversationState {
 }
 
 #[cfg(not(test))]
-fn write_state(state: &ConversationState) {
-    let path = history_path();
+fn write_state(session: &str, state: &ConversationState) {
+    let path = history_path(session);
     match serde_json::to_string_pretty(state) {
         Ok(json) => {
             if let Err(e) = fs::write(&path, json.as_bytes()) {
@@ -789,6 +959,23 @@ mod tests {
         assert!(ctx.contains("## Bond\nCasual and technical"));
     }
 
+    #[test]
+    fn sessions_have_their_own_files() {
+        assert_eq!(history_filename(""), "conversation.json");
+        assert_eq!(
+            history_filename("telegram:group:-100123"),
+            "conversation.telegram_group_-100123.json"
+        );
+        assert_eq!(
+            history_filename("email:thread:<a/b@example.org>"),
+            "conversation.email_thread_%3Ca%2Fb%40example.org%3E.json"
+        );
+        // Escapes are escaped, so distinct sessions never collide.
+        assert_ne!(history_filename("a:b"), history_filename("a_b"));
+        assert_ne!(history_filename("a%3Ab"), history_filename("a:b"));
+        assert_ne!(history_filename("../x"), history_filename("..%2Fx"));
+    }
+
     #[test]
     fn truncate_short_string() {
         assert_eq!(truncate_str("hello", 10), "hello");
@@ -0,0 +1,232 @@
+#[cfg(not(test))]
+use crate::bindings::asterai::host::api as host_api;
+#[cfg(not(test))]
+use crate::bindings::asterbot::types::agent;
+#[cfg(not(test))]
+use crate::bindings::asterbot::types::types::{
+    self as agent_types, AgentError, ConverseRequest, ConverseResponse, InputPart, MediaData,
+    ProgressTarget,
+};
+#[cfg(not(test))]
+use crate::bindings::exports::asterbot::types::progress_listener::Guest as ProgressGuest;
+#[cfg(not(test))]
+use crate::bindings::exports::asterbot::types::repl::Guest;
+#[cfg(not(test))]
+use crate::terminal::Input;
+#[cfg(not(test))]
+use gateway_common::{Commands, EventLog, Format, Host, IdKind, SessionKey, reply};
+#[cfg(not(test))]
+use std::io::{BufRead, Write};
+#[cfg(not(test))]
+use std::sync::LazyLock;
+#[cfg(not(test))]
+use std::time::Instant;
+
+mod terminal;
+
+#[cfg(not(test))]
+const COMPONENT: &str = "asterbot:repl";
+
+/// The person at the terminal, who may run every command.
+#[cfg(not(test))]
+const USER: &str = "local";
+
+#[cfg(not(test))]
+#[allow(warnings)]
+mod bindings {
+    wit_bindgen::generate!({
+        path: "wit/package.wasm",
+        world: "component",
+        generate_all,
+    });
+}
+
+#[cfg(not(test))]
+static COMMANDS: LazyLock<Commands> =
+    LazyLock::new(|| Commands::from_env("REPL", IdKind::User, &[USER.to_string()]));
+
+/// ANSI unless NO_COLOR (https://no-color.org) is set.
+#[cfg(not(test))]
+static FORMAT: LazyLock<Format> = LazyLock::new(|| {
+    let no_color = std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
+    Format::from_env(
+        "REPL",
+        match no_color {
+            true => Format::Plain,
+            false => Format::Ansi,
+        },
+    )
+});
+
+/// Whether tool calls are printed as the agent makes them, from
+/// `REPL_PROGRESS` (`status`, the default, or `off`).
+#[cfg(not(test))]
+static PROGRESS: LazyLock<bool> = LazyLock::new(|| {
+    let var = std::env::var("REPL_PROGRESS").unwrap_or_default();
+    match var.trim().to_ascii_lowercase().as_str() {
+        "" | "status" => true,
+        "off" => false,
+        other => {
+            eprintln!("unknown REPL_PROGRESS {other:?}, using \"status\"");
+            true
+        }
+    }
+});
+
+#[cfg(not(test))]
+struct Component;
+
+#[cfg(not(test))]
+struct WasmHost;
+
+#[cfg(not(test))]
+impl Host for WasmHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String> {
+        host_api::call_component_function(component, function, args_json).map_err(|e| e.message)
+    }
+}
+
+#[cfg(not(test))]
+impl Guest for Component {
+    fn run() {
+        let events = EventLog::from_env(COMPONENT);
+        let session = SessionKey::direct("repl", USER);
+        println!("asterbot — /help lists commands, /exit or Ctrl-D leaves.");
+        let stdin = std::io::stdin();
+        let mut input = Input::new();
+        loop {
+            print!("{}", input.prompt());
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            let message = match stdin.lock().read_line(&mut line) {
+                Ok(0) => match input.finish() {
+                    Some(message) => message,
+                    None => break,
+                },
+                Ok(_) => match input.push(&line) {
+                    Some(message) => message,
+                    None => continue,
+                },
+                Err(e) => {
+                    eprintln!("failed to read input: {e}");
+                    break;
+                }
+            };
+            if terminal::is_exit(&message) {
+                break;
+            }
+            turn(&message, &session, &events);
+        }
+        println!();
+    }
+}
+
+#[cfg(not(test))]
+impl ProgressGuest for Component {
+    /// Shows each tool call as the agent makes it.
+    fn on_progress(_context: String, progress: agent_types::Progress) {
+        if progress.tool.is_some() {
+            println!("{}", terminal::tool_line(*FORMAT, &progress.status));
+        }
+    }
+}
+
+/// Runs a command or an agent turn, and prints the reply.
+#[cfg(not(test))]
+fn turn(input: &str, session: &SessionKey, events: &EventLog) {
+    let started = Instant::now();
+    if let Some(handled) = COMMANDS.handle(&WasmHost, input, USER, session) {
+        println!("{}\n", handled.reply);
+        events.emit(
+            "command.handled",
+            serde_json::json!({
+                "sender": USER,
+                "session": session.as_str(),
+                "command": handled.command,
+                "durationMs": started.elapsed().as_millis() as u64,
+            }),
+        );
+        return;
+    }
+    let response = converse(input, session);
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let text = match &response {
+        Ok(response) => reply_text(response),
+        Err(e) => format!("error: {}", e.message),
+    };
+    println!("{}", FORMAT.render(&text));
+    if let Ok(response) = &response {
+        let usage = &response.usage;
+        let line = terminal::usage_line(
+            *FORMAT,
+            response.tool_invocations.len(),
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cost_usd,
+            duration_ms,
+        );
+        println!("{line}");
+    }
+    println!();
+    events.emit(
+        "message.handled",
+        serde_json::json!({
+            "turnId": response.as_ref().ok().map(|r| &r.turn_id),
+            "sender": USER,
+            "session": session.as_str(),
+            "durationMs": duration_ms,
+            "parts": 1,
+            "replyAttachments": response.as_ref().map_or(0, |r| r.attachments.len()),
+            "input": events.content(input),
+            "reply": events.content(&text),
+        }),
+    );
+}
+
+/// Sends the message to the agent in `session`, reporting tool
+/// calls back to this component.
+#[cfg(not(test))]
+fn converse(text: &str, session: &SessionKey) -> Result<ConverseResponse, AgentError> {
+    agent::converse(&ConverseRequest {
+        session: Some(session.as_str().to_string()),
+        parts: vec![InputPart::Text(text.to_string())],
+        progress: PROGRESS.then(|| ProgressTarget {
+            component: COMPONENT.to_string(),
+            context: session.to_string(),
+        }),
+    })
+}
+
+/// The reply's text, with the files the agent wrote named,
+/// links to files at a URL and text fallbacks for quick replies
+/// and the location.
+#[cfg(not(test))]
+fn reply_text(response: &ConverseResponse) -> String {
+    let mut lines: Vec<String> = response
+        .attachments
+        .iter()
+        .map(|media| match &media.data {
+            MediaData::Url(url) => reply::link_text(&media.name, url),
+            MediaData::Bytes(data) => format!("File: {} ({} bytes)", media.name, data.len()),
+        })
+        .collect();
+    if !response.buttons.is_empty() {
+        lines.push(reply::options_text(&response.buttons));
+    }
+    if let Some(location) = &response.location {
+        lines.push(reply::location_text(
+            location.latitude,
+            location.longitude,
+            &location.label,
+        ));
+    }
+    reply::compose(&response.reply, &lines)
+}
+
+#[cfg(not(test))]
+bindings::export!(Component with_types_in bindings);
@@ -0,0 +1,174 @@
+//! Reading messages in the terminal REPL, and the lines it shows
+//! around the agent's reply.
+//!
+//! A message is one line, unless it ends with `\`, which
+//! continues it on the next line, or is wrapped in `"""` lines,
+//! which keep everything between them, blank lines included.
+
+use gateway_common::Format;
+
+/// Starts and ends a block of lines sent as one message.
+pub const BLOCK_DELIMITER: &str = "\"\"\"";
+
+/// A message being typed, line by line.
+#[derive(Debug, Default)]
+pub struct Input {
+    lines: Vec<String>,
+    in_block: bool,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input::default()
+    }
+
+    /// Adds a line read from the terminal, without its line
+    /// ending. Returns the message once it is complete; blank
+    /// messages are dropped.
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim() == BLOCK_DELIMITER {
+            self.in_block = !self.in_block;
+            if self.in_block {
+                return None;
+            }
+            return self.take();
+        }
+        if self.in_block {
+            self.lines.push(line.to_string());
+            return None;
+        }
+        match line.strip_suffix('\\') {
+            Some(rest) => {
+                self.lines.push(rest.to_string());
+                None
+            }
+            None => {
+                self.lines.push(line.to_string());
+                self.take()
+            }
+        }
+    }
+
+    /// Whether part of a message has been typed.
+    pub fn is_pending(&self) -> bool {
+        self.in_block || !self.lines.is_empty()
+    }
+
+    /// The prompt for the next line.
+    pub fn prompt(&self) -> &'static str {
+        match self.is_pending() {
+            true => "… ",
+            false => "> ",
+        }
+    }
+
+    /// Whatever was typed before the input ended, e.g. an
+    /// unclosed block when stdin is closed.
+    pub fn finish(&mut self) -> Option<String> {
+        self.in_block = false;
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let text = std::mem::take(&mut self.lines).join("\n");
+        let text = text.trim_matches('\n').trim_end();
+        (!text.trim().is_empty()).then(|| text.to_string())
+    }
+}
+
+/// Whether `text` asks to leave the REPL.
+pub fn is_exit(text: &str) -> bool {
+    matches!(text.trim(), "/exit" | "/quit")
+}
+
+/// A tool call reported while the turn runs, e.g.
+/// "  ⚙ Using firecrawl (scrape)…".
+pub fn tool_line(format: Format, status: &str) -> String {
+    let status = Format::Plain.render(status);
+    match format {
+        Format::Ansi => format!("  \x1b[2m⚙ {status}\x1b[22m"),
+        _ => format!("  ⚙ {status}"),
+    }
+}
+
+/// What a turn used, shown under the reply, e.g.
+/// "2 tool calls · 1520 in / 84 out tokens · $0.0058 · 3.2s".
+pub fn usage_line(
+    format: Format,
+    tool_calls: usize,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost_usd: f64,
+    duration_ms: u64,
+) -> String {
+    let mut parts = Vec::new();
+    match tool_calls {
+        0 => {}
+        1 => parts.push("1 tool call".to_string()),
+        n => parts.push(format!("{n} tool calls")),
+    }
+    parts.push(format!("{input_tokens} in / {output_tokens} out tokens"));
+    // Zero for models without a known price.
+    if cost_usd > 0.0 {
+        parts.push(format!("${cost_usd:.4}"));
+    }
+    parts.push(format!("{:.1}s", duration_ms as f64 / 1000.0));
+    let line = parts.join(" · ");
+    match format {
+        Format::Ansi => format!("\x1b[2m{line}\x1b[22m"),
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(lines: &[&str]) -> Vec<String> {
+        let mut input = Input::new();
+        let mut out: Vec<String> = lines.iter().filter_map(|l| input.push(l)).collect();
+        out.extend(input.finish());
+        out
+    }
+
+    #[test]
+    fn lines_are_messages_unless_continued() {
+        assert_eq!(messages(&["hello", "", "  ", "bye\r\n"]), ["hello", "bye"]);
+        assert_eq!(
+            messages(&["first line\\", "second line"]),
+            ["first line\nsecond line"]
+        );
+        let mut input = Input::new();
+        assert_eq!(input.prompt(), "> ");
+        assert_eq!(input.push("more\\"), None);
+        assert!(input.is_pending());
+        assert_eq!(input.prompt(), "… ");
+    }
+
+    #[test]
+    fn blocks_keep_blank_lines() {
+        let q = BLOCK_DELIMITER;
+        let block = [q, "fn main() {", "", "    ok\\", "}", q, "next"];
+        assert_eq!(messages(&block), ["fn main() {\n\n    ok\\\n}", "next"]);
+        assert_eq!(messages(&[q, "unclosed"]), ["unclosed"]);
+        assert!(is_exit(" /quit "));
+        assert!(!is_exit("/exit now"));
+    }
+
+    #[test]
+    fn turns_are_summarised() {
+        assert_eq!(
+            usage_line(Format::Plain, 2, 1520, 84, 0.0058, 3240),
+            "2 tool calls · 1520 in / 84 out tokens · $0.0058 · 3.2s"
+        );
+        assert_eq!(
+            usage_line(Format::Ansi, 0, 10, 2, 0.0, 400),
+            "\x1b[2m10 in / 2 out tokens · 0.4s\x1b[22m"
+        );
+        assert_eq!(
+            tool_line(Format::Plain, "Using **firecrawl** (scrape)…"),
+            "  ⚙ Using firecrawl (scrape)…"
+        );
+    }
+}
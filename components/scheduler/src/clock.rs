@@ -0,0 +1,318 @@
+//! Local time: the UTC offset, calendar dates and the times
+//! `schedule-once` accepts.
+//!
+//! Times are seconds since the Unix epoch. "Local" times are
+//! shifted by the configured UTC offset, so calendar arithmetic on
+//! them gives the user's dates and hours.
+
+pub const MINUTE: i64 = 60;
+pub const HOUR: i64 = 60 * MINUTE;
+pub const DAY: i64 = 24 * HOUR;
+
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The year, month and day of a day since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// 0 for Sunday through 6 for Saturday.
+pub fn weekday(days: i64) -> u32 {
+    (days + 4).rem_euclid(7) as u32
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses a UTC offset in seconds: "+02:00", "-0530", "+2", or
+/// empty or "UTC" for none.
+pub fn parse_offset(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    if text.is_empty() || text.eq_ignore_ascii_case("utc") || text == "Z" {
+        return Ok(0);
+    }
+    let invalid = || format!("invalid UTC offset {text:?}, expected e.g. \"+02:00\"");
+    let (sign, rest) = match text.split_at(1) {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 14 || minutes >= 60 {
+        return Err(invalid());
+    }
+    Ok(sign * (hours * HOUR + minutes * MINUTE))
+}
+
+/// A local time as e.g. "Tue 2026-10-20 09:00".
+pub fn format_local(local: i64) -> String {
+    let days = local.div_euclid(DAY);
+    let secs = local.rem_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+    let name = &WEEKDAYS[weekday(days) as usize][..3];
+    let mut name = name.to_string();
+    name[..1].make_ascii_uppercase();
+    format!(
+        "{name} {year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / HOUR,
+        secs % HOUR / MINUTE
+    )
+}
+
+/// Parses when to run a task, relative to `now`, both local:
+/// "in 20 minutes", "in 1h30m", "tomorrow 09:00", "friday 6pm",
+/// "14:00" (the next one), "2026-10-20 09:00" or
+/// "2026-10-20T09:00". The time must be in the future.
+pub fn parse_at(at: &str, now: i64) -> Result<i64, String> {
+    let text = at.trim().to_ascii_lowercase();
+    let text = text.strip_prefix("at ").unwrap_or(&text).trim();
+    if let Some(duration) = text.strip_prefix("in ") {
+        return Ok(now + parse_duration(duration)?);
+    }
+    let text = text.replacen('t', " ", usize::from(is_iso_date_time(text)));
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let today = now.div_euclid(DAY);
+    let (day, time_words) = match words.first().copied() {
+        None => return Err("no time given".to_string()),
+        Some("today") => (Some(today), &words[1..]),
+        Some("tomorrow") => (Some(today + 1), &words[1..]),
+        Some(word) => match (parse_weekday(word), parse_date(word)) {
+            (Some(target), _) => {
+                let ahead = (i64::from(target) - i64::from(weekday(today))).rem_euclid(7);
+                (Some(today + ahead), &words[1..])
+            }
+            (None, Some(date)) => (Some(date), &words[1..]),
+            (None, None) => (None, &words[..]),
+        },
+    };
+    let time_words = match time_words.first() {
+        Some(&"at") => &time_words[1..],
+        _ => time_words,
+    };
+    let time_text = time_words.join("");
+    if time_text.is_empty() {
+        return Err(format!("no time of day in {at:?}, e.g. \"tomorrow 09:00\""));
+    }
+    let time = parse_time(&time_text).ok_or_else(|| format!("can't read the time in {at:?}"))?;
+    let mut local = day.unwrap_or(today) * DAY + time;
+    // A bare time, or a weekday whose time has passed today, is the
+    // next one.
+    let is_weekday = words.first().is_some_and(|w| parse_weekday(w).is_some());
+    if local <= now && day.is_none() {
+        local += DAY;
+    } else if local <= now && is_weekday {
+        local += 7 * DAY;
+    }
+    if local <= now {
+        return Err(format!("{} is in the past", format_local(local)));
+    }
+    Ok(local)
+}
+
+fn is_iso_date_time(text: &str) -> bool {
+    text.split_once('t')
+        .is_some_and(|(date, _)| parse_date(date).is_some())
+}
+
+/// "20 minutes", "2 hours and 5 minutes", "1h30m" or "3 days".
+fn parse_duration(text: &str) -> Result<i64, String> {
+    let invalid = || format!("can't read the duration {text:?}, e.g. \"in 2 hours\"");
+    let mut total = 0;
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        rest = rest
+            .trim_start_matches(|c: char| c.is_whitespace() || c == ',')
+            .trim_start_matches("and ")
+            .trim_start();
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let amount: i64 = match (digits, &rest[..digits]) {
+            (0, _) if rest.starts_with("an ") || rest.starts_with("a ") => 1,
+            (0, _) => return Err(invalid()),
+            (_, digits) => digits.parse().map_err(|_| invalid())?,
+        };
+        rest = match digits {
+            0 => rest.split_once(' ').map_or("", |(_, r)| r),
+            _ => &rest[digits..],
+        }
+        .trim_start();
+        let letters = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .len();
+        let unit = match &rest[..letters] {
+            "m" | "min" | "mins" | "minute" | "minutes" => MINUTE,
+            "h" | "hr" | "hrs" | "hour" | "hours" => HOUR,
+            "d" | "day" | "days" => DAY,
+            "w" | "week" | "weeks" => 7 * DAY,
+            _ => return Err(invalid()),
+        };
+        total += amount.checked_mul(unit).ok_or_else(invalid)?;
+        rest = rest[letters..].trim_start();
+    }
+    match total {
+        0 => Err(invalid()),
+        total => Ok(total),
+    }
+}
+
+fn parse_weekday(word: &str) -> Option<u32> {
+    let word = word.trim_end_matches(',');
+    if word.len() < 3 {
+        return None;
+    }
+    WEEKDAYS
+        .iter()
+        .position(|day| day.starts_with(word))
+        .map(|i| i as u32)
+}
+
+/// Days since 1970-01-01 for "2026-10-20".
+fn parse_date(word: &str) -> Option<i64> {
+    let mut parts = word.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    let valid = (1970..=9999).contains(&year)
+        && (1..=12).contains(&month)
+        && (1..=days_in_month(year, month)).contains(&day);
+    valid.then(|| days_from_civil(year, month, day))
+}
+
+/// Seconds into the day for "09:00", "9:30:15", "9am", "6.30pm",
+/// "noon" or "midnight".
+fn parse_time(text: &str) -> Option<i64> {
+    match text {
+        "noon" | "midday" => return Some(12 * HOUR),
+        "midnight" => return Some(0),
+        _ => {}
+    }
+    let (text, meridiem) = match (text.strip_suffix("am"), text.strip_suffix("pm")) {
+        (Some(t), _) => (t, Some(0)),
+        (_, Some(t)) => (t, Some(12)),
+        _ => (text, None),
+    };
+    let mut parts = text.split([':', '.']);
+    let hour: i64 = parts.next()?.parse().ok()?;
+    let minute: i64 = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+    // Seconds are accepted, but tasks run on the minute.
+    let _seconds: i64 = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+    if parts.next().is_some() || minute >= 60 {
+        return None;
+    }
+    let hour = match meridiem {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None if hour < 24 => hour,
+        None => return None,
+    };
+    Some(hour * HOUR + minute * MINUTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mon 2026-10-19 08:55 local.
+    const NOW: i64 = 1_792_400_100;
+
+    fn at(text: &str) -> String {
+        parse_at(text, NOW).map(format_local).unwrap_or_else(|e| e)
+    }
+
+    #[test]
+    fn dates_convert_both_ways() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 2, 29), 11_016);
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(weekday(0), 4);
+        assert_eq!(format_local(NOW), "Mon 2026-10-19 08:55");
+    }
+
+    #[test]
+    fn offsets_are_parsed() {
+        assert_eq!(parse_offset(""), Ok(0));
+        assert_eq!(parse_offset("UTC"), Ok(0));
+        assert_eq!(parse_offset("+02:00"), Ok(2 * HOUR));
+        assert_eq!(parse_offset("-0530"), Ok(-5 * HOUR - 30 * MINUTE));
+        assert_eq!(parse_offset("+1"), Ok(HOUR));
+        assert!(parse_offset("Europe/Lisbon").is_err());
+        assert!(parse_offset("+25:00").is_err());
+    }
+
+    #[test]
+    fn relative_times_are_added() {
+        assert_eq!(at("in 20 minutes"), "Mon 2026-10-19 09:15");
+        assert_eq!(at("in 1h30m"), "Mon 2026-10-19 10:25");
+        assert_eq!(at("in 2 hours and 5 minutes"), "Mon 2026-10-19 11:00");
+        assert_eq!(at("in an hour"), "Mon 2026-10-19 09:55");
+        assert_eq!(at("In 3 days"), "Thu 2026-10-22 08:55");
+        assert!(at("in a while").starts_with("can't read the duration"));
+        assert!(at("in 0 minutes").starts_with("can't read the duration"));
+    }
+
+    #[test]
+    fn days_and_times_are_the_next_ones() {
+        assert_eq!(at("tomorrow 09:00"), "Tue 2026-10-20 09:00");
+        assert_eq!(at("tomorrow at 9am"), "Tue 2026-10-20 09:00");
+        assert_eq!(at("today 6.30pm"), "Mon 2026-10-19 18:30");
+        assert_eq!(at("14:00"), "Mon 2026-10-19 14:00");
+        assert_eq!(at("8:00"), "Tue 2026-10-20 08:00");
+        assert_eq!(at("friday noon"), "Fri 2026-10-23 12:00");
+        assert_eq!(at("mon 08:00"), "Mon 2026-10-26 08:00");
+        assert_eq!(at("monday 10:00"), "Mon 2026-10-19 10:00");
+        assert_eq!(at("2026-10-20 09:00"), "Tue 2026-10-20 09:00");
+        assert_eq!(at("2026-10-20T09:00:00"), "Tue 2026-10-20 09:00");
+    }
+
+    #[test]
+    fn invalid_and_past_times_are_refused() {
+        assert_eq!(at("today 08:00"), "Mon 2026-10-19 08:00 is in the past");
+        assert_eq!(
+            at("tomorrow"),
+            "no time of day in \"tomorrow\", e.g. \"tomorrow 09:00\""
+        );
+        assert!(at("2026-02-30 09:00").starts_with("can't read the time"));
+        assert!(at("25:00").starts_with("can't read the time"));
+        assert!(at("13pm").starts_with("can't read the time"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Five-field cron expressions: "minute hour day-of-month month
+//! day-of-week", evaluated in local time.
+
+use crate::clock::{civil_from_days, weekday, DAY, HOUR, MINUTE};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead `next_after` looks, e.g. for "0 0 30 2 *".
+const MAX_DAYS_AHEAD: i64 = 5 * 366;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    days_of_week: Vec<bool>,
+    /// Restricted fields; when both day fields are, either may match.
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "invalid cron {expression:?}: expected 5 fields, \
+                 \"minute hour day-of-month month day-of-week\""
+            ));
+        };
+        let invalid = |field: &str, e: String| format!("invalid cron {expression:?}: {field} {e}");
+        let mut days_of_week =
+            parse_field(day_of_week, 0, 7, &DAYS).map_err(|e| invalid("day-of-week", e))?;
+        // Both 0 and 7 are Sunday.
+        if days_of_week[7] {
+            days_of_week[0] = true;
+        }
+        days_of_week.truncate(7);
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59, &[]).map_err(|e| invalid("minute", e))?,
+            hours: parse_field(hour, 0, 23, &[]).map_err(|e| invalid("hour", e))?,
+            days_of_month: parse_field(day_of_month, 1, 31, &[])
+                .map_err(|e| invalid("day-of-month", e))?,
+            months: parse_field(month, 1, 12, &MONTHS).map_err(|e| invalid("month", e))?,
+            days_of_week,
+            day_of_month_any: day_of_month == "*",
+            day_of_week_any: day_of_week == "*",
+        })
+    }
+
+    /// The first local time, on the minute, after `local`.
+    pub fn next_after(&self, local: i64) -> Option<i64> {
+        let start = (local.div_euclid(MINUTE) + 1) * MINUTE;
+        let first_day = start.div_euclid(DAY);
+        for day in first_day..first_day + MAX_DAYS_AHEAD {
+            if !self.matches_day(day) {
+                continue;
+            }
+            for hour in 0..24 {
+                if !self.hours[hour as usize] {
+                    continue;
+                }
+                for minute in 0..60 {
+                    let time = day * DAY + hour * HOUR + minute * MINUTE;
+                    if self.minutes[minute as usize] && time >= start {
+                        return Some(time);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        if !self.months[month as usize] {
+            return false;
+        }
+        let by_month = self.days_of_month[day_of_month as usize];
+        let by_week = self.days_of_week[weekday(day) as usize];
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (false, false) => by_month || by_week,
+            _ => by_month && by_week,
+        }
+    }
+}
+
+/// Which values in `0..=max` a field allows; values below `min`
+/// are never set. `names` are the values from `min` up.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("has an invalid step {step:?}")),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                parse_value(start, min, max, names)?,
+                parse_value(end, min, max, names)?,
+            ),
+            // "5/15" runs from 5 to the end.
+            None if step > 1 => (parse_value(range, min, max, names)?, max),
+            None => {
+                let value = parse_value(range, min, max, names)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(format!("has a backwards range {range:?}"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+fn parse_value(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(i) = names.iter().position(|name| *name == lower) {
+        return Ok(min + i as u32);
+    }
+    match text.parse::<u32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("has {text:?}, expected {min}-{max}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{days_from_civil, format_local};
+
+    /// Mon 2026-10-19 08:55.
+    fn now() -> i64 {
+        days_from_civil(2026, 10, 19) * DAY + 8 * HOUR + 55 * MINUTE
+    }
+
+    fn next(expression: &str) -> String {
+        let cron = Cron::parse(expression).unwrap();
+        cron.next_after(now()).map(format_local).unwrap_or_default()
+    }
+
+    #[test]
+    fn next_runs_are_found() {
+        assert_eq!(next("* * * * *"), "Mon 2026-10-19 08:56");
+        assert_eq!(next("0 9 * * 1-5"), "Mon 2026-10-19 09:00");
+        assert_eq!(next("30 8 * * mon-fri"), "Tue 2026-10-20 08:30");
+        assert_eq!(next("*/15 * * * *"), "Mon 2026-10-19 09:00");
+        assert_eq!(next("55 8 * * *"), "Tue 2026-10-20 08:55");
+        assert_eq!(next("0 18 * * sat,sun"), "Sat 2026-10-24 18:00");
+        assert_eq!(next("0 9 * * 7"), "Sun 2026-10-25 09:00");
+        assert_eq!(next("0 0 1 jan *"), "Fri 2027-01-01 00:00");
+        assert_eq!(next("0 12 29 2 *"), "Tue 2028-02-29 12:00");
+        assert_eq!(next("@hourly"), "Mon 2026-10-19 09:00");
+        assert_eq!(next("@weekly"), "Sun 2026-10-25 00:00");
+        assert_eq!(next("@monthly"), "Sun 2026-11-01 00:00");
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 1st of the month or any Friday.
+        assert_eq!(next("0 9 1 * fri"), "Fri 2026-10-23 09:00");
+        assert_eq!(next("0 9 20 * fri"), "Tue 2026-10-20 09:00");
+    }
+
+    #[test]
+    fn invalid_expressions_are_refused() {
+        assert!(Cron::parse("0 9 * *")
+            .unwrap_err()
+            .contains("expected 5 fields"));
+        assert!(Cron::parse("60 * * * *").unwrap_err().contains("minute"));
+        assert!(Cron::parse("0 9 * * 1-8")
+            .unwrap_err()
+            .contains("day-of-week"));
+        assert!(Cron::parse("0 9 * foo *").unwrap_err().contains("month"));
+        assert!(Cron::parse("*/0 * * * *").unwrap_err().contains("step"));
+        assert!(Cron::parse("0 17-9 * * *")
+            .unwrap_err()
+            .contains("backwards"));
+        assert_eq!(Cron::parse("0 0 30 2 *").unwrap().next_after(now()), None);
+    }
+}
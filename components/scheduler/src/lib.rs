@@ -0,0 +1,326 @@
+#[cfg(not(test))]
+use crate::bindings::asterai::host::api;
+#[cfg(not(test))]
+use crate::bindings::asterbot::types::types::{AgentError, ErrorKind};
+#[cfg(not(test))]
+use crate::bindings::exports::asterai::host_cron::incoming_handler::Guest as CronGuest;
+#[cfg(not(test))]
+use crate::bindings::exports::asterbot::types::scheduler::Guest;
+#[cfg(not(test))]
+use crate::cron::Cron;
+#[cfg(not(test))]
+use crate::task::{Store, Task};
+#[cfg(not(test))]
+use asterbot_common::origin::Origin;
+use serde_json::Value;
+
+mod clock;
+mod cron;
+mod task;
+
+#[cfg(not(test))]
+const DEFAULT_MAX_TASKS: usize = 50;
+
+#[cfg(not(test))]
+#[allow(warnings)]
+mod bindings {
+    wit_bindgen::generate!({
+        path: "wit/package.wasm",
+        world: "component",
+        generate_all,
+    });
+}
+
+#[cfg(not(test))]
+struct Component;
+
+#[cfg(not(test))]
+impl Guest for Component {
+    fn schedule_once(session: String, at: String, task: String) -> Result<String, AgentError> {
+        let offset = resolve_offset()?;
+        let now = now();
+        let local = clock::parse_at(&at, now + offset).map_err(tool_error)?;
+        schedule(&session, &task, None, local - offset, now, offset)
+    }
+
+    fn schedule_repeating(
+        session: String,
+        cron: String,
+        task: String,
+    ) -> Result<String, AgentError> {
+        let offset = resolve_offset()?;
+        let now = now();
+        let next = Cron::parse(&cron)
+            .map_err(tool_error)?
+            .next_after(now + offset)
+            .ok_or_else(|| tool_error(format!("cron {cron:?} never runs")))?;
+        schedule(&session, &task, Some(&cron), next - offset, now, offset)
+    }
+
+    fn list(session: String) -> Result<String, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let offset = resolve_offset()?;
+        let store = Store::load(&host_dir).map_err(storage_error)?;
+        Ok(store.list_text(&session, now(), offset))
+    }
+
+    fn cancel(session: String, id: String) -> Result<String, AgentError> {
+        let host_dir = resolve_host_dir()?;
+        let mut store = Store::load(&host_dir).map_err(storage_error)?;
+        let task = store
+            .cancel(&session, &id)
+            .ok_or_else(|| tool_error(format!("no scheduled task {id:?}")))?;
+        store.save(&host_dir).map_err(storage_error)?;
+        Ok(format!("Cancelled task {}: {}", task.id, task.task))
+    }
+}
+
+/// Called by asterai every minute.
+#[cfg(not(test))]
+impl CronGuest for Component {
+    fn on_tick() {
+        if let Err(e) = run_due() {
+            eprintln!("scheduler: {}", e.message);
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn schedule(
+    session: &str,
+    task: &str,
+    cron: Option<&str>,
+    next_run: i64,
+    now: i64,
+    offset: i64,
+) -> Result<String, AgentError> {
+    if task.trim().is_empty() {
+        return Err(tool_error("task is empty".to_string()));
+    }
+    let host_dir = resolve_host_dir()?;
+    let mut store = Store::load(&host_dir).map_err(storage_error)?;
+    let max_tasks = resolve_max_tasks();
+    if store.tasks.len() >= max_tasks {
+        return Err(AgentError {
+            kind: ErrorKind::Limit,
+            message: format!("{max_tasks} tasks are already scheduled, cancel one first"),
+        });
+    }
+    let origin = Origin::load(&host_dir, session);
+    let text = store
+        .add(task, cron, next_run, now, session, origin)
+        .scheduled_text(offset);
+    store.save(&host_dir).map_err(storage_error)?;
+    Ok(text)
+}
+
+/// Runs the due tasks, one turn each, and delivers the replies.
+/// The store is saved first, so a task runs once even if its turn
+/// outlasts the next tick.
+#[cfg(not(test))]
+fn run_due() -> Result<(), AgentError> {
+    let host_dir = resolve_host_dir()?;
+    let offset = resolve_offset()?;
+    let now = now();
+    let mut store = Store::load(&host_dir).map_err(storage_error)?;
+    let due = store.take_due(now, offset);
+    if due.is_empty() {
+        return Ok(());
+    }
+    store.save(&host_dir).map_err(storage_error)?;
+    for task in due {
+        run(&task, offset);
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn run(task: &Task, offset: i64) {
+    // The turn is in the conversation the task was scheduled
+    // from, so its reply follows on from it, and tasks it
+    // schedules go to the same chat.
+    let request = serde_json::json!({
+        "session": task.session,
+        "parts": [{ "text": task.prompt(offset) }],
+        "progress": null,
+    });
+    let reply = call(
+        "asterbot:agent",
+        "agent/converse",
+        &serde_json::json!([request]),
+    )
+    .and_then(|output| decode_reply(&output));
+    let text = match reply {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("scheduler: task {} failed: {e}", task.id);
+            return;
+        }
+    };
+    let Some(origin) = &task.origin else {
+        return;
+    };
+    if text.trim().is_empty() {
+        return;
+    }
+    let delivered = call(
+        &origin.component,
+        "delivery/deliver",
+        &serde_json::json!([origin.address, text]),
+    )
+    .and_then(|output| decode_unit(&output));
+    if let Err(e) = delivered {
+        eprintln!(
+            "scheduler: failed to deliver task {} to {} {}: {e}",
+            task.id, origin.component, origin.address
+        );
+    }
+}
+
+#[cfg(not(test))]
+fn call(component: &str, function: &str, args: &Value) -> Result<String, String> {
+    api::call_component_function(component, function, &args.to_string())
+        .map_err(|e| format!("{component}/{function} failed: {}", e.message))
+}
+
+/// The text to deliver from the JSON encoding of the agent's
+/// `result<converse-response, agent-error>`: the reply, and links
+/// to the files it attached at a URL. Files in the host dir are
+/// named, since delivery is text only.
+fn decode_reply(output: &str) -> Result<String, String> {
+    let value: Value = serde_json::from_str(output).unwrap_or(Value::Null);
+    let str_field = |v: &Value, key: &str| {
+        v.get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    if let Some(err) = value.get("err") {
+        return Err(format!(
+            "{}: {}",
+            str_field(err, "kind"),
+            str_field(err, "message")
+        ));
+    }
+    let Some(ok) = value.get("ok") else {
+        return Err(format!("unexpected agent output: {output}"));
+    };
+    let mut lines = vec![str_field(ok, "reply").trim().to_string()];
+    let attachments = ok.get("attachments").and_then(Value::as_array);
+    for media in attachments.into_iter().flatten() {
+        let name = str_field(media, "name");
+        let data = media.get("data").unwrap_or(&Value::Null);
+        match data.get("url").and_then(Value::as_str) {
+            Some(url) if name.is_empty() => lines.push(url.to_string()),
+            Some(url) => lines.push(format!("[{name}]({url})")),
+            None => lines.push(format!("File: {name}")),
+        }
+    }
+    lines.retain(|line| !line.is_empty());
+    Ok(lines.join("\n\n"))
+}
+
+/// The JSON encoding of `result<_, agent-error>`.
+#[cfg(not(test))]
+fn decode_unit(output: &str) -> Result<(), String> {
+    let value: Value = serde_json::from_str(output).unwrap_or(Value::Null);
+    let Some(err) = value.get("err") else {
+        return Ok(());
+    };
+    let field = |key: &str| err.get(key).and_then(Value::as_str).unwrap_or_default();
+    Err(format!("{}: {}", field("kind"), field("message")))
+}
+
+#[cfg(not(test))]
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(not(test))]
+fn resolve_offset() -> Result<i64, AgentError> {
+    let offset = std::env::var("SCHEDULER_UTC_OFFSET").unwrap_or_default();
+    clock::parse_offset(&offset).map_err(|e| AgentError {
+        kind: ErrorKind::Config,
+        message: format!("SCHEDULER_UTC_OFFSET: {e}"),
+    })
+}
+
+#[cfg(not(test))]
+fn resolve_max_tasks() -> usize {
+    std::env::var("SCHEDULER_MAX_TASKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_TASKS)
+}
+
+#[cfg(not(test))]
+fn resolve_host_dir() -> Result<String, AgentError> {
+    if let Ok(v) = std::env::var("ASTERBOT_HOST_DIR") {
+        if !v.is_empty() {
+            return Ok(v);
+        }
+    }
+    if let Ok(dirs) = std::env::var("ASTERAI_ALLOWED_DIRS") {
+        if let Some(first) = dirs.split(':').next() {
+            if !first.is_empty() {
+                return Ok(first.to_string());
+            }
+        }
+    }
+    Err(AgentError {
+        kind: ErrorKind::Config,
+        message: "no state directory available — pass --allow-dir to grant filesystem access"
+            .to_string(),
+    })
+}
+
+#[cfg(not(test))]
+fn tool_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Tool,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+fn storage_error(message: String) -> AgentError {
+    AgentError {
+        kind: ErrorKind::Storage,
+        message,
+    }
+}
+
+#[cfg(not(test))]
+bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_decoded_with_links() {
+        let output = r#"{"ok":{
+            "reply":"Time to call the dentist!",
+            "attachments":[
+                {"mime-type":"image/png","name":"map.png","data":{"url":"https://example.org/map.png"}},
+                {"mime-type":"text/plain","name":"notes.txt","data":{"bytes":[104,105]}}],
+            "buttons":[],"tool-invocations":[],
+            "usage":{"rounds":1,"llm-calls":1,"input-tokens":10,"output-tokens":5,"cost-usd":0.0},
+            "model":"asterbot/stub","stop-reason":"completed"}}"#;
+        assert_eq!(
+            decode_reply(output).unwrap(),
+            "Time to call the dentist!\n\n[map.png](https://example.org/map.png)\n\nFile: notes.txt"
+        );
+    }
+
+    #[test]
+    fn failed_turns_are_errors() {
+        assert_eq!(
+            decode_reply(r#"{"err":{"kind":"limit","message":"daily budget exceeded"}}"#),
+            Err("limit: daily budget exceeded".to_string())
+        );
+        assert!(decode_reply("not json").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Scheduled tasks, persisted in `scheduler/tasks.json` in the
+//! host dir.
+
+use crate::clock::format_local;
+use crate::cron::Cron;
+use asterbot_common::origin::Origin;
+use serde::{Deserialize, Serialize};
+
+pub const TASKS_PATH: &str = "scheduler/tasks.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub id: String,
+    /// What the agent is to do, in its own words.
+    pub task: String,
+    /// Set for repeating tasks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// Seconds since the Unix epoch, UTC.
+    pub next_run: i64,
+    pub created: i64,
+    /// The conversation it was scheduled from, which it runs in
+    /// and which alone can list and cancel it. Empty for the
+    /// default conversation.
+    #[serde(default)]
+    pub session: String,
+    /// Where the reply goes: the session's origin when the task
+    /// was scheduled. Without one, it is only in the conversation
+    /// history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Store {
+    pub next_id: u64,
+    pub tasks: Vec<Task>,
+}
+
+impl Store {
+    /// An empty store if there is no file yet.
+    pub fn load(host_dir: &str) -> Result<Self, String> {
+        let path = format!("{host_dir}/{TASKS_PATH}");
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("invalid {path}: {e}")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Store::default()),
+            Err(e) => Err(format!("failed to read {path}: {e}")),
+        }
+    }
+
+    pub fn save(&self, host_dir: &str) -> Result<(), String> {
+        let path = format!("{host_dir}/{TASKS_PATH}");
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let json = serde_json::to_string_pretty(self).unwrap_or_default();
+        std::fs::write(&path, json).map_err(|e| format!("failed to write {path}: {e}"))
+    }
+
+    pub fn add(
+        &mut self,
+        task: &str,
+        cron: Option<&str>,
+        next_run: i64,
+        now: i64,
+        session: &str,
+        origin: Option<Origin>,
+    ) -> &Task {
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id: self.next_id.to_string(),
+            task: task.trim().to_string(),
+            cron: cron.map(|c| c.trim().to_string()),
+            next_run,
+            created: now,
+            session: session.to_string(),
+            origin,
+        });
+        &self.tasks[self.tasks.len() - 1]
+    }
+
+    /// Cancels the task with `id` if it was scheduled from
+    /// `session`.
+    pub fn cancel(&mut self, session: &str, id: &str) -> Option<Task> {
+        let id = id.trim().trim_start_matches('#');
+        let index = self
+            .tasks
+            .iter()
+            .position(|t| t.id == id && t.session == session)?;
+        Some(self.tasks.remove(index))
+    }
+
+    /// Removes the tasks due at `now` and returns them. Repeating
+    /// tasks are put back with their next run.
+    pub fn take_due(&mut self, now: i64, offset: i64) -> Vec<Task> {
+        let (due, rest): (Vec<Task>, Vec<Task>) =
+            self.tasks.drain(..).partition(|t| t.next_run <= now);
+        self.tasks = rest;
+        for task in &due {
+            let next = task
+                .cron
+                .as_deref()
+                .and_then(|cron| Cron::parse(cron).ok())
+                .and_then(|cron| cron.next_after(now + offset));
+            if let Some(next) = next {
+                self.tasks.push(Task {
+                    next_run: next - offset,
+                    ..task.clone()
+                });
+            }
+        }
+        self.tasks.sort_by_key(|t| t.next_run);
+        due
+    }
+
+    /// The tasks scheduled from `session`.
+    pub fn list_text(&self, session: &str, now: i64, offset: i64) -> String {
+        let mut tasks: Vec<&Task> = self.tasks.iter().filter(|t| t.session == session).collect();
+        tasks.sort_by_key(|t| t.next_run);
+        let mut lines = vec![format!("It is now {}.", format_local(now + offset))];
+        match tasks.is_empty() {
+            true => lines.push("No tasks are scheduled.".to_string()),
+            false => lines.push(format!("{} scheduled:", tasks.len())),
+        }
+        for task in tasks {
+            let repeats = match &task.cron {
+                Some(cron) => format!(", repeats {cron}"),
+                None => String::new(),
+            };
+            lines.push(format!(
+                "- {}: {} ({}{repeats})",
+                task.id,
+                task.task,
+                format_local(task.next_run + offset)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Task {
+    /// The tool's reply once the task is scheduled.
+    pub fn scheduled_text(&self, offset: i64) -> String {
+        let repeats = match &self.cron {
+            Some(cron) => format!(", then repeats ({cron})"),
+            None => String::new(),
+        };
+        let delivery = match &self.origin {
+            Some(_) => "The reply will be sent to this chat.",
+            None => {
+                "No chat was recorded for this conversation, so the reply \
+                 will only be in the conversation history."
+            }
+        };
+        format!(
+            "Scheduled task {} for {}{repeats}. {delivery}",
+            self.id,
+            format_local(self.next_run + offset)
+        )
+    }
+
+    /// The synthetic message the agent gets when the task is due.
+    pub fn prompt(&self, offset: i64) -> String {
+        let kind = match &self.cron {
+            Some(cron) => format!("repeating task {} ({cron})", self.id),
+            None => format!("task {}", self.id),
+        };
+        format!(
+            "[Scheduled {kind}, set {}. The user didn't just write: \
+             this runs on its own, and your reply is sent to them as a message.]\n{}",
+            format_local(self.created + offset),
+            self.task
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{days_from_civil, DAY, HOUR, MINUTE};
+
+    /// Mon 2026-10-19 08:55 UTC.
+    fn now() -> i64 {
+        days_from_civil(2026, 10, 19) * DAY + 8 * HOUR + 55 * MINUTE
+    }
+
+    const SESSION: &str = "telegram:dm:42";
+
+    fn origin() -> Origin {
+        Origin::new("asterbot:telegram-gateway", "42")
+    }
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("scheduler-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn tasks_are_saved_listed_and_cancelled() {
+        let dir = temp_dir("store");
+        let mut store = Store::load(&dir).unwrap();
+        let task = store.add(
+            " Call the dentist ",
+            None,
+            now() + HOUR,
+            now(),
+            SESSION,
+            Some(origin()),
+        );
+        assert_eq!(
+            task.scheduled_text(2 * HOUR),
+            "Scheduled task 1 for Mon 2026-10-19 11:55. The reply will be sent to this chat."
+        );
+        let task = store.add(
+            "Stand-up notes",
+            Some("0 9 * * 1-5"),
+            now() + 5 * MINUTE,
+            now(),
+            SESSION,
+            None,
+        );
+        assert!(task.scheduled_text(0).starts_with(
+            "Scheduled task 2 for Mon 2026-10-19 09:00, then repeats (0 9 * * 1-5). No chat"
+        ));
+        store.save(&dir).unwrap();
+
+        let mut store = Store::load(&dir).unwrap();
+        assert_eq!(store.tasks[0].origin, Some(origin()));
+        assert_eq!(
+            store.list_text(SESSION, now(), 0),
+            "It is now Mon 2026-10-19 08:55.\n2 scheduled:\n\
+             - 2: Stand-up notes (Mon 2026-10-19 09:00, repeats 0 9 * * 1-5)\n\
+             - 1: Call the dentist (Mon 2026-10-19 09:55)"
+        );
+        assert_eq!(
+            store.cancel(SESSION, "#1").unwrap().task,
+            "Call the dentist"
+        );
+        assert_eq!(store.cancel(SESSION, "1"), None);
+        store.cancel(SESSION, "2");
+        assert!(store
+            .list_text(SESSION, now(), 0)
+            .ends_with("No tasks are scheduled."));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn due_tasks_are_taken_and_repeating_ones_rescheduled() {
+        let mut store = Store::default();
+        store.add("once", None, now(), now() - DAY, "", None);
+        store.add(
+            "weekdays",
+            Some("55 8 * * 1-5"),
+            now(),
+            now() - DAY,
+            "",
+            None,
+        );
+        store.add("later", None, now() + MINUTE, now(), "", None);
+        let due = store.take_due(now(), 0);
+        assert_eq!(
+            due.iter().map(|t| t.task.as_str()).collect::<Vec<_>>(),
+            ["once", "weekdays"]
+        );
+        assert_eq!(store.tasks.len(), 2);
+        assert_eq!(store.tasks[0].task, "later");
+        assert_eq!(store.tasks[1].next_run, now() + DAY);
+        assert!(store.take_due(now(), 0).is_empty());
+        // In UTC+02:00, 08:55 local is 06:55 UTC.
+        let mut store = Store::default();
+        store.add("weekdays", Some("55 8 * * 1-5"), now(), now(), "", None);
+        store.take_due(now(), 2 * HOUR);
+        assert_eq!(store.tasks[0].next_run, now() + DAY - 2 * HOUR);
+    }
+
+    #[test]
+    fn prompts_say_the_task_runs_on_its_own() {
+        let mut store = Store::default();
+        let task = store.add(
+            "Remind the user to call the dentist",
+            None,
+            now(),
+            now() - DAY,
+            "",
+            None,
+        );
+        assert_eq!(
+            task.prompt(0),
+            "[Scheduled task 1, set Sun 2026-10-18 08:55. The user didn't just write: \
+             this runs on its own, and your reply is sent to them as a message.]\n\
+             Remind the user to call the dentist"
+        );
+    }
+
+    #[test]
+    fn other_sessions_tasks_are_neither_listed_nor_cancelled() {
+        let mut store = Store::default();
+        store.add("mine", None, now() + HOUR, now(), SESSION, Some(origin()));
+        store.add("theirs", None, now() + HOUR, now(), "telegram:dm:7", None);
+        store.add("default", None, now() + HOUR, now(), "", None);
+        let listed = store.list_text(SESSION, now(), 0);
+        assert!(listed.contains("1 scheduled:\n- 1: mine"), "{listed}");
+        assert!(!listed.contains("theirs") && !listed.contains("default"));
+        assert_eq!(store.cancel(SESSION, "2"), None);
+        assert_eq!(store.cancel(SESSION, "3"), None);
+        assert_eq!(store.cancel("", "3").unwrap().task, "default");
+        assert_eq!(store.tasks.len(), 2);
+    }
+}
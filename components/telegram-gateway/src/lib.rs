@@ -1,8 +1,20 @@
+use crate::bindings::asterai::host::api as host_api;
 use crate::bindings::asterai::telegram::api;
 use crate::bindings::asterai::telegram::types::Message;
 use crate::bindings::asterbot::types::agent;
+use crate::bindings::asterbot::types::types::{
+    AgentError, ConverseRequest, ConverseResponse, ErrorKind, InputPart, MediaData,
+};
 use crate::bindings::exports::asterai::telegram::incoming_handler::Guest;
+use crate::bindings::exports::asterbot::types::delivery::Guest as DeliveryGuest;
+use gateway_common::{
+    Access, Commands, Contact, EventLog, Format, GroupPolicy, Host, IdKind, Origin, RateLimit,
+    SessionKey, Splitter, Verdict, reply, resolve_host_dir,
+};
 use std::sync::LazyLock;
+use std::time::Instant;
+
+const COMPONENT: &str = "asterbot:telegram-gateway";
 
 #[allow(warnings)]
 mod bindings {
@@ -13,71 +25,200 @@ mod bindings {
     });
 }
 
-enum AccessMode {
-    AllowList(Vec<i64>),
-    Public,
-    Disabled,
-}
+static ACCESS: LazyLock<Access> = LazyLock::new(|| {
+    Access::from_env("TELEGRAM_ALLOWED_USER_IDS", "TELEGRAM_PUBLIC", IdKind::User)
+});
+
+static COMMANDS: LazyLock<Commands> =
+    LazyLock::new(|| Commands::from_env("TELEGRAM", IdKind::User, ACCESS.allowlist()));
+
+static LIMITS: LazyLock<RateLimit> =
+    LazyLock::new(|| RateLimit::from_env("TELEGRAM", IdKind::User));
 
-static ACCESS_MODE: LazyLock<AccessMode> = LazyLock::new(|| init_access_mode());
+static SPLITTER: LazyLock<Splitter> =
+    LazyLock::new(|| Splitter::from_env("TELEGRAM", Splitter::TELEGRAM));
+
+static FORMAT: LazyLock<Format> = LazyLock::new(|| Format::from_env("TELEGRAM", Format::Html));
+
+static GROUPS: LazyLock<GroupPolicy> = LazyLock::new(|| {
+    let me = api::get_self();
+    GroupPolicy::from_env("TELEGRAM", &[&me.username], &[&me.first_name])
+});
 
 struct Component;
 
+struct WasmHost;
+
+impl Host for WasmHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String> {
+        host_api::call_component_function(component, function, args_json).map_err(|e| e.message)
+    }
+}
+
 impl Guest for Component {
     fn on_message(message: Message) {
-        println!(
-            "telegram-gateway: message from {} (id={}): {}",
-            message.sender.username, message.sender.id, message.content
-        );
         let self_user = api::get_self();
         if message.sender.id == self_user.id {
             return;
         }
-        if !validate_access(&message) {
+        // Private chats share the user's id; group ids differ.
+        let is_group = message.chat_id != message.sender.id;
+        let input = match is_group {
+            false => message.content.clone(),
+            // Commands too, so "/help" meant for another bot in
+            // the group is left alone.
+            true => match GROUPS.input(&message.content) {
+                Some(input) => input,
+                None => return,
+            },
+        };
+        let events = EventLog::from_env(COMPONENT);
+        let sender = message.sender.id.to_string();
+        if !ACCESS.allows(&sender) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({ "sender": message.sender.id }),
+            );
+            return;
+        }
+        let is_admin = COMMANDS.is_admin(&sender);
+        if !is_admin && LIMITS.is_blocked(&sender) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({
+                    "sender": message.sender.id,
+                    "reason": "blocked",
+                }),
+            );
+            return;
+        }
+        let session = match is_group {
+            false => SessionKey::direct("telegram", &sender),
+            true => SessionKey::group("telegram", &message.chat_id.to_string()),
+        };
+        let send = |text: &str| api::send_message(&FORMAT.render(text), message.chat_id);
+        let started = Instant::now();
+        if let Some(handled) = COMMANDS.handle(&WasmHost, &input, &sender, &session) {
+            for part in SPLITTER.split(&handled.reply) {
+                send(&part);
+            }
+            events.emit(
+                "command.handled",
+                serde_json::json!({
+                    "sender": message.sender.id,
+                    "session": session.as_str(),
+                    "command": handled.command,
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+            );
+            return;
+        }
+        let verdict = match is_admin {
+            true => Verdict::Allowed,
+            false => LIMITS.check(&sender, &input),
+        };
+        if verdict != Verdict::Allowed {
+            if let Some(reply) = verdict.reply() {
+                send(&reply);
+            }
+            events.emit(
+                "message.limited",
+                serde_json::json!({
+                    "sender": message.sender.id,
+                    "session": session.as_str(),
+                    "reason": verdict.reason(),
+                }),
+            );
             return;
         }
-        let response = agent::converse(&message.content);
-        api::send_message(&response, message.chat_id);
+        let chat = message.chat_id.to_string();
+        let host_dir = resolve_host_dir();
+        Origin::new(COMPONENT, &chat).record(&host_dir, session.as_str());
+        if !is_group {
+            let name = &message.sender.first_name;
+            Contact::new("telegram", &sender, name, COMPONENT, &chat).learn(&host_dir);
+        }
+        let response = converse(&input, &session);
+        let text = match &response {
+            Ok(response) => reply_text(response),
+            Err(e) => format!("error: {}", e.message),
+        };
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            send(part);
+        }
+        events.emit(
+            "message.handled",
+            serde_json::json!({
+                "turnId": response.as_ref().ok().map(|r| &r.turn_id),
+                "sender": message.sender.id,
+                "session": session.as_str(),
+                "durationMs": started.elapsed().as_millis() as u64,
+                "parts": parts.len(),
+                "replyAttachments": response.as_ref().map_or(0, |r| r.attachments.len()),
+                "input": events.content(&input),
+                "reply": events.content(&text),
+            }),
+        );
     }
 }
 
-fn validate_access(message: &Message) -> bool {
-    match &*ACCESS_MODE {
-        AccessMode::AllowList(ids) => ids.contains(&message.sender.id),
-        AccessMode::Public => true,
-        AccessMode::Disabled => {
-            eprintln!(
-                "telegram-gateway: disabled, set \
-                     TELEGRAM_ALLOWED_USER_IDS or TELEGRAM_PUBLIC=true"
-            );
-            false
+impl DeliveryGuest for Component {
+    /// Sends a message the agent wrote on its own, e.g. a scheduled
+    /// reminder. `address` is the chat id.
+    fn deliver(address: String, text: String) -> Result<(), AgentError> {
+        let chat_id: i64 = address.parse().map_err(|_| AgentError {
+            kind: ErrorKind::Config,
+            message: format!("invalid chat id {address:?}"),
+        })?;
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            api::send_message(&FORMAT.render(part), chat_id);
         }
+        EventLog::from_env(COMPONENT).emit(
+            "message.delivered",
+            serde_json::json!({ "chat": chat_id, "parts": parts.len() }),
+        );
+        Ok(())
     }
 }
 
-fn init_access_mode() -> AccessMode {
-    let allowed_ids: Vec<i64> = std::env::var("TELEGRAM_ALLOWED_USER_IDS")
-        .unwrap_or_default()
-        .split(',')
-        .filter_map(|s| s.trim().parse().ok())
+/// Sends the message to the agent in `session`.
+fn converse(text: &str, session: &SessionKey) -> Result<ConverseResponse, AgentError> {
+    agent::converse(&ConverseRequest {
+        session: Some(session.as_str().to_string()),
+        parts: vec![InputPart::Text(text.to_string())],
+        progress: None,
+    })
+}
+
+/// The reply's text, followed by what Telegram's API can't
+/// send: files, quick replies and the location.
+fn reply_text(response: &ConverseResponse) -> String {
+    let mut lines: Vec<String> = response
+        .attachments
+        .iter()
+        .map(|media| match &media.data {
+            MediaData::Url(url) => reply::link_text(&media.name, url),
+            MediaData::Bytes(_) => format!("({} can't be sent here)", media.name),
+        })
         .collect();
-    let is_public = std::env::var("TELEGRAM_PUBLIC")
-        .map(|v| v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false);
-    if !allowed_ids.is_empty() && is_public {
-        eprintln!(
-            "TELEGRAM_ALLOWED_USER_IDS and TELEGRAM_PUBLIC=true are both set; \
-             using TELEGRAM_ALLOWED_USER_IDS"
-        );
-        return AccessMode::AllowList(allowed_ids);
-    }
-    if !allowed_ids.is_empty() {
-        return AccessMode::AllowList(allowed_ids);
+    if !response.buttons.is_empty() {
+        lines.push(reply::options_text(&response.buttons));
     }
-    if is_public {
-        return AccessMode::Public;
+    if let Some(location) = &response.location {
+        lines.push(reply::location_text(
+            location.latitude,
+            location.longitude,
+            &location.label,
+        ));
     }
-    AccessMode::Disabled
+    reply::compose(&response.reply, &lines)
 }
 
 bindings::export!(Component with_types_in bindings);
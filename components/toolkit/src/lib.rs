@@ -1,7 +1,11 @@
 use crate::bindings::asterai::host::api;
-use crate::bindings::asterbot::types::types::ToolParam;
+use crate::bindings::asterbot::types::types::{AgentError, ErrorKind, ToolParam};
 use crate::bindings::exports::asterbot::types::toolkit::{Guest, ToolInfo};
+use asterbot_common::events::EventLog;
 use serde_json::Value;
+use std::time::Instant;
+
+const COMPONENT: &str = "asterbot:toolkit";
 
 #[allow(warnings)]
 mod bindings {
@@ -14,7 +18,22 @@ mod bindings {
 
 struct Component;
 
-const SKIP_INTERFACES: &[&str] = &["agent", "core", "toolkit", "types", "api"];
+/// Interfaces that aren't tools, including the handlers asterai
+/// calls, e.g. `asterai:host-cron`'s on asterbot:scheduler.
+const SKIP_INTERFACES: &[&str] = &[
+    "agent",
+    "core",
+    "toolkit",
+    "types",
+    "api",
+    "incoming-handler",
+];
+
+/// Tool parameters with this name are filled with the session of
+/// the turn calling the tool, and aren't shown to the model, so a
+/// tool like asterbot:scheduler acts for the conversation it is
+/// called from.
+const SESSION_PARAM: &str = "session";
 
 impl Guest for Component {
     fn list_tools() -> Vec<ToolInfo> {
@@ -35,6 +54,7 @@ impl Guest for Component {
                 let params = f
                     .inputs
                     .iter()
+                    .filter(|p| p.name != SESSION_PARAM)
                     .map(|p| ToolParam {
                         name: p.name.clone(),
                         type_name: p.type_name.clone(),
@@ -58,21 +78,32 @@ impl Guest for Component {
     }
 
     fn call_tool(component_name: String, function_name: String, args_json: String) -> String {
-        let allowed = tool_component_names();
-        if !allowed.iter().any(|n| n == &component_name) {
-            return format!(
-                "error: component '{}' is not in ASTERBOT_TOOLS",
-                component_name,
-            );
-        }
-        let args = convert_args_to_array(&component_name, &function_name, &args_json);
-        match api::call_component_function(&component_name, &function_name, &args) {
-            Ok(result) => result,
-            Err(e) => format!(
-                "error: {}/{} failed ({:?}): {}",
-                component_name, function_name, e.kind, e.message,
-            ),
-        }
+        Self::try_call_tool(component_name, function_name, args_json)
+            .unwrap_or_else(|e| format!("error: {}", e.message))
+    }
+
+    fn try_call_tool(
+        component_name: String,
+        function_name: String,
+        args_json: String,
+    ) -> Result<String, AgentError> {
+        dispatch_tool(None, "", &component_name, &function_name, &args_json)
+    }
+
+    fn try_call_tool_in_turn(
+        turn_id: String,
+        session: String,
+        component_name: String,
+        function_name: String,
+        args_json: String,
+    ) -> Result<String, AgentError> {
+        dispatch_tool(
+            Some(&turn_id),
+            &session,
+            &component_name,
+            &function_name,
+            &args_json,
+        )
     }
 
     fn format_tools_for_prompt() -> String {
@@ -101,19 +132,98 @@ impl Guest for Component {
     }
 }
 
-fn convert_args_to_array(component: &str, function: &str, args_json: &str) -> String {
+/// Calls a tool, logging the dispatch under `turn_id` when it is
+/// for a turn of core's. `session` is the turn's, and empty for
+/// the default conversation.
+fn dispatch_tool(
+    turn_id: Option<&str>,
+    session: &str,
+    component: &str,
+    function: &str,
+    args_json: &str,
+) -> Result<String, AgentError> {
+    let events = EventLog::from_env(COMPONENT);
+    let allowed = tool_component_names();
+    if !allowed.iter().any(|n| n == component) {
+        let message = format!("component '{}' is not in ASTERBOT_TOOLS", component);
+        events.emit(
+            "error",
+            serde_json::json!({ "turnId": turn_id, "kind": "permission", "message": message }),
+        );
+        return Err(AgentError {
+            kind: ErrorKind::Permission,
+            message,
+        });
+    }
+    let args = convert_args_to_array(component, function, args_json, session);
+    let started = Instant::now();
+    let result = api::call_component_function(component, function, &args);
+    events.emit(
+        "tool.dispatched",
+        serde_json::json!({
+            "turnId": turn_id,
+            "target": component,
+            "function": function,
+            "durationMs": started.elapsed().as_millis() as u64,
+            "isError": result.is_err(),
+            "args": events.content(&args),
+        }),
+    );
+    result.map_err(|e| AgentError {
+        kind: ErrorKind::Tool,
+        message: format!(
+            "{}/{} failed ({:?}): {}",
+            component, function, e.kind, e.message,
+        ),
+    })
+}
+
+/// The tool's arguments as a JSON array in parameter order, from
+/// the model's object of named arguments (or array), with the
+/// session parameter, if the function has one, set to `session`.
+fn convert_args_to_array(
+    component: &str,
+    function: &str,
+    args_json: &str,
+    session: &str,
+) -> String {
     let Ok(value) = serde_json::from_str::<Value>(args_json) else {
         return args_json.to_string();
     };
-    if value.is_array() {
-        return args_json.to_string();
-    }
-    let Some(obj) = value.as_object() else {
+    if !value.is_array() && !value.is_object() {
         return format!("[{args_json}]");
-    };
-    let Some(info) = api::get_component(component) else {
+    }
+    let Some(inputs) = input_names(component, function) else {
         return args_json.to_string();
     };
+    let session_index = inputs.iter().position(|name| name == SESSION_PARAM);
+    let mut arr: Vec<Value> = match value {
+        Value::Array(values) => values,
+        Value::Object(obj) => inputs
+            .iter()
+            .map(|name| {
+                obj.get(name)
+                    .or_else(|| obj.get(&name.replace('-', "_")))
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect(),
+        _ => return format!("[{args_json}]"),
+    };
+    if let Some(index) = session_index {
+        let session = Value::String(session.to_string());
+        // Positional arguments from the model leave it out.
+        match arr.len() < inputs.len() {
+            true => arr.insert(index.min(arr.len()), session),
+            false => arr[index] = session,
+        }
+    }
+    serde_json::to_string(&arr).unwrap_or_else(|_| args_json.to_string())
+}
+
+/// The names of `function`'s parameters, in order.
+fn input_names(component: &str, function: &str) -> Option<Vec<String>> {
+    let info = api::get_component(component)?;
     let (iface_name, func_name) = match function.split_once('/') {
         Some((i, f)) => (Some(i), f),
         None => (None, function),
@@ -121,20 +231,8 @@ fn convert_args_to_array(component: &str, function: &str, args_json: &str) -> St
     let func = info
         .functions
         .iter()
-        .find(|f| f.name == func_name && f.interface_name.as_deref() == iface_name);
-    let Some(func) = func else {
-        return args_json.to_string();
-    };
-    let arr: Vec<&Value> = func
-        .inputs
-        .iter()
-        .map(|p| {
-            obj.get(&p.name)
-                .or_else(|| obj.get(&p.name.replace('-', "_")))
-                .unwrap_or(&Value::Null)
-        })
-        .collect();
-    serde_json::to_string(&arr).unwrap_or_else(|_| args_json.to_string())
+        .find(|f| f.name == func_name && f.interface_name.as_deref() == iface_name)?;
+    Some(func.inputs.iter().map(|p| p.name.clone()).collect())
 }
 
 fn tool_component_names() -> Vec<String> {
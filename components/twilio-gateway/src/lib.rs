@@ -1,8 +1,22 @@
+use crate::bindings::asterai::host::api as host_api;
 use crate::bindings::asterai::twilio::api;
-use crate::bindings::asterai::twilio::types::Message;
+use crate::bindings::asterai::twilio::types::{Message, Webhook};
 use crate::bindings::asterbot::types::agent;
+use crate::bindings::asterbot::types::types::{
+    AgentError, ConverseRequest, ConverseResponse, ErrorKind, InputPart, MediaData,
+};
 use crate::bindings::exports::asterai::twilio::incoming_handler::Guest;
+use crate::bindings::exports::asterbot::types::delivery::Guest as DeliveryGuest;
+use gateway_common::twilio::Verifier;
+use gateway_common::{
+    Access, Blocklist, Commands, Contact, EventLog, Format, Host, IdKind, Keyword, Origin,
+    RateLimit, SessionKey, SmsSplitter, Splitter, Verdict, normalise_phone, reply,
+    resolve_host_dir,
+};
 use std::sync::LazyLock;
+use std::time::Instant;
+
+const COMPONENT: &str = "asterbot:twilio-gateway";
 
 #[allow(warnings)]
 mod bindings {
@@ -13,78 +27,295 @@ mod bindings {
     });
 }
 
-enum AccessMode {
-    AllowList(Vec<String>),
-    Public,
-    Disabled,
-}
+static ACCESS: LazyLock<Access> =
+    LazyLock::new(|| Access::from_env("TWILIO_ALLOWED_PHONES", "TWILIO_PUBLIC", IdKind::Phone));
+
+static COMMANDS: LazyLock<Commands> =
+    LazyLock::new(|| Commands::from_env("TWILIO", IdKind::Phone, ACCESS.allowlist()));
+
+static LIMITS: LazyLock<RateLimit> = LazyLock::new(|| RateLimit::from_env("TWILIO", IdKind::Phone));
+
+static SPLITTER: LazyLock<Splitter> = LazyLock::new(|| Splitter::from_env("TWILIO", Splitter::SMS));
+
+static FORMAT: LazyLock<Format> = LazyLock::new(|| Format::from_env("TWILIO", Format::Plain));
+
+static VERIFIER: LazyLock<Option<Verifier>> = LazyLock::new(|| Verifier::from_env("TWILIO"));
 
-static ACCESS_MODE: LazyLock<AccessMode> = LazyLock::new(|| init_access_mode());
+static SMS: LazyLock<SmsSplitter> = LazyLock::new(|| SmsSplitter::from_env("TWILIO"));
+
+/// Numbers that sent STOP. Twilio refuses to message them, so
+/// their messages aren't passed to the agent.
+static OPTED_OUT: LazyLock<Blocklist> =
+    LazyLock::new(|| Blocklist::named("twilio-opted-out", IdKind::Phone, &resolve_host_dir()));
+
+/// Sent in answer to HELP; Twilio answers it itself by default.
+static HELP_TEXT: LazyLock<Option<String>> = LazyLock::new(|| {
+    std::env::var("TWILIO_HELP_TEXT")
+        .ok()
+        .filter(|text| !text.trim().is_empty())
+});
 
 struct Component;
 
+struct WasmHost;
+
+impl Host for WasmHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String> {
+        host_api::call_component_function(component, function, args_json).map_err(|e| e.message)
+    }
+}
+
 impl Guest for Component {
     fn on_message(message: Message) {
-        println!(
-            "twilio-gateway: message from {}: {}",
-            message.sender.phone, message.content
-        );
+        let events = EventLog::from_env(COMPONENT);
+        if !is_signed(&message) {
+            eprintln!("ignoring a message without a valid Twilio signature");
+            events.emit(
+                "access.denied",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "reason": "signature",
+                }),
+            );
+            return;
+        }
         let self_user = api::get_self();
         if message.sender.phone == self_user.phone {
             return;
         }
-        if !validate_access(&message) {
+        if !ACCESS.allows(&message.sender.phone) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({ "sender": message.sender.phone }),
+            );
+            return;
+        }
+        // Twilio also relays WhatsApp, which has no segments or
+        // carrier keywords.
+        let is_sms = !message.sender.phone.starts_with("whatsapp:");
+        let keyword = Keyword::of(&message.content).filter(|_| is_sms);
+        // Twilio confirms opt-outs and opt-ins itself.
+        let opt_out = match keyword {
+            Some(Keyword::Stop) => Some(OPTED_OUT.block(&message.sender.phone)),
+            Some(Keyword::Start) => Some(OPTED_OUT.unblock(&message.sender.phone)),
+            _ => None,
+        };
+        if let Some(result) = opt_out {
+            if let Err(e) = result {
+                eprintln!("failed to record opt-out: {e}");
+            }
+            events.emit(
+                "keyword.handled",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "keyword": message.content.trim().to_ascii_uppercase(),
+                }),
+            );
+            return;
+        }
+        if OPTED_OUT.contains(&message.sender.phone) {
+            return;
+        }
+        let is_admin = COMMANDS.is_admin(&message.sender.phone);
+        if !is_admin && LIMITS.is_blocked(&message.sender.phone) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "reason": "blocked",
+                }),
+            );
+            return;
+        }
+        let phone = normalise_phone(&message.sender.phone);
+        let session =
+            SessionKey::direct("twilio", phone.as_deref().unwrap_or(&message.sender.phone));
+        let format = match is_sms {
+            true => *FORMAT,
+            false => Format::WhatsApp,
+        };
+        let send = |text: &str| send_reply(&message.sender.phone, text, format);
+        let started = Instant::now();
+        if keyword == Some(Keyword::Help) {
+            if let Some(help) = HELP_TEXT.as_deref() {
+                send(help);
+            }
+            events.emit(
+                "keyword.handled",
+                serde_json::json!({ "sender": message.sender.phone, "keyword": "HELP" }),
+            );
+            return;
+        }
+        if let Some(handled) =
+            COMMANDS.handle(&WasmHost, &message.content, &message.sender.phone, &session)
+        {
+            send(&handled.reply);
+            events.emit(
+                "command.handled",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "session": session.as_str(),
+                    "command": handled.command,
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+            );
+            return;
+        }
+        if keyword == Some(Keyword::More)
+            && let Some(next) = SMS.next(&message.sender.phone)
+        {
+            api::send_message(&next, &message.sender.phone);
+            events.emit(
+                "keyword.handled",
+                serde_json::json!({ "sender": message.sender.phone, "keyword": "MORE" }),
+            );
             return;
         }
-        let response = agent::converse(&message.content);
-        api::send_message(&response, &message.sender.phone);
+        let verdict = match is_admin {
+            true => Verdict::Allowed,
+            false => LIMITS.check(&message.sender.phone, &message.content),
+        };
+        if verdict != Verdict::Allowed {
+            if let Some(reply) = verdict.reply() {
+                send(&reply);
+            }
+            events.emit(
+                "message.limited",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "session": session.as_str(),
+                    "reason": verdict.reason(),
+                }),
+            );
+            return;
+        }
+        let host_dir = resolve_host_dir();
+        let sender = &message.sender;
+        Origin::new(COMPONENT, &sender.phone).record(&host_dir, session.as_str());
+        let id = phone.as_deref().unwrap_or(&sender.phone);
+        Contact::new("twilio", id, &sender.name, COMPONENT, &sender.phone).learn(&host_dir);
+        let response = converse(&message.content, &session);
+        let text = match &response {
+            Ok(response) => reply_text(response),
+            Err(e) => format!("error: {}", e.message),
+        };
+        let parts = send(&text);
+        events.emit(
+            "message.handled",
+            serde_json::json!({
+                "turnId": response.as_ref().ok().map(|r| &r.turn_id),
+                "sender": message.sender.phone,
+                "session": session.as_str(),
+                "durationMs": started.elapsed().as_millis() as u64,
+                "parts": parts,
+                "replyAttachments": response.as_ref().map_or(0, |r| r.attachments.len()),
+                "input": events.content(&message.content),
+                "reply": events.content(&text),
+            }),
+        );
     }
 }
 
-fn validate_access(message: &Message) -> bool {
-    match &*ACCESS_MODE {
-        AccessMode::AllowList(phones) => phones.contains(&message.sender.phone),
-        AccessMode::Public => true,
-        AccessMode::Disabled => {
-            eprintln!(
-                "twilio-gateway: disabled, set \
-                 TWILIO_ALLOWED_PHONES or TWILIO_PUBLIC=true"
-            );
-            false
+impl DeliveryGuest for Component {
+    /// Sends a message the agent wrote on its own, e.g. a scheduled
+    /// reminder. `address` is the phone number, "whatsapp:"
+    /// prefixed for WhatsApp.
+    fn deliver(address: String, text: String) -> Result<(), AgentError> {
+        if address.is_empty() {
+            return Err(AgentError {
+                kind: ErrorKind::Config,
+                message: "no phone number".to_string(),
+            });
+        }
+        if OPTED_OUT.contains(&address) {
+            return Err(AgentError {
+                kind: ErrorKind::Permission,
+                message: format!("{address} has opted out"),
+            });
         }
+        let format = match address.starts_with("whatsapp:") {
+            true => Format::WhatsApp,
+            false => *FORMAT,
+        };
+        let parts = send_reply(&address, &text, format);
+        EventLog::from_env(COMPONENT).emit(
+            "message.delivered",
+            serde_json::json!({ "to": address, "parts": parts }),
+        );
+        Ok(())
     }
 }
 
-fn init_access_mode() -> AccessMode {
-    let allowed_phones: Vec<String> = std::env::var("TWILIO_ALLOWED_PHONES")
-        .unwrap_or_default()
-        .split(',')
-        .map(|s| {
-            let s = s.trim();
-            match s.starts_with('+') {
-                true => s.to_owned(),
-                false => format!("+{s}"),
-            }
+/// Whether the message came from Twilio, if signatures are checked.
+fn is_signed(message: &Message) -> bool {
+    let Some(verifier) = VERIFIER.as_ref() else {
+        return true;
+    };
+    match &message.webhook {
+        Some(Webhook {
+            url,
+            signature: Some(signature),
+            params,
+        }) => verifier.verify(url, params, signature),
+        _ => false,
+    }
+}
+
+/// Sends `text` to `to`, with any linked files as MMS, returning
+/// how many messages were sent. SMS replies are one message of a
+/// few segments, continued on MORE; WhatsApp replies are split
+/// into numbered messages.
+fn send_reply(to: &str, text: &str, format: Format) -> usize {
+    let parts = match to.starts_with("whatsapp:") {
+        true => SPLITTER
+            .split(text)
+            .iter()
+            .map(|part| format.render(part))
+            .collect(),
+        false => vec![SMS.first(to, &format.render(text))],
+    };
+    for part in parts.iter().filter(|p| !p.is_empty()) {
+        api::send_message(part, to);
+    }
+    parts.len()
+}
+
+/// Sends the message to the agent in `session`.
+fn converse(text: &str, session: &SessionKey) -> Result<ConverseResponse, AgentError> {
+    agent::converse(&ConverseRequest {
+        session: Some(session.as_str().to_string()),
+        parts: vec![InputPart::Text(text.to_string())],
+        progress: None,
+    })
+}
+
+/// The reply's text, followed by what SMS can't carry: files that
+/// aren't links, quick replies and the location.
+fn reply_text(response: &ConverseResponse) -> String {
+    let mut lines: Vec<String> = response
+        .attachments
+        .iter()
+        .map(|media| match &media.data {
+            MediaData::Url(url) => reply::link_text(&media.name, url),
+            MediaData::Bytes(_) => format!("({} can't be sent by text message)", media.name),
         })
-        .filter(|s| s.len() > 1)
         .collect();
-    let is_public = std::env::var("TWILIO_PUBLIC")
-        .map(|v| v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false);
-    if !allowed_phones.is_empty() && is_public {
-        eprintln!(
-            "TWILIO_ALLOWED_PHONES and TWILIO_PUBLIC=true are both set; \
-             using TWILIO_ALLOWED_PHONES"
-        );
-        return AccessMode::AllowList(allowed_phones);
-    }
-    if !allowed_phones.is_empty() {
-        return AccessMode::AllowList(allowed_phones);
+    if !response.buttons.is_empty() {
+        lines.push(reply::options_text(&response.buttons));
     }
-    if is_public {
-        return AccessMode::Public;
+    if let Some(location) = &response.location {
+        lines.push(reply::location_text(
+            location.latitude,
+            location.longitude,
+            &location.label,
+        ));
     }
-    AccessMode::Disabled
+    reply::compose(&response.reply, &lines)
 }
 
 bindings::export!(Component with_types_in bindings);
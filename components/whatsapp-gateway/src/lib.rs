@@ -1,8 +1,20 @@
+use crate::bindings::asterai::host::api as host_api;
 use crate::bindings::asterai::whatsapp::api;
 use crate::bindings::asterai::whatsapp::types::Message;
 use crate::bindings::asterbot::types::agent;
+use crate::bindings::asterbot::types::types::{
+    AgentError, ConverseRequest, ConverseResponse, ErrorKind, InputPart, MediaData,
+};
 use crate::bindings::exports::asterai::whatsapp::incoming_handler::Guest;
+use crate::bindings::exports::asterbot::types::delivery::Guest as DeliveryGuest;
+use gateway_common::{
+    Access, Commands, Contact, EventLog, Format, GroupPolicy, Host, IdKind, Origin, RateLimit,
+    SessionKey, Splitter, Verdict, normalise_phone, reply, resolve_host_dir,
+};
 use std::sync::LazyLock;
+use std::time::Instant;
+
+const COMPONENT: &str = "asterbot:whatsapp-gateway";
 
 #[allow(warnings)]
 mod bindings {
@@ -13,72 +25,203 @@ mod bindings {
     });
 }
 
-enum AccessMode {
-    AllowList(Vec<String>),
-    Public,
-    Disabled,
-}
+static ACCESS: LazyLock<Access> =
+    LazyLock::new(|| Access::from_env("WHATSAPP_ALLOWED_PHONES", "WHATSAPP_PUBLIC", IdKind::Phone));
+
+static COMMANDS: LazyLock<Commands> =
+    LazyLock::new(|| Commands::from_env("WHATSAPP", IdKind::Phone, ACCESS.allowlist()));
+
+static LIMITS: LazyLock<RateLimit> =
+    LazyLock::new(|| RateLimit::from_env("WHATSAPP", IdKind::Phone));
 
-static ACCESS_MODE: LazyLock<AccessMode> = LazyLock::new(|| init_access_mode());
+static SPLITTER: LazyLock<Splitter> =
+    LazyLock::new(|| Splitter::from_env("WHATSAPP", Splitter::WHATSAPP));
+
+static FORMAT: LazyLock<Format> = LazyLock::new(|| Format::from_env("WHATSAPP", Format::WhatsApp));
+
+static GROUPS: LazyLock<GroupPolicy> = LazyLock::new(|| {
+    let me = api::get_self();
+    // Mentions appear in the text as "@" and the number's digits.
+    let number = normalise_phone(&me.phone).unwrap_or(me.phone);
+    GroupPolicy::from_env("WHATSAPP", &[number.trim_start_matches('+')], &[&me.name])
+});
 
 struct Component;
 
+struct WasmHost;
+
+impl Host for WasmHost {
+    fn call_component_function(
+        &self,
+        component: &str,
+        function: &str,
+        args_json: &str,
+    ) -> Result<String, String> {
+        host_api::call_component_function(component, function, args_json).map_err(|e| e.message)
+    }
+}
+
 impl Guest for Component {
     fn on_message(message: Message) {
-        println!(
-            "whatsapp-gateway: message from {} ({}): {}",
-            message.sender.name, message.sender.phone, message.content
-        );
         let self_user = api::get_self();
         if message.sender.phone == self_user.phone {
             return;
         }
-        if !validate_access(&message) {
+        let input = match &message.group_id {
+            None => message.content.clone(),
+            Some(_) => match GROUPS.input(&message.content) {
+                Some(input) => input,
+                None => return,
+            },
+        };
+        let events = EventLog::from_env(COMPONENT);
+        if !ACCESS.allows(&message.sender.phone) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({ "sender": message.sender.phone }),
+            );
+            return;
+        }
+        let is_admin = COMMANDS.is_admin(&message.sender.phone);
+        if !is_admin && LIMITS.is_blocked(&message.sender.phone) {
+            events.emit(
+                "access.denied",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "reason": "blocked",
+                }),
+            );
             return;
         }
-        let response = agent::converse(&message.content);
-        api::send_message(&response, &message.sender.phone);
+        let phone = normalise_phone(&message.sender.phone);
+        let session = match &message.group_id {
+            Some(group) => SessionKey::group("whatsapp", group),
+            None => SessionKey::direct(
+                "whatsapp",
+                phone.as_deref().unwrap_or(&message.sender.phone),
+            ),
+        };
+        let to = message.group_id.as_deref().unwrap_or(&message.sender.phone);
+        let send = |text: &str| api::send_message(&FORMAT.render(text), to);
+        let started = Instant::now();
+        if let Some(handled) = COMMANDS.handle(&WasmHost, &input, &message.sender.phone, &session) {
+            for part in SPLITTER.split(&handled.reply) {
+                send(&part);
+            }
+            events.emit(
+                "command.handled",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "session": session.as_str(),
+                    "command": handled.command,
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+            );
+            return;
+        }
+        let verdict = match is_admin {
+            true => Verdict::Allowed,
+            false => LIMITS.check(&message.sender.phone, &input),
+        };
+        if verdict != Verdict::Allowed {
+            if let Some(reply) = verdict.reply() {
+                send(&reply);
+            }
+            events.emit(
+                "message.limited",
+                serde_json::json!({
+                    "sender": message.sender.phone,
+                    "session": session.as_str(),
+                    "reason": verdict.reason(),
+                }),
+            );
+            return;
+        }
+        let host_dir = resolve_host_dir();
+        Origin::new(COMPONENT, to).record(&host_dir, session.as_str());
+        if message.group_id.is_none() {
+            let sender = &message.sender;
+            let id = phone.as_deref().unwrap_or(&sender.phone);
+            Contact::new("whatsapp", id, &sender.name, COMPONENT, &sender.phone).learn(&host_dir);
+        }
+        let response = converse(&input, &session);
+        let text = match &response {
+            Ok(response) => reply_text(response),
+            Err(e) => format!("error: {}", e.message),
+        };
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            send(part);
+        }
+        events.emit(
+            "message.handled",
+            serde_json::json!({
+                "turnId": response.as_ref().ok().map(|r| &r.turn_id),
+                "sender": message.sender.phone,
+                "session": session.as_str(),
+                "durationMs": started.elapsed().as_millis() as u64,
+                "parts": parts.len(),
+                "replyAttachments": response.as_ref().map_or(0, |r| r.attachments.len()),
+                "input": events.content(&input),
+                "reply": events.content(&text),
+            }),
+        );
     }
 }
 
-fn validate_access(message: &Message) -> bool {
-    match &*ACCESS_MODE {
-        AccessMode::AllowList(phones) => phones.contains(&message.sender.phone),
-        AccessMode::Public => true,
-        AccessMode::Disabled => {
-            eprintln!(
-                "whatsapp-gateway: disabled, set \
-                 WHATSAPP_ALLOWED_PHONES or WHATSAPP_PUBLIC=true"
-            );
-            false
+impl DeliveryGuest for Component {
+    /// Sends a message the agent wrote on its own, e.g. a scheduled
+    /// reminder. `address` is the group id or phone number.
+    fn deliver(address: String, text: String) -> Result<(), AgentError> {
+        if address.is_empty() {
+            return Err(AgentError {
+                kind: ErrorKind::Config,
+                message: "no group id or phone number".to_string(),
+            });
+        }
+        let parts = SPLITTER.split(&text);
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            api::send_message(&FORMAT.render(part), &address);
         }
+        EventLog::from_env(COMPONENT).emit(
+            "message.delivered",
+            serde_json::json!({ "to": address, "parts": parts.len() }),
+        );
+        Ok(())
     }
 }
 
-fn init_access_mode() -> AccessMode {
-    let allowed_phones: Vec<String> = std::env::var("WHATSAPP_ALLOWED_PHONES")
-        .unwrap_or_default()
-        .split(',')
-        .map(|s| s.trim().trim_start_matches('+').to_owned())
-        .filter(|s| !s.is_empty())
+/// Sends the message to the agent in `session`.
+fn converse(text: &str, session: &SessionKey) -> Result<ConverseResponse, AgentError> {
+    agent::converse(&ConverseRequest {
+        session: Some(session.as_str().to_string()),
+        parts: vec![InputPart::Text(text.to_string())],
+        progress: None,
+    })
+}
+
+/// The reply's text, followed by what WhatsApp's API can't
+/// send: files, quick replies and the location.
+fn reply_text(response: &ConverseResponse) -> String {
+    let mut lines: Vec<String> = response
+        .attachments
+        .iter()
+        .map(|media| match &media.data {
+            MediaData::Url(url) => reply::link_text(&media.name, url),
+            MediaData::Bytes(_) => format!("({} can't be sent here)", media.name),
+        })
         .collect();
-    let is_public = std::env::var("WHATSAPP_PUBLIC")
-        .map(|v| v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false);
-    if !allowed_phones.is_empty() && is_public {
-        eprintln!(
-            "WHATSAPP_ALLOWED_PHONES and WHATSAPP_PUBLIC=true are both set; \
-             using WHATSAPP_ALLOWED_PHONES"
-        );
-        return AccessMode::AllowList(allowed_phones);
-    }
-    if !allowed_phones.is_empty() {
-        return AccessMode::AllowList(allowed_phones);
+    if !response.buttons.is_empty() {
+        lines.push(reply::options_text(&response.buttons));
     }
-    if is_public {
-        return AccessMode::Public;
+    if let Some(location) = &response.location {
+        lines.push(reply::location_text(
+            location.latitude,
+            location.longitude,
+            &location.label,
+        ));
     }
-    AccessMode::Disabled
+    reply::compose(&response.reply, &lines)
 }
 
 bindings::export!(Component with_types_in bindings);